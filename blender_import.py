@@ -14,17 +14,18 @@ XML_PATH = ""
 TEX_PATH = ""
 DMI_PATH = ""
 EXCLUDED = []
+IMPORT_GLTF = False  # Assemble the level in the library and import it as a single glTF file.
+GLTF_PATH = ""  # Where the level .glb is written when IMPORT_GLTF is set.
 
 # Constants
-VERSION = "0.7.1"
+VERSION = "0.8.0"
 bl_info = {
     "name": "Journey Level Importer",
     "blender": (2, 80, 0),
     "category": "Import-Export",
     "description": "Import DecorationMeshInstances.lua from Journey",
 }
-xml_cache = {}
-tex_cache = {}
+asset_index = None
 errors = []
 lib = None
 
@@ -39,6 +40,23 @@ class ParsedModelData(ctypes.Structure):
         ("uvs_len_ptr", ctypes.POINTER(ctypes.c_size_t)),
         ("faces_len_ptr", ctypes.POINTER(ctypes.c_size_t)),
         ("translation_ptr", ctypes.POINTER(ctypes.c_float)),
+        ("transforms_ptr", ctypes.POINTER(ctypes.c_float)),
+        ("normals_ptr", ctypes.POINTER(ctypes.c_float)),
+        ("normals_len_ptr", ctypes.POINTER(ctypes.c_size_t)),
+        ("bounds_ptr", ctypes.POINTER(ctypes.c_float)),
+    ]
+
+
+class FfiOptions(ctypes.Structure):
+    _fields_ = [
+        ("up_axis", ctypes.c_uint),
+        ("left_handed", ctypes.c_bool),
+        ("scale", ctypes.c_float),
+        ("bake_transforms", ctypes.c_bool),
+        ("merge", ctypes.c_bool),
+        ("normals", ctypes.c_uint),
+        ("flat_normals", ctypes.c_bool),
+        ("crease_angle", ctypes.c_float),
     ]
 
 
@@ -89,35 +107,40 @@ def load_lib():
     lib.ffi_version.restype = ctypes.c_char_p
     lib.ffi_parse.restype = ctypes.POINTER(ParsedModelData)
     lib.ffi_parse.argtypes = [ctypes.c_char_p]
+    lib.ffi_parse_with.restype = ctypes.POINTER(ParsedModelData)
+    lib.ffi_parse_with.argtypes = [ctypes.c_char_p, ctypes.POINTER(FfiOptions)]
     lib.ffi_free.argtypes = [ctypes.POINTER(ParsedModelData)]
+    lib.ffi_asset_index_new.restype = ctypes.c_void_p
+    lib.ffi_asset_index_new.argtypes = [ctypes.c_char_p, ctypes.c_char_p]
+    lib.ffi_asset_index_resolve_mesh.restype = ctypes.c_void_p
+    lib.ffi_asset_index_resolve_mesh.argtypes = [ctypes.c_void_p, ctypes.c_char_p]
+    lib.ffi_asset_index_resolve_texture.restype = ctypes.c_void_p
+    lib.ffi_asset_index_resolve_texture.argtypes = [ctypes.c_void_p, ctypes.c_char_p]
+    lib.ffi_asset_index_free.argtypes = [ctypes.c_void_p]
+    lib.ffi_string_free.argtypes = [ctypes.c_void_p]
+    lib.ffi_export_level.restype = ctypes.c_bool
+    lib.ffi_export_level.argtypes = [ctypes.c_char_p] * 5
 
     version = lib.ffi_version().decode("utf-8")
     if version != VERSION:
         raise Exception(f"Version mismatch! Expected a binary with version {VERSION}, got {version}. Please update your binaries / this script.")
 
 
-def create_cache(path, ext, cache):
-    i = 0
-    for root, _, files in os.walk(path):
-        for file in files:
-            if file.endswith(ext):
-                filename_without_ext = os.path.splitext(file)[0]
-                full_path = os.path.join(root, file)
-                cache[filename_without_ext] = full_path
-                i = i+1
-    print(f"Cached {i} {ext} files.")
+def create_index():
+    global asset_index
+    asset_index = lib.ffi_asset_index_new(XML_PATH.encode("utf-8"), TEX_PATH.encode("utf-8"))
 
 
-def find_from_cache(name, cache):
-    for filename, full_path in cache.items():
-        if name == filename:
-            return full_path
-
-
-def find_tex_from_tex_name(tex_name: str):
-    if tex_name is None:
+def resolve(resolver, name):
+    if name is None:
+        return None
+    result = resolver(asset_index, name.encode("utf-8"))
+    if not result:
         return None
-    return find_from_cache(tex_name.replace("P_", "")*2, tex_cache)
+    try:
+        return ctypes.string_at(result).decode("utf-8")
+    finally:
+        lib.ffi_string_free(result)
 
 
 def traverse_lua_table(lua_table):
@@ -160,8 +183,8 @@ def traverse_lua_table(lua_table):
             if not tex:
                 print("Failed to parse texture")
 
-            xml_name = find_from_cache(mesh_name, xml_cache)
-            tex = find_tex_from_tex_name(tex)
+            xml_name = resolve(lib.ffi_asset_index_resolve_mesh, mesh_name)
+            tex = resolve(lib.ffi_asset_index_resolve_texture, tex)
             if xml_name is not None:
                 print(f"Spawning {xml_name} for {mesh_name} with {tex}")
                 spawn_models(xml_name, mesh_name, tex, matrix)
@@ -173,7 +196,8 @@ def traverse_lua_table(lua_table):
 
 
 def spawn_models(xml_name, mesh_name, tex, transformation_matrix):
-    result = lib.ffi_parse(xml_name.encode("utf-8"))
+    options = FfiOptions(up_axis=0, left_handed=False, scale=1.0, bake_transforms=False, merge=True, normals=0, flat_normals=False, crease_angle=30.0)
+    result = lib.ffi_parse_with(xml_name.encode("utf-8"), ctypes.byref(options))
 
     if not result or not result.contents or not result.contents.object_count:
         errors.append(f"Failed to parse {xml_name}")
@@ -182,36 +206,31 @@ def spawn_models(xml_name, mesh_name, tex, transformation_matrix):
 
     result = result.contents
     try:
-        objs = []
         for i in range(result.object_count):
             vertices_start = sum(result.vertices_len_ptr[:i])
             uvs_start = sum(result.uvs_len_ptr[:i])
             faces_start = sum(result.faces_len_ptr[:i])
+            normals_start = sum(result.normals_len_ptr[:i])
 
             vertices_flat = [round(result.vertices_ptr[i], 4) for i in range(vertices_start, vertices_start + result.vertices_len_ptr[i])]
             uvs_flat = [round(result.uvs_ptr[i], 4) for i in range(uvs_start, uvs_start + result.uvs_len_ptr[i])]
             faces_flat = [result.faces_ptr[i] for i in range(faces_start, faces_start + result.faces_len_ptr[i])]
+            normals_flat = [result.normals_ptr[i] for i in range(normals_start, normals_start + result.normals_len_ptr[i])]
 
             vertices = [Vector(vertices_flat[i:i+3]) for i in range(0, len(vertices_flat), 3)]
             uvs = [uvs_flat[i:i+2] for i in range(0, len(uvs_flat), 2)]
             faces = [faces_flat[i:i+3] for i in range(0, len(faces_flat), 3)]
+            normals = [normals_flat[i:i+3] for i in range(0, len(normals_flat), 3)]
 
             translation_matrix = Matrix.Translation(Vector(result.translation_ptr[:3]))
 
             print(f"Spawning model {i+1} of {result.object_count} for {mesh_name}")
-            obj = spawn_xml_model(vertices, uvs, faces, mesh_name, tex, transformation_matrix, translation_matrix, i > 0)
-            objs.append(obj)
-        if result.object_count > 1:
-            for obj in objs:
-                obj.select_set(True)
-            bpy.context.view_layer.objects.active = objs[0]
-            bpy.ops.object.join()
-            bpy.ops.object.select_all(action='DESELECT')
+            spawn_xml_model(vertices, uvs, faces, normals, mesh_name, tex, transformation_matrix, translation_matrix, i > 0)
     finally:
         lib.ffi_free(result)
 
 
-def spawn_xml_model(vertices, uvs, faces, mesh_name, tex, transformation_matrix, translation_matrix, joined=False):
+def spawn_xml_model(vertices, uvs, faces, normals, mesh_name, tex, transformation_matrix, translation_matrix, joined=False):
     if not vertices or not faces:
         errors.append(f"Invalid xml data for model:{mesh_name}! V:{len(vertices)} F:{len(faces)}")
         print(f"Invalid xml data for model:{mesh_name}! V:{len(vertices)} F:{len(faces)}")
@@ -228,6 +247,11 @@ def spawn_xml_model(vertices, uvs, faces, mesh_name, tex, transformation_matrix,
             vert_idx = mesh.loops[loop_idx].vertex_index
             uv_layer[loop_idx].uv = uvs[vert_idx]
 
+    if normals:
+        if hasattr(mesh, "use_auto_smooth"):
+            mesh.use_auto_smooth = True
+        mesh.normals_split_custom_set_from_vertices(normals)
+
     mesh.transform(translation_matrix)
     mesh.update()
 
@@ -251,13 +275,31 @@ def spawn_xml_model(vertices, uvs, faces, mesh_name, tex, transformation_matrix,
     return obj
 
 
+def import_gltf():
+    dmi_file = os.path.join(DMI_PATH, "DecorationMeshInstances.lua")
+    ok = lib.ffi_export_level(
+        dmi_file.encode("utf-8"),
+        XML_PATH.encode("utf-8"),
+        TEX_PATH.encode("utf-8") if TEX_PATH else None,
+        "\n".join(EXCLUDED).encode("utf-8"),
+        GLTF_PATH.encode("utf-8"),
+    )
+    if not ok:
+        raise Exception(f"Failed to export {dmi_file} to {GLTF_PATH}")
+    bpy.ops.import_scene.gltf(filepath=GLTF_PATH)
+
+
 if __name__ == "__main__":
     load_lib()
-    create_cache(XML_PATH, ".xml", xml_cache)
-    create_cache(TEX_PATH, ".png", tex_cache)
-
-    os.chdir(DMI_PATH)
-    lua = LuaRuntime(unpack_returned_tuples=True)
-    lua.execute('dofile("DecorationMeshInstances.lua")')
-    dmi_table = lua.globals().DecorationMeshInstances
-    traverse_lua_table(dmi_table)
+    if IMPORT_GLTF:
+        import_gltf()
+    else:
+        create_index()
+        try:
+            os.chdir(DMI_PATH)
+            lua = LuaRuntime(unpack_returned_tuples=True)
+            lua.execute('dofile("DecorationMeshInstances.lua")')
+            dmi_table = lua.globals().DecorationMeshInstances
+            traverse_lua_table(dmi_table)
+        finally:
+            lib.ffi_asset_index_free(asset_index)
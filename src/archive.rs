@@ -0,0 +1,123 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+use zip::ZipArchive;
+
+use crate::{entity::Entity, error::ParseError, pssg, structure};
+
+/// A zip archive, such as `Journey.ipa`, read without extracting it.
+///
+/// Entries are addressed by their name inside the archive. Anywhere the crate
+/// takes a file path, an entry can also be given as the archive path followed
+/// by the entry name, e.g. `Journey.ipa/Payload/JourneyLaunch.app/Data/Meshes/P_Rock.pssg.gz`.
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    zip: ZipArchive<BufReader<File>>,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self, ParseError> {
+        let file = File::open(path)
+            .map_err(|e| ParseError::new(&format!("Failed to open file {:?}: {e}", path)))?;
+        let zip = ZipArchive::new(BufReader::new(file))
+            .map_err(|e| ParseError::new(&format!("Failed to read archive {:?}: {e}", path)))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            zip,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Names of all files in the archive.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .zip
+            .file_names()
+            .filter(|n| !n.ends_with('/'))
+            .map(str::to_string)
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Names of the entries that hold PSSG models, XML or binary.
+    pub fn models(&mut self) -> Vec<String> {
+        let candidates: Vec<String> = self
+            .names()
+            .into_iter()
+            .filter(|n| has_extension(n, &pssg::MODEL_EXTENSIONS))
+            .collect();
+        let models: Vec<String> = candidates
+            .into_iter()
+            .filter(|n| self.zip.by_name(n).is_ok_and(pssg::is_pssg))
+            .collect();
+        info!("Found {} model(s) in {:?}", models.len(), self.path);
+        models
+    }
+
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, ParseError> {
+        let mut entry = self.zip.by_name(name).map_err(|e| {
+            ParseError::new(&format!("Failed to find {} in {:?}: {e}", name, self.path))
+        })?;
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(|e| {
+            ParseError::new(&format!("Failed to read {} in {:?}: {e}", name, self.path))
+        })?;
+        Ok(bytes)
+    }
+
+    pub fn parse(&mut self, name: &str) -> Result<Entity, ParseError> {
+        info!("Parsing {} in {:?}", name, self.path);
+        let pssg_file = structure::load_bytes(self.read(name)?)?;
+        Entity::from_pssg(pssg_file)
+    }
+
+    /// The path that addresses entry `name`.
+    pub fn entry_path(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+/// Whether `path` is a zip archive (by extension: `.ipa` or `.zip`).
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("ipa") || e.eq_ignore_ascii_case("zip"))
+}
+
+/// Splits a path that points into an archive into the archive and the entry
+/// name. Returns `None` for paths that exist on disk.
+pub fn split_path(path: &Path) -> Option<(&Path, String)> {
+    if path.exists() {
+        return None;
+    }
+    let archive = path.ancestors().skip(1).find(|p| is_archive(p))?;
+    let entry = path.strip_prefix(archive).ok()?;
+    let entry: Vec<String> = entry
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some((archive, entry.join("/")))
+}
+
+/// Reads a file from disk or from inside an archive.
+pub fn read_file(path: &Path) -> Result<Vec<u8>, ParseError> {
+    match split_path(path) {
+        Some((archive, name)) => Archive::open(archive)?.read(&name),
+        None => fs::read(path)
+            .map_err(|e| ParseError::new(&format!("Failed to open file {:?}: {e}", path))),
+    }
+}
+
+fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    Path::new(name)
+        .extension()
+        .is_some_and(|e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
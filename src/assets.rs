@@ -0,0 +1,327 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    archive::{self, Archive},
+    pssg,
+};
+
+/// File extensions indexed as textures.
+const TEXTURE_EXTENSIONS: [&str; 1] = ["png"];
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum AssetKind {
+    Mesh,
+    Texture,
+}
+
+/// Rules tried, in order, when a name has no exact match. Every match made
+/// through one is logged.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum Fallback {
+    /// Compare names case-insensitively. Not enabled by default: the game's
+    /// names are case-sensitive.
+    IgnoreCase,
+    /// Add or remove the `P_` prefix of mesh names.
+    TogglePrefix,
+    /// Look up textures by their plain name instead of the doubled one.
+    SingleTextureName,
+}
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct Resolution {
+    /// The indexed name that matched.
+    pub name: String,
+    /// Every file with that name; more than one means the name is ambiguous.
+    pub paths: Vec<PathBuf>,
+    /// The fallback that produced the match, if the exact rule did not.
+    pub fallback: Option<Fallback>,
+}
+
+impl Resolution {
+    pub fn path(&self) -> &Path {
+        &self.paths[0]
+    }
+
+    pub fn is_ambiguous(&self) -> bool {
+        self.paths.len() > 1
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct Duplicate {
+    pub kind: AssetKind,
+    pub name: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let paths: Vec<String> = self.paths.iter().map(|p| p.display().to_string()).collect();
+        write!(f, "{:?} {} in {}", self.kind, self.name, paths.join(", "))
+    }
+}
+
+/// Mesh and texture files found below a set of directories, keyed by file
+/// stem. Directories are scanned once; lookups are hash lookups.
+///
+/// Meshes resolve by their exact name. Textures resolve by the Steam naming
+/// rule: the texture name without `P_`, written twice (`P_Stone` ->
+/// `StoneStone`). Names that don't match are retried with [`Fallback`]s;
+/// by default the prefix and texture name rules, not [`Fallback::IgnoreCase`].
+#[derive(Debug)]
+pub struct AssetIndex {
+    meshes: BTreeMap<String, Vec<PathBuf>>,
+    textures: BTreeMap<String, Vec<PathBuf>>,
+    lowercase: HashMap<(AssetKind, String), Vec<String>>,
+    pub fallbacks: Vec<Fallback>,
+}
+
+impl AssetIndex {
+    pub fn new() -> Self {
+        Self {
+            meshes: BTreeMap::new(),
+            textures: BTreeMap::new(),
+            lowercase: HashMap::new(),
+            fallbacks: vec![Fallback::TogglePrefix, Fallback::SingleTextureName],
+        }
+    }
+
+    /// Indexes a mesh and a texture directory, either of which may be absent.
+    pub fn scan(mesh_dir: Option<&Path>, texture_dir: Option<&Path>) -> Self {
+        let mut index = Self::new();
+        if let Some(dir) = mesh_dir {
+            index.add_dir(AssetKind::Mesh, dir);
+        }
+        if let Some(dir) = texture_dir {
+            index.add_dir(AssetKind::Texture, dir);
+        }
+        index
+    }
+
+    /// Recursively indexes `dir`, returning the number of files added. `dir`
+    /// may also be an `.ipa`/`.zip` archive, whose entries are indexed as
+    /// archive paths.
+    pub fn add_dir(&mut self, kind: AssetKind, dir: &Path) -> usize {
+        let extensions: &[&str] = match kind {
+            AssetKind::Mesh => &pssg::MODEL_EXTENSIONS,
+            AssetKind::Texture => &TEXTURE_EXTENSIONS,
+        };
+        let mut files = Vec::new();
+        if archive::is_archive(dir) {
+            match Archive::open(dir) {
+                Ok(mut archive) => {
+                    let names = match kind {
+                        AssetKind::Mesh => archive.models(),
+                        AssetKind::Texture => archive.names(),
+                    };
+                    files.extend(names.iter().map(|n| archive.entry_path(n)));
+                }
+                Err(e) => warn!("{}", e),
+            }
+        } else {
+            collect_files(dir, &mut files);
+        }
+        files.retain(|f| {
+            f.extension()
+                .is_some_and(|e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x)))
+        });
+        // Archive models are already checked by `Archive::models`.
+        if kind == AssetKind::Mesh && !archive::is_archive(dir) {
+            files.retain(|f| File::open(f).is_ok_and(pssg::is_pssg));
+        }
+        files.sort();
+
+        let count = files.len();
+        for path in files {
+            let name = match kind {
+                AssetKind::Mesh => pssg::model_name(&path),
+                AssetKind::Texture => path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            };
+            self.add(kind, name, path);
+        }
+        info!("Indexed {} {:?} file(s) in {:?}", count, kind, dir);
+        count
+    }
+
+    /// Adds a single file under `name`.
+    pub fn add(&mut self, kind: AssetKind, name: String, path: PathBuf) {
+        let names = self
+            .lowercase
+            .entry((kind, name.to_lowercase()))
+            .or_default();
+        if !names.contains(&name) {
+            names.push(name.clone());
+        }
+        self.files_mut(kind).entry(name).or_default().push(path);
+    }
+
+    /// Number of indexed files of `kind`.
+    pub fn count(&self, kind: AssetKind) -> usize {
+        self.files(kind).values().map(Vec::len).sum()
+    }
+
+    /// Names that were found more than once.
+    pub fn duplicates(&self) -> Vec<Duplicate> {
+        [AssetKind::Mesh, AssetKind::Texture]
+            .into_iter()
+            .flat_map(|kind| {
+                self.files(kind)
+                    .iter()
+                    .filter(|(_, paths)| paths.len() > 1)
+                    .map(move |(name, paths)| Duplicate {
+                        kind,
+                        name: name.clone(),
+                        paths: paths.clone(),
+                    })
+            })
+            .collect()
+    }
+
+    /// Resolves a mesh or texture name, trying the exact rule first and then
+    /// the configured fallbacks.
+    pub fn resolve(&self, kind: AssetKind, name: &str) -> Option<Resolution> {
+        let exact = match kind {
+            AssetKind::Mesh => name.to_string(),
+            AssetKind::Texture => texture_file_name(name),
+        };
+        if let Some(resolution) = self.lookup(kind, &exact, None) {
+            return Some(resolution);
+        }
+
+        let candidates: Vec<(String, Fallback)> = self
+            .fallbacks
+            .iter()
+            .flat_map(|&fallback| {
+                let names = match fallback {
+                    Fallback::IgnoreCase => vec![exact.clone()],
+                    Fallback::TogglePrefix if kind == AssetKind::Mesh => {
+                        match name.strip_prefix("P_") {
+                            Some(stripped) => vec![stripped.to_string()],
+                            None => vec![format!("P_{}", name)],
+                        }
+                    }
+                    Fallback::SingleTextureName if kind == AssetKind::Texture => {
+                        vec![name.to_string(), name.replace("P_", "")]
+                    }
+                    _ => Vec::new(),
+                };
+                names.into_iter().map(move |n| (n, fallback))
+            })
+            .collect();
+
+        for (candidate, fallback) in candidates {
+            if let Some(resolution) = self.lookup(kind, &candidate, Some(fallback)) {
+                warn!(
+                    "{:?} name {} not found, using {} ({:?})",
+                    kind, name, resolution.name, fallback
+                );
+                return Some(resolution);
+            }
+        }
+        None
+    }
+
+    /// Resolves a mesh name to a file, warning if it is ambiguous.
+    pub fn resolve_mesh(&self, name: &str) -> Option<&Path> {
+        self.resolve_path(AssetKind::Mesh, name)
+    }
+
+    /// Resolves a texture name from shader parameters to a file, warning if
+    /// it is ambiguous.
+    pub fn resolve_texture(&self, name: &str) -> Option<&Path> {
+        self.resolve_path(AssetKind::Texture, name)
+    }
+
+    fn resolve_path(&self, kind: AssetKind, name: &str) -> Option<&Path> {
+        let resolution = self.resolve(kind, name)?;
+        if resolution.is_ambiguous() {
+            warn!(
+                "{:?} name {} is ambiguous, using {:?} of {:?}",
+                kind,
+                name,
+                resolution.path(),
+                resolution.paths
+            );
+        }
+        self.files(kind)
+            .get(&resolution.name)
+            .map(|paths| paths[0].as_path())
+    }
+
+    fn lookup(
+        &self,
+        kind: AssetKind,
+        name: &str,
+        fallback: Option<Fallback>,
+    ) -> Option<Resolution> {
+        let files = self.files(kind);
+        if fallback == Some(Fallback::IgnoreCase) {
+            let names = self.lowercase.get(&(kind, name.to_lowercase()))?;
+            return Some(Resolution {
+                name: names[0].clone(),
+                paths: names.iter().flat_map(|n| files[n].clone()).collect(),
+                fallback,
+            });
+        }
+
+        files.get(name).map(|paths| Resolution {
+            name: name.to_string(),
+            paths: paths.clone(),
+            fallback,
+        })
+    }
+
+    fn files(&self, kind: AssetKind) -> &BTreeMap<String, Vec<PathBuf>> {
+        match kind {
+            AssetKind::Mesh => &self.meshes,
+            AssetKind::Texture => &self.textures,
+        }
+    }
+
+    fn files_mut(&mut self, kind: AssetKind) -> &mut BTreeMap<String, Vec<PathBuf>> {
+        match kind {
+            AssetKind::Mesh => &mut self.meshes,
+            AssetKind::Texture => &mut self.textures,
+        }
+    }
+}
+
+impl Default for AssetIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Steam textures are named after the texture twice, without the `P_` prefix.
+pub fn texture_file_name(texture: &str) -> String {
+    texture.replace("P_", "").repeat(2)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        warn!("Failed to read {:?}", dir);
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
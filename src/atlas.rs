@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{entity::Entity, error::ParseError, object::Object, scene::Material, texture::Image};
+
+/// A rectangle of an atlas in UV space, with v = 0 at the top row of the
+/// image as in glTF.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct AtlasRegion {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl AtlasRegion {
+    pub const FULL: AtlasRegion = AtlasRegion {
+        min: [0.0, 0.0],
+        max: [1.0, 1.0],
+    };
+
+    /// The smallest region containing `uvs`.
+    pub fn from_uvs(uvs: &[[f32; 2]]) -> Option<Self> {
+        let first = *uvs.first()?;
+        Some(uvs.iter().fold(
+            AtlasRegion {
+                min: first,
+                max: first,
+            },
+            |r, uv| AtlasRegion {
+                min: [r.min[0].min(uv[0]), r.min[1].min(uv[1])],
+                max: [r.max[0].max(uv[0]), r.max[1].max(uv[1])],
+            },
+        ))
+    }
+
+    pub fn size(&self) -> [f32; 2] {
+        [self.max[0] - self.min[0], self.max[1] - self.min[1]]
+    }
+
+    /// Whether the region lies inside the atlas; UVs that tile past its
+    /// edges cannot be cut out.
+    pub fn is_inside(&self) -> bool {
+        self.min.iter().all(|v| *v >= 0.0) && self.max.iter().all(|v| *v <= 1.0)
+    }
+
+    /// Maps atlas UVs to UVs of the cropped texture.
+    pub fn to_crop(&self, uv: [f32; 2]) -> [f32; 2] {
+        let size = self.size();
+        [0, 1].map(|i| {
+            if size[i] > 0.0 {
+                (uv[i] - self.min[i]) / size[i]
+            } else {
+                0.5
+            }
+        })
+    }
+
+    /// Maps UVs of the cropped texture back into the atlas.
+    pub fn to_atlas(&self, uv: [f32; 2]) -> [f32; 2] {
+        let size = self.size();
+        [0, 1].map(|i| self.min[i] + uv[i] * size[i])
+    }
+
+    /// The pixels `(x, y, width, height)` covering the region in a `width` x
+    /// `height` image, rounded outwards to at least one pixel.
+    pub fn pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let span = |min: f32, max: f32, size: u32| {
+            let start = ((min.clamp(0.0, 1.0) * size as f32).floor() as u32).min(size - 1);
+            let end = ((max.clamp(0.0, 1.0) * size as f32).ceil() as u32).clamp(start + 1, size);
+            (start, end - start)
+        };
+        let (x, w) = span(self.min[0], self.max[0], width.max(1));
+        let (y, h) = span(self.min[1], self.max[1], height.max(1));
+        (x, y, w, h)
+    }
+
+    /// The region of exactly the pixels [`AtlasRegion::pixels`] covers, so
+    /// UVs mapped into a crop land on the same texels.
+    pub fn snapped(&self, width: u32, height: u32) -> AtlasRegion {
+        let (x, y, w, h) = self.pixels(width, height);
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        AtlasRegion {
+            min: [x as f32 / width, y as f32 / height],
+            max: [(x + w) as f32 / width, (y + h) as f32 / height],
+        }
+    }
+}
+
+/// The regions of a texture atlas, as stored in the `<atlas>.atlas.json`
+/// file next to the atlas texture.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Atlas {
+    /// Texture name of the atlas, as materials reference it.
+    pub texture: String,
+    /// Regions by the texture, object or model that uses them.
+    pub regions: BTreeMap<String, AtlasRegion>,
+}
+
+impl Atlas {
+    pub fn load(path: &Path) -> Result<Self, ParseError> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| ParseError::new(&format!("Failed to read {:?}: {e}", path)))?;
+        serde_json::from_str(&json)
+            .map_err(|e| ParseError::new(&format!("Invalid atlas metadata {:?}: {e}", path)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ParseError> {
+        let json = serde_json::to_string_pretty(self).expect("Failed to serialize atlas");
+        fs::write(path, json)
+            .map_err(|e| ParseError::new(&format!("Failed to write {:?}: {e}", path)))
+    }
+
+    /// Where the metadata of an atlas texture lives: `ClothAtlas.png` has
+    /// `ClothAtlas.atlas.json`.
+    pub fn metadata_path(texture_path: &Path) -> PathBuf {
+        texture_path.with_extension("atlas.json")
+    }
+
+    /// The metadata next to an atlas texture, if it has any.
+    pub fn find(texture_path: &Path) -> Result<Option<Self>, ParseError> {
+        let path = Self::metadata_path(texture_path);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Self::load(&path).map(Some)
+    }
+
+    /// The region of the first of `keys` the atlas has.
+    pub fn region(&self, keys: &[&str]) -> Option<AtlasRegion> {
+        keys.iter().find_map(|k| self.regions.get(*k)).copied()
+    }
+}
+
+impl Object {
+    /// The part of the texture the object samples.
+    pub fn uv_region(&self) -> Option<AtlasRegion> {
+        AtlasRegion::from_uvs(&self.uvs)
+    }
+
+    pub fn map_uvs_to_crop(&mut self, region: &AtlasRegion) {
+        for uv in &mut self.uvs {
+            *uv = region.to_crop(*uv);
+        }
+    }
+
+    pub fn map_uvs_to_atlas(&mut self, region: &AtlasRegion) {
+        for uv in &mut self.uvs {
+            *uv = region.to_atlas(*uv);
+        }
+    }
+
+    /// Points the object and its submeshes at `material`.
+    fn set_material(&mut self, material: &str) {
+        self.material = Some(material.to_string());
+        for submesh in &mut self.submeshes {
+            submesh.material = Some(material.to_string());
+        }
+    }
+}
+
+impl Entity {
+    /// Cuts the part of `atlas` each object samples into a texture of its
+    /// own, `<name>_<object>` (`<name>_<object>_<n>` for repeated names),
+    /// and maps the object's UVs into it. Regions
+    /// come from `metadata` (by object, then model name) or else from the
+    /// object's UVs; objects whose UVs tile past the atlas are left alone.
+    ///
+    /// Returns the crops and metadata locating them in the atlas, which
+    /// [`Entity::join_atlas`] can use to undo the split.
+    pub fn split_atlas(
+        &mut self,
+        atlas: &Image,
+        atlas_name: &str,
+        metadata: Option<&Atlas>,
+        name: &str,
+    ) -> (Vec<(String, Image)>, Atlas) {
+        let mut crops = Vec::new();
+        let mut regions = BTreeMap::new();
+        for object in &mut self.objects {
+            if !object.has_uvs() {
+                continue;
+            }
+            let region = metadata
+                .and_then(|m| m.region(&[&object.name, name]))
+                .or_else(|| object.uv_region());
+            let Some(region) = region.filter(AtlasRegion::is_inside) else {
+                warn!("{} samples outside the atlas; keeping its UVs", object.name);
+                continue;
+            };
+            let (x, y, width, height) = region.pixels(atlas.width, atlas.height);
+            let region = region.snapped(atlas.width, atlas.height);
+
+            // Objects may share a name, or only differ in characters
+            // file_name replaces; each still gets its own crop.
+            let base = file_name(&format!("{}_{}", name, object.name));
+            let mut texture = base.clone();
+            let mut n = 1;
+            while regions.contains_key(&texture) {
+                n += 1;
+                texture = format!("{}_{}", base, n);
+            }
+            object.map_uvs_to_crop(&region);
+            object.set_material(&texture);
+            if self.materials.iter().all(|m| m.name != texture) {
+                self.materials.push(Material {
+                    name: texture.clone(),
+                    texture: Some(texture.clone()),
+                });
+            }
+            regions.insert(texture.clone(), region);
+            crops.push((texture, atlas.crop(x, y, width, height)));
+        }
+        info!("Cut {} textures out of {}", crops.len(), atlas_name);
+        let metadata = Atlas {
+            texture: atlas_name.to_string(),
+            regions,
+        };
+        (crops, metadata)
+    }
+
+    /// Maps the UVs of every object whose texture (or name) has a region in
+    /// `atlas` into the atlas and points the object at the atlas texture.
+    /// Returns how many objects were remapped.
+    pub fn join_atlas(&mut self, atlas: &Atlas) -> usize {
+        let mut joined = 0;
+        for i in 0..self.objects.len() {
+            let object = &self.objects[i];
+            let texture = object
+                .material
+                .as_deref()
+                .and_then(|m| self.material(m))
+                .and_then(|m| m.texture.clone())
+                .unwrap_or_default();
+            let Some(region) = atlas.region(&[&texture, &object.name]) else {
+                continue;
+            };
+            if !object.has_uvs() {
+                continue;
+            }
+            let object = &mut self.objects[i];
+            object.map_uvs_to_atlas(&region);
+            object.set_material(&atlas.texture);
+            joined += 1;
+        }
+        if joined > 0 && self.materials.iter().all(|m| m.name != atlas.texture) {
+            self.materials.push(Material {
+                name: atlas.texture.clone(),
+                texture: Some(atlas.texture.clone()),
+            });
+        }
+        info!("Mapped {} objects into {}", joined, atlas.texture);
+        joined
+    }
+}
+
+/// Keeps a texture name usable as a file name.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
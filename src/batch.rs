@@ -0,0 +1,210 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use tracing::info;
+
+use crate::{
+    archive::{self, Archive},
+    entity::Entity,
+    error::ParseError,
+    export::ExportOptions,
+    parse, pssg,
+};
+
+#[derive(Debug)]
+pub struct BatchOptions {
+    pub export: ExportOptions,
+    /// Number of worker threads; defaults to the number of cores.
+    pub jobs: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct FileReport {
+    pub input: PathBuf,
+    pub outputs: Vec<PathBuf>,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct BatchReport {
+    pub succeeded: usize,
+    pub with_warnings: usize,
+    pub failed: usize,
+    pub files: Vec<FileReport>,
+}
+
+impl BatchReport {
+    fn new(files: Vec<FileReport>) -> Self {
+        let failed = files.iter().filter(|f| f.error.is_some()).count();
+        let with_warnings = files
+            .iter()
+            .filter(|f| f.error.is_none() && !f.warnings.is_empty())
+            .count();
+        Self {
+            succeeded: files.len() - failed,
+            with_warnings,
+            failed,
+            files,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize batch report")
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for file in &self.files {
+            for warning in &file.warnings {
+                writeln!(f, "warning: {}: {}", file.input.display(), warning)?;
+            }
+            if let Some(error) = &file.error {
+                writeln!(f, "error: {}: {}", file.input.display(), error)?;
+            }
+        }
+        write!(
+            f,
+            "{} file(s): {} converted ({} with warnings), {} failed",
+            self.files.len(),
+            self.succeeded,
+            self.with_warnings,
+            self.failed
+        )
+    }
+}
+
+/// Recursively finds PSSG files (XML, binary or gzipped) below `dir`. If
+/// `dir` is an archive, its model entries are returned as archive paths.
+pub fn find_pssg_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if archive::is_archive(dir) {
+        let mut archive = Archive::open(dir).map_err(io::Error::other)?;
+        let models = archive.models();
+        return Ok(models.iter().map(|m| archive.entry_path(m)).collect());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(find_pssg_files(&path)?);
+        } else if path.extension().is_some_and(|e| {
+            pssg::MODEL_EXTENSIONS
+                .iter()
+                .any(|x| e.eq_ignore_ascii_case(x))
+        }) && pssg::is_pssg(File::open(&path)?)
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Converts every PSSG file below `input` in parallel, mirroring the directory
+/// tree into `options.export.out_dir` (or next to the inputs if unset). An
+/// archive is read in place and mirrored into a folder named after it.
+pub fn convert_dir(input: &Path, options: &BatchOptions) -> Result<BatchReport, ParseError> {
+    let root = match &options.export.out_dir {
+        Some(out_dir) => out_dir.clone(),
+        None if archive::is_archive(input) => input.with_extension(""),
+        None => input.to_path_buf(),
+    };
+    for_each_file(input, options.jobs, |file, relative, entity| {
+        crate::export(
+            entity,
+            &pssg::model_name(file),
+            &root.join(relative),
+            &options.export,
+        )
+        .map(|report| report.written)
+    })
+}
+
+/// Parses every PSSG file below `input` in parallel and runs `process` on
+/// it with the file's directory relative to `input`, collecting the files
+/// it returns into a report.
+pub(crate) fn for_each_file<F>(
+    input: &Path,
+    jobs: Option<usize>,
+    process: F,
+) -> Result<BatchReport, ParseError>
+where
+    F: Fn(&Path, &Path, &Entity) -> Result<Vec<PathBuf>, ParseError> + Sync,
+{
+    let files = find_pssg_files(input)
+        .map_err(|e| ParseError::new(&format!("Failed to read {:?}: {e}", input)))?;
+    info!("Processing {} file(s) from {:?}", files.len(), input);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+        .map_err(|e| ParseError::new(&format!("Failed to start worker threads: {e}")))?;
+
+    let is_archive = archive::is_archive(input);
+    let reports = pool.install(|| {
+        files
+            .par_iter()
+            .map_init(
+                // Every worker reads from its own handle to the archive.
+                || is_archive.then(|| Archive::open(input)).transpose(),
+                |archive, file| {
+                    let archive = archive
+                        .as_mut()
+                        .map_err(|e| ParseError::new(&e.to_string()));
+                    process_one(input, file, archive, &process)
+                },
+            )
+            .collect()
+    });
+
+    Ok(BatchReport::new(reports))
+}
+
+fn process_one<F>(
+    input: &Path,
+    file: &Path,
+    archive: Result<&mut Option<Archive>, ParseError>,
+    process: &F,
+) -> FileReport
+where
+    F: Fn(&Path, &Path, &Entity) -> Result<Vec<PathBuf>, ParseError>,
+{
+    let relative = file
+        .parent()
+        .and_then(|p| p.strip_prefix(input).ok())
+        .unwrap_or(Path::new(""));
+
+    let mut report = FileReport {
+        input: file.to_path_buf(),
+        outputs: Vec::new(),
+        warnings: Vec::new(),
+        error: None,
+    };
+
+    let entity = archive.and_then(|archive| match archive {
+        Some(archive) => {
+            let entry = file.strip_prefix(input).unwrap_or(file);
+            archive.parse(&entry.to_string_lossy().replace('\\', "/"))
+        }
+        None => parse(file),
+    });
+    let result = entity.and_then(|entity| {
+        report.warnings.extend(entity.warnings.iter().cloned());
+        report.warnings.extend(entity.validate());
+        process(file, relative, &entity)
+    });
+
+    match result {
+        Ok(outputs) => report.outputs = outputs,
+        Err(e) => report.error = Some(e.to_string()),
+    }
+    report
+}
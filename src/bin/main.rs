@@ -1,13 +1,801 @@
-use std::env;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use journey_model_parser::convert_file;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let xml_file = Path::new(&args[1]);
+use journey_model_parser::{
+    convert_dir, convert_file_with, dump, export_textures, load_textures, model_name, parse,
+    parse_with, render_catalogue, Archive, AssetIndex, AssetKind, BatchOptions, CatalogueOptions,
+    CoordinateSystem, CountMismatch, DumpFormat, DumpOptions, ExportOptions, Fallback, Format,
+    Handedness, Level, LevelOptions, MipSelection, NormalGeneration, NormalMode, NormalOptions,
+    NormalWeighting, ParseOptions, RenderMode, RenderOptions, RepairOptions, SimplifyOptions,
+    SubmeshMode, TextureOptions, TransformMode, UpAxis, Validation, WeldMode, WeldOptions,
+};
 
-    assert!(xml_file.exists(), "File does not exist");
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Convert Journey PSSG XML models",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    convert_file(xml_file);
+    /// File to convert to OBJ (shorthand for `convert <FILE>`)
+    file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert one or more files
+    Convert {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        export: ExportArgs,
+    },
+    /// Print what a file contains
+    Info {
+        file: PathBuf,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the parsed PSSG database
+    Dump {
+        file: PathBuf,
+        /// Print YAML instead of JSON
+        #[arg(long)]
+        yaml: bool,
+        /// Maximum number of values kept per payload
+        #[arg(long, default_value_t = 64, conflicts_with = "full")]
+        max_payload: usize,
+        /// Keep payloads whole
+        #[arg(long)]
+        full: bool,
+        /// Include the first N decoded values of every data block
+        #[arg(long, value_name = "N")]
+        preview: Option<usize>,
+    },
+    /// Check files for decoding problems
+    Validate {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Fail on every warning and count mismatch instead of working around them
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Convert every PSSG file below a directory in parallel
+    Batch {
+        dir: PathBuf,
+        #[command(flatten)]
+        export: ExportArgs,
+        /// Number of worker threads (defaults to the number of cores)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Write a JSON report of all files to this path
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// List the models inside an .ipa or .zip archive
+    List { archive: PathBuf },
+    /// Index mesh and texture directories and resolve names against them
+    Assets {
+        /// Directory containing PSSG XML files
+        #[arg(long)]
+        xml_dir: Option<PathBuf>,
+        /// Directory containing PNG textures
+        #[arg(long)]
+        texture_dir: Option<PathBuf>,
+        /// Mesh name to resolve (repeatable)
+        #[arg(long, value_name = "NAME")]
+        mesh: Vec<String>,
+        /// Texture name from shader parameters to resolve (repeatable)
+        #[arg(long, value_name = "NAME")]
+        texture: Vec<String>,
+        /// Only resolve exact names, without the prefix fallbacks
+        #[arg(long, conflicts_with = "ignore_case")]
+        exact: bool,
+        /// Also fall back to comparing names case-insensitively
+        #[arg(long)]
+        ignore_case: bool,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Decode the textures in PSSG files to PNG
+    Textures {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Output directory (defaults to each input file's directory)
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+        /// Mip level to write; textures with fewer levels write their smallest
+        #[arg(long, default_value_t = 0, conflicts_with = "all_mips")]
+        mip: usize,
+        /// Write every mip level as `<name>_mip<n>.png`
+        #[arg(long)]
+        all_mips: bool,
+        /// Write cube maps as one strip of six faces instead of a file per face
+        #[arg(long)]
+        cube_strip: bool,
+        /// Only list the textures and their formats
+        #[arg(long)]
+        list: bool,
+    },
+    /// Render models to PNG thumbnails without a GPU
+    Render {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Output directory (defaults to each input file's directory)
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Render a thumbnail of every PSSG file below a directory into a catalogue
+    Catalogue {
+        dir: PathBuf,
+        /// Output directory for the thumbnails and index.html
+        #[arg(short, long, default_value = "catalogue")]
+        out_dir: PathBuf,
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Number of worker threads (defaults to the number of cores)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Export a level from DecorationMeshInstances.lua as one glTF or USD scene
+    Level {
+        dmi: PathBuf,
+        /// Directory containing the PSSG XML files of the meshes
+        #[arg(long)]
+        xml_dir: PathBuf,
+        /// Directory containing the PNG textures
+        #[arg(long)]
+        texture_dir: Option<PathBuf>,
+        /// Mesh to leave out (repeatable)
+        #[arg(long, value_name = "MESH")]
+        exclude: Vec<String>,
+        /// Output file; `.glb` writes a binary glTF, `.usda` a USD layer
+        #[arg(short, long, default_value = "level.glb")]
+        output: PathBuf,
+        #[command(flatten)]
+        coordinates: CoordinateArgs,
+    },
+}
+
+#[derive(Parser)]
+struct ExportArgs {
+    /// Output format: obj, dae, usda, gltf or glb
+    #[arg(short, long, default_value = "obj")]
+    format: Format,
+    /// Output directory (defaults to the input file's directory)
+    #[arg(short, long)]
+    out_dir: Option<PathBuf>,
+    /// Merge all objects of a model into a single mesh
+    #[arg(long)]
+    merge: bool,
+    /// Ranges a merged mesh keeps: none, object or material
+    #[arg(long, default_value = "object", requires = "merge")]
+    submeshes: SubmeshMode,
+    /// Weld vertices closer than this distance (0 welds exact duplicates)
+    #[arg(long, value_name = "TOLERANCE")]
+    weld: Option<f32>,
+    /// Weld by position only, ignoring normals, UVs and colors
+    #[arg(long, requires = "weld")]
+    weld_positions: bool,
+    /// Unify face winding and drop degenerate faces, duplicate faces and unused vertices
+    #[arg(long)]
+    repair: bool,
+    /// Cut each object's part of this atlas PNG into its own texture and remap the UVs
+    #[arg(long, value_name = "PNG")]
+    atlas: Option<PathBuf>,
+    /// Map UVs of standalone textures into the regions of this atlas metadata file
+    #[arg(long, value_name = "JSON", conflicts_with = "atlas")]
+    into_atlas: Option<PathBuf>,
+    /// When to generate normals: missing, always or never
+    #[arg(long, default_value = "missing")]
+    normals: NormalGeneration,
+    /// Generate flat instead of smooth normals
+    #[arg(long)]
+    flat_normals: bool,
+    /// Weight smooth normals by face area instead of corner angle
+    #[arg(long, conflicts_with = "flat_normals")]
+    area_weighted: bool,
+    /// Keep edges sharper than this angle in degrees when smoothing
+    #[arg(long, default_value_t = 30.0, conflicts_with = "flat_normals")]
+    crease_angle: f32,
+    /// Reorder faces and vertices for GPU vertex cache and fetch efficiency
+    #[arg(long)]
+    optimize: bool,
+    /// Also write a simplified LOD keeping this fraction of the triangles (repeatable)
+    #[arg(long, value_name = "RATIO")]
+    lod: Vec<f32>,
+    /// Stop simplifying LODs before the surface moves further than this
+    #[arg(long, value_name = "DISTANCE")]
+    lod_error: Option<f32>,
+    /// Write LODs to separate files instead of glTF MSFT_lod levels
+    #[arg(long)]
+    separate_lods: bool,
+    /// Bake node transforms into the geometry instead of writing them as matrices
+    #[arg(long)]
+    bake: bool,
+    #[command(flatten)]
+    coordinates: CoordinateArgs,
+}
+
+impl ExportArgs {
+    fn options(&self) -> ExportOptions {
+        ExportOptions {
+            format: self.format,
+            out_dir: self.out_dir.clone(),
+            merge: self.merge,
+            submeshes: self.submeshes,
+            weld: self.weld.map(|tolerance| WeldOptions {
+                mode: if self.weld_positions {
+                    WeldMode::Position
+                } else {
+                    WeldMode::Attributes
+                },
+                tolerance,
+            }),
+            repair: self.repair.then(RepairOptions::default),
+            split_atlas: self.atlas.clone(),
+            join_atlas: self.into_atlas.clone(),
+            normals: NormalOptions {
+                generate: self.normals,
+                mode: if self.flat_normals {
+                    NormalMode::Flat
+                } else {
+                    NormalMode::Smooth {
+                        weighting: if self.area_weighted {
+                            NormalWeighting::Area
+                        } else {
+                            NormalWeighting::Angle
+                        },
+                        crease_angle: Some(self.crease_angle),
+                    }
+                },
+            },
+            optimize: self.optimize,
+            lods: self.lods(),
+            separate_lods: self.separate_lods,
+            coordinate_system: self.coordinates.system(),
+            transforms: if self.bake {
+                TransformMode::Bake
+            } else {
+                TransformMode::Keep
+            },
+        }
+    }
+
+    /// One LOD per `--lod` ratio; `--lod-error` alone simplifies as far as the
+    /// error allows.
+    fn lods(&self) -> Vec<SimplifyOptions> {
+        let ratios = match (self.lod.is_empty(), self.lod_error) {
+            (true, Some(_)) => vec![0.0],
+            _ => self.lod.clone(),
+        };
+        ratios
+            .into_iter()
+            .map(|ratio| SimplifyOptions {
+                ratio,
+                max_error: self.lod_error,
+            })
+            .collect()
+    }
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// Width and height of each view in pixels
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..))]
+    size: u32,
+    /// Render mode: shaded or textured
+    #[arg(long, default_value = "shaded")]
+    mode: RenderMode,
+    /// Directory containing the PNG textures for `--mode textured`
+    #[arg(long)]
+    texture_dir: Option<PathBuf>,
+    /// Camera angle around the model in degrees
+    #[arg(long, default_value_t = 45.0, allow_negative_numbers = true)]
+    yaw: f32,
+    /// Camera angle above the horizon in degrees
+    #[arg(long, default_value_t = 25.0, allow_negative_numbers = true)]
+    pitch: f32,
+    /// Render a contact sheet of this many views around the model
+    #[arg(long, default_value_t = 1)]
+    views: usize,
+    /// Views per row of a contact sheet
+    #[arg(long, default_value_t = 4)]
+    columns: usize,
+}
+
+impl RenderArgs {
+    fn options(&self) -> RenderOptions {
+        RenderOptions {
+            width: self.size,
+            height: self.size,
+            mode: self.mode,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            views: self.views,
+            columns: self.columns,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Args)]
+struct CoordinateArgs {
+    /// Up axis of the output: y (the game's) or z
+    #[arg(long)]
+    up: Option<UpAxis>,
+    /// Mirror the output into a left-handed system
+    #[arg(long)]
+    left_handed: bool,
+    /// Scale factor applied to positions and translations
+    #[arg(long, value_parser = parse_scale)]
+    scale: Option<f32>,
+}
+
+fn parse_scale(s: &str) -> Result<f32, String> {
+    let scale: f32 = s.parse().map_err(|e| format!("{}", e))?;
+    CoordinateSystem::new(UpAxis::Y, Handedness::Right, scale)
+        .map(|_| scale)
+        .map_err(|e| e.message().to_string())
+}
+
+impl CoordinateArgs {
+    fn system(&self) -> Option<CoordinateSystem> {
+        if self.up.is_none() && !self.left_handed && self.scale.is_none() {
+            return None;
+        }
+        let system = CoordinateSystem::new(
+            self.up.unwrap_or(UpAxis::Y),
+            if self.left_handed {
+                Handedness::Left
+            } else {
+                Handedness::Right
+            },
+            self.scale.unwrap_or(1.0),
+        );
+        Some(system.expect("scale is checked by parse_scale"))
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let command = match (cli.command, cli.file) {
+        (Some(command), _) => command,
+        (None, Some(file)) => Command::Convert {
+            files: vec![file],
+            export: ExportArgs::parse_from(["convert"]),
+        },
+        (None, None) => {
+            Cli::command().print_help().ok();
+            return ExitCode::from(2);
+        }
+    };
+
+    let ok = match command {
+        Command::Convert { files, export } => convert(&files, &export.options()),
+        Command::Info { file, json } => info(&file, json),
+        Command::Dump {
+            file,
+            yaml,
+            max_payload,
+            full,
+            preview,
+        } => {
+            let options = DumpOptions {
+                format: if yaml {
+                    DumpFormat::Yaml
+                } else {
+                    DumpFormat::Json
+                },
+                max_payload: (!full).then_some(max_payload),
+                preview,
+            };
+            match dump(&file, &options) {
+                Ok(dump) => {
+                    println!("{}", dump);
+                    true
+                }
+                Err(e) => {
+                    eprintln!("{}: {}", file.display(), e);
+                    false
+                }
+            }
+        }
+        Command::Validate { files, strict } => {
+            let options = if strict {
+                ParseOptions::new()
+                    .validation(Validation::Strict)
+                    .count_mismatch(CountMismatch::Error)
+            } else {
+                ParseOptions::default()
+            };
+            validate(&files, &options)
+        }
+        Command::Batch {
+            dir,
+            export,
+            jobs,
+            report,
+        } => batch(&dir, export.options(), jobs, report.as_deref()),
+        Command::List { archive } => match Archive::open(&archive) {
+            Ok(mut archive) => {
+                for model in archive.models() {
+                    println!("{}", archive.entry_path(&model).display());
+                }
+                true
+            }
+            Err(e) => {
+                eprintln!("{}: {}", archive.display(), e);
+                false
+            }
+        },
+        Command::Assets {
+            xml_dir,
+            texture_dir,
+            mesh,
+            texture,
+            exact,
+            ignore_case,
+            json,
+        } => {
+            let mut index = AssetIndex::scan(xml_dir.as_deref(), texture_dir.as_deref());
+            if exact {
+                index.fallbacks.clear();
+            }
+            if ignore_case {
+                index.fallbacks.insert(0, Fallback::IgnoreCase);
+            }
+            let queries: Vec<(AssetKind, String)> = mesh
+                .into_iter()
+                .map(|n| (AssetKind::Mesh, n))
+                .chain(texture.into_iter().map(|n| (AssetKind::Texture, n)))
+                .collect();
+            assets(&index, &queries, json)
+        }
+        Command::Textures {
+            files,
+            out_dir,
+            mip,
+            all_mips,
+            cube_strip,
+            list,
+        } => {
+            let options = TextureOptions {
+                mips: if all_mips {
+                    MipSelection::All
+                } else {
+                    MipSelection::Level(mip)
+                },
+                split_faces: !cube_strip,
+            };
+            textures(&files, out_dir.as_deref(), &options, list)
+        }
+        Command::Render {
+            files,
+            out_dir,
+            render: args,
+        } => {
+            let index = AssetIndex::scan(None, args.texture_dir.as_deref());
+            render(&files, out_dir.as_deref(), &args.options(), &index)
+        }
+        Command::Catalogue {
+            dir,
+            out_dir,
+            render: args,
+            jobs,
+        } => {
+            let options = CatalogueOptions {
+                render: args.options(),
+                texture_dir: args.texture_dir,
+                jobs,
+            };
+            catalogue(&dir, &out_dir, &options)
+        }
+        Command::Level {
+            dmi,
+            xml_dir,
+            texture_dir,
+            exclude,
+            output,
+            coordinates,
+        } => {
+            let options = LevelOptions {
+                xml_dir,
+                texture_dir,
+                excluded: exclude,
+                ..Default::default()
+            };
+            level(&dmi, &options, coordinates.system(), &output)
+        }
+    };
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn convert(files: &[PathBuf], options: &ExportOptions) -> bool {
+    let mut ok = true;
+    for file in files {
+        match convert_file_with(file, options) {
+            Ok(report) => {
+                for path in &report.written {
+                    println!("{}", path.display());
+                }
+                for (object, optimized) in &report.optimized {
+                    println!("optimized {}: {}", object, optimized);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn info(file: &Path, json: bool) -> bool {
+    match parse(file) {
+        Ok(entity) => {
+            let summary = entity.summary();
+            if json {
+                println!("{}", summary.to_json());
+            } else {
+                println!("{}", file.display());
+                print!("{}", summary);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("{}: {}", file.display(), e);
+            false
+        }
+    }
+}
+
+fn validate(files: &[PathBuf], options: &ParseOptions) -> bool {
+    let mut ok = true;
+    for file in files {
+        match parse_with(file, options) {
+            Ok(entity) => {
+                let issues = entity.validate();
+                if issues.is_empty() {
+                    println!("{}: ok", file.display());
+                }
+                for issue in &issues {
+                    println!("{}: {}", file.display(), issue);
+                }
+                ok &= issues.is_empty();
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn batch(
+    dir: &Path,
+    export: ExportOptions,
+    jobs: Option<usize>,
+    report_path: Option<&Path>,
+) -> bool {
+    let report = match convert_dir(dir, &BatchOptions { export, jobs }) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", dir.display(), e);
+            return false;
+        }
+    };
+
+    println!("{}", report);
+    if let Some(path) = report_path {
+        if let Err(e) = fs::write(path, report.to_json()) {
+            eprintln!("{}: {}", path.display(), e);
+            return false;
+        }
+    }
+    report.failed == 0
+}
+
+fn level(
+    dmi: &Path,
+    options: &LevelOptions,
+    coordinate_system: Option<CoordinateSystem>,
+    output: &Path,
+) -> bool {
+    let mut level = match Level::load(dmi, options) {
+        Ok(level) => level,
+        Err(e) => {
+            eprintln!("{}: {}", dmi.display(), e);
+            return false;
+        }
+    };
+    for error in &level.errors {
+        eprintln!("{}", error);
+    }
+    if let Some(coordinate_system) = coordinate_system {
+        level.convert(&coordinate_system);
+    }
+
+    match level.save(output) {
+        Ok(written) => {
+            for path in written {
+                println!("{}", path.display());
+            }
+            println!(
+                "{} instances of {} meshes",
+                level.instances.len(),
+                level.entities.len()
+            );
+            if let Some(bounds) = level.bounds() {
+                println!("Bounds: {:?} - {:?}", bounds.aabb.min, bounds.aabb.max);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("{}: {}", output.display(), e);
+            false
+        }
+    }
+}
+
+fn textures(
+    files: &[PathBuf],
+    out_dir: Option<&Path>,
+    options: &TextureOptions,
+    list: bool,
+) -> bool {
+    let mut ok = true;
+    for file in files {
+        let result = if list {
+            load_textures(file).map(|textures| {
+                for texture in textures {
+                    println!(
+                        "{}: {}x{} {}, {} mips{}",
+                        texture.name,
+                        texture.width,
+                        texture.height,
+                        texture.format,
+                        texture.mip_count(),
+                        if texture.is_cube_map() {
+                            ", cube map"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            })
+        } else {
+            let dir = out_dir
+                .or_else(|| file.parent())
+                .unwrap_or_else(|| Path::new("."));
+            export_textures(file, dir, options).map(|written| {
+                for path in written {
+                    println!("{}", path.display());
+                }
+            })
+        };
+        if let Err(e) = result {
+            eprintln!("{}: {}", file.display(), e);
+            ok = false;
+        }
+    }
+    ok
+}
+
+fn render(
+    files: &[PathBuf],
+    out_dir: Option<&Path>,
+    options: &RenderOptions,
+    index: &AssetIndex,
+) -> bool {
+    if let Some(dir) = out_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("{}: {}", dir.display(), e);
+            return false;
+        }
+    }
+    let mut ok = true;
+    for file in files {
+        let dir = out_dir
+            .or_else(|| file.parent())
+            .unwrap_or_else(|| Path::new("."));
+        let path = dir.join(format!("{}.png", model_name(file)));
+        let result =
+            parse(file).and_then(|entity| entity.thumbnail(options, Some(index)).save_png(&path));
+        match result {
+            Ok(()) => println!("{}", path.display()),
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn catalogue(dir: &Path, out_dir: &Path, options: &CatalogueOptions) -> bool {
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        eprintln!("{}: {}", out_dir.display(), e);
+        return false;
+    }
+    match render_catalogue(dir, out_dir, options) {
+        Ok(report) => {
+            println!("{}", report);
+            println!("{}", out_dir.join("index.html").display());
+            report.failed == 0
+        }
+        Err(e) => {
+            eprintln!("{}: {}", dir.display(), e);
+            false
+        }
+    }
+}
+
+fn assets(index: &AssetIndex, queries: &[(AssetKind, String)], json: bool) -> bool {
+    let duplicates = index.duplicates();
+    let resolved: Vec<_> = queries
+        .iter()
+        .map(|(kind, name)| (*kind, name, index.resolve(*kind, name)))
+        .collect();
+
+    if json {
+        let resolved: Vec<_> = resolved
+            .iter()
+            .map(|(kind, name, resolution)| {
+                serde_json::json!({ "kind": kind, "name": name, "resolution": resolution })
+            })
+            .collect();
+        let report = serde_json::json!({
+            "meshes": index.count(AssetKind::Mesh),
+            "textures": index.count(AssetKind::Texture),
+            "duplicates": duplicates,
+            "resolved": resolved,
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        println!(
+            "{} mesh file(s), {} texture file(s)",
+            index.count(AssetKind::Mesh),
+            index.count(AssetKind::Texture)
+        );
+        for duplicate in &duplicates {
+            println!("duplicate: {}", duplicate);
+        }
+        for (kind, name, resolution) in &resolved {
+            match resolution {
+                Some(resolution) => {
+                    print!("{:?} {}: {}", kind, name, resolution.path().display());
+                    if let Some(fallback) = resolution.fallback {
+                        print!(" (via {:?})", fallback);
+                    }
+                    if resolution.is_ambiguous() {
+                        print!(" (ambiguous, {} candidates)", resolution.paths.len());
+                    }
+                    println!();
+                }
+                None => println!("{:?} {}: not found", kind, name),
+            }
+        }
+    }
+    resolved
+        .iter()
+        .all(|(_, _, resolution)| resolution.is_some())
 }
@@ -0,0 +1,275 @@
+use serde::Serialize;
+
+use crate::{
+    entity::Entity,
+    level::Level,
+    math::{dot, sub, transform_point, Matrix},
+    object::Object,
+    scene::SceneNode,
+};
+
+/// Relative tolerance [`Entity::bounds_mismatches`] is used with in summaries;
+/// `BOUNDINGBOX` values are often rounded or padded slightly.
+pub const BOUNDS_TOLERANCE: f32 = 1e-3;
+
+/// Axis-aligned bounding box.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a [f32; 3]>) -> Option<Self> {
+        points.into_iter().fold(None, |acc: Option<Aabb>, p| {
+            Some(match acc {
+                Some(b) => b.union(&Aabb { min: *p, max: *p }),
+                None => Aabb { min: *p, max: *p },
+            })
+        })
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut out = *self;
+        for i in 0..3 {
+            out.min[i] = out.min[i].min(other.min[i]);
+            out.max[i] = out.max[i].max(other.max[i]);
+        }
+        out
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| (self.min[i] + self.max[i]) / 2.0)
+    }
+
+    pub fn size(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| self.max[i] - self.min[i])
+    }
+
+    pub fn corners(&self) -> [[f32; 3]; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|c| {
+            [0, 1, 2].map(|i| {
+                if c & (1 << i) == 0 {
+                    self.min[i]
+                } else {
+                    self.max[i]
+                }
+            })
+        })
+    }
+
+    /// The box around this box after transforming it by `matrix`.
+    pub fn transformed(&self, matrix: &Matrix) -> Aabb {
+        let corners = self.corners().map(|c| transform_point(&c, matrix));
+        Aabb::from_points(&corners).unwrap_or(*self)
+    }
+
+    /// Whether both corners lie within `tolerance` of `other`'s, where the
+    /// tolerance is relative to the size of the larger box.
+    pub fn approx_eq(&self, other: &Aabb, tolerance: f32) -> bool {
+        let size = self
+            .size()
+            .into_iter()
+            .chain(other.size())
+            .fold(0.0f32, f32::max);
+        let tolerance = tolerance * size.max(1.0);
+        (0..3).all(|i| {
+            (self.min[i] - other.min[i]).abs() <= tolerance
+                && (self.max[i] - other.max[i]).abs() <= tolerance
+        })
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct Sphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// A sphere around `points` using Ritter's algorithm: close to minimal
+    /// and computed in two passes.
+    pub fn from_points(points: &[[f32; 3]]) -> Option<Self> {
+        let first = *points.first()?;
+        let farthest = |from: [f32; 3]| {
+            points
+                .iter()
+                .copied()
+                .max_by(|a, b| distance2(*a, from).total_cmp(&distance2(*b, from)))
+                .unwrap_or(from)
+        };
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut sphere = Sphere {
+            center: [0, 1, 2].map(|i| (a[i] + b[i]) / 2.0),
+            radius: distance2(a, b).sqrt() / 2.0,
+        };
+        for p in points {
+            let d = distance2(*p, sphere.center).sqrt();
+            if d > sphere.radius {
+                let radius = (sphere.radius + d) / 2.0;
+                let shift = (d - radius) / d;
+                sphere.center =
+                    [0, 1, 2].map(|i| sphere.center[i] + (p[i] - sphere.center[i]) * shift);
+                sphere.radius = radius;
+            }
+        }
+        Some(sphere)
+    }
+
+    /// The smallest sphere containing both spheres.
+    pub fn union(&self, other: &Sphere) -> Sphere {
+        let d = distance2(self.center, other.center).sqrt();
+        if d + other.radius <= self.radius {
+            return *self;
+        }
+        if d + self.radius <= other.radius {
+            return *other;
+        }
+        let radius = (d + self.radius + other.radius) / 2.0;
+        let shift = (radius - self.radius) / d;
+        Sphere {
+            center: [0, 1, 2].map(|i| self.center[i] + (other.center[i] - self.center[i]) * shift),
+            radius,
+        }
+    }
+
+    /// The sphere after transforming it by `matrix`, scaled by the largest
+    /// axis scale so it stays conservative.
+    pub fn transformed(&self, matrix: &Matrix) -> Sphere {
+        let scale = (0..3)
+            .map(|i| {
+                let axis = [matrix[i][0], matrix[i][1], matrix[i][2]];
+                dot(axis, axis).sqrt()
+            })
+            .fold(0.0f32, f32::max);
+        Sphere {
+            center: transform_point(&self.center, matrix),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct Bounds {
+    #[serde(rename = "box")]
+    pub aabb: Aabb,
+    pub sphere: Sphere,
+}
+
+impl Bounds {
+    pub fn from_points(points: &[[f32; 3]]) -> Option<Self> {
+        Some(Bounds {
+            aabb: Aabb::from_points(points)?,
+            sphere: Sphere::from_points(points)?,
+        })
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            aabb: self.aabb.union(&other.aabb),
+            sphere: self.sphere.union(&other.sphere),
+        }
+    }
+
+    pub fn transformed(&self, matrix: &Matrix) -> Bounds {
+        Bounds {
+            aabb: self.aabb.transformed(matrix),
+            sphere: self.sphere.transformed(matrix),
+        }
+    }
+}
+
+/// A node whose `BOUNDINGBOX` differs from the box around its geometry.
+#[derive(Serialize, Clone, Debug)]
+#[non_exhaustive]
+pub struct BoundsMismatch {
+    pub node: String,
+    pub file: Aabb,
+    pub computed: Option<Aabb>,
+}
+
+impl Object {
+    /// Bounds of the vertices in the object's own space.
+    pub fn bounds(&self) -> Option<Bounds> {
+        Bounds::from_points(&self.vertices)
+    }
+}
+
+impl Entity {
+    /// Bounds of every object placed by its node transforms.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let points: Vec<[f32; 3]> = self
+            .objects
+            .iter()
+            .zip(self.object_transforms())
+            .flat_map(|(object, transform)| {
+                object
+                    .vertices
+                    .iter()
+                    .map(move |v| transform_point(v, &transform))
+            })
+            .collect();
+        Bounds::from_points(&points)
+    }
+
+    /// Compares each node's `BOUNDINGBOX` with the box around the geometry
+    /// below it, both in the node's space. `tolerance` is relative to the
+    /// size of the box.
+    pub fn bounds_mismatches(&self, tolerance: f32) -> Vec<BoundsMismatch> {
+        let mut mismatches = Vec::new();
+        if let Some(root) = &self.root {
+            self.node_bounds(root, tolerance, &mut mismatches);
+        }
+        mismatches
+    }
+
+    fn node_bounds(
+        &self,
+        node: &SceneNode,
+        tolerance: f32,
+        mismatches: &mut Vec<BoundsMismatch>,
+    ) -> Option<Aabb> {
+        let own = node
+            .objects
+            .iter()
+            .filter_map(|&i| Aabb::from_points(&self.objects[i].vertices));
+        let children: Vec<Aabb> = node
+            .children
+            .iter()
+            .filter_map(|c| {
+                self.node_bounds(c, tolerance, mismatches)
+                    .map(|b| b.transformed(&c.transform))
+            })
+            .collect();
+        let computed = own.chain(children).reduce(|a, b| a.union(&b));
+
+        if let Some(file) = node.bounding_box {
+            if !computed.is_some_and(|c| c.approx_eq(&file, tolerance)) {
+                mismatches.push(BoundsMismatch {
+                    node: node.id.clone(),
+                    file,
+                    computed,
+                });
+            }
+        }
+        computed
+    }
+}
+
+impl Level {
+    /// Bounds of every placed instance.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let entities: Vec<Option<Bounds>> =
+            self.entities.iter().map(|(_, _, e)| e.bounds()).collect();
+        self.instances
+            .iter()
+            .filter_map(|i| entities[i.entity].map(|b| b.transformed(&i.transform)))
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+fn distance2(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let d = sub(a, b);
+    dot(d, d)
+}
@@ -0,0 +1,533 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    entity::Entity,
+    math::{flatten, transpose, Matrix},
+    object::Object,
+    scene::{NodeKind, SceneNode},
+};
+
+impl Entity {
+    pub fn to_collada(&self) -> String {
+        let mut dae = String::new();
+        dae.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        dae.push_str(
+            "<COLLADA xmlns=\"http://www.collada.org/2005/11/COLLADASchema\" version=\"1.4.1\">\n",
+        );
+        dae.push_str("  <asset>\n");
+        dae.push_str(&format!(
+            "    <contributor><authoring_tool>journey-model-parser {}</authoring_tool></contributor>\n",
+            env!("CARGO_PKG_VERSION")
+        ));
+        dae.push_str("    <unit name=\"meter\" meter=\"1\"/>\n");
+        dae.push_str(&format!(
+            "    <up_axis>{}_UP</up_axis>\n",
+            self.coordinate_system.up()
+        ));
+        dae.push_str("  </asset>\n");
+
+        let node_ids = NodeIds::new(self);
+        self.write_materials(&mut dae);
+
+        dae.push_str("  <library_geometries>\n");
+        for (i, object) in self.objects.iter().enumerate() {
+            write_geometry(&mut dae, i, object);
+        }
+        dae.push_str("  </library_geometries>\n");
+
+        if let Some(skin) = &self.skin {
+            dae.push_str("  <library_controllers>\n");
+            for (i, object) in self.objects.iter().enumerate() {
+                if object.is_skinned() {
+                    write_controller(
+                        &mut dae,
+                        i,
+                        object,
+                        &skin.joints,
+                        &skin.inverse_bind_matrices,
+                        &node_ids,
+                    );
+                }
+            }
+            dae.push_str("  </library_controllers>\n");
+        }
+
+        dae.push_str("  <library_visual_scenes>\n");
+        dae.push_str("    <visual_scene id=\"Scene\" name=\"Scene\">\n");
+        let mut placed = HashSet::new();
+        if let Some(root) = &self.root {
+            self.write_node(&mut dae, root, 3, &node_ids, &mut 0, &mut placed);
+        }
+        for (i, object) in self.objects.iter().enumerate() {
+            if !placed.contains(&i) {
+                dae.push_str(&format!(
+                    "      <node id=\"object{}\" name=\"{}\" type=\"NODE\">\n",
+                    i,
+                    escape(&object.name)
+                ));
+                self.write_instance(&mut dae, i, 4, &node_ids);
+                dae.push_str("      </node>\n");
+            }
+        }
+        dae.push_str("    </visual_scene>\n");
+        dae.push_str("  </library_visual_scenes>\n");
+
+        dae.push_str("  <scene>\n");
+        dae.push_str("    <instance_visual_scene url=\"#Scene\"/>\n");
+        dae.push_str("  </scene>\n");
+        dae.push_str("</COLLADA>\n");
+        dae
+    }
+
+    fn write_materials(&self, dae: &mut String) {
+        let mut textures: Vec<&str> = Vec::new();
+        for texture in self.materials.iter().filter_map(|m| m.texture.as_deref()) {
+            if !textures.contains(&texture) {
+                textures.push(texture);
+            }
+        }
+
+        if !textures.is_empty() {
+            dae.push_str("  <library_images>\n");
+            for texture in &textures {
+                dae.push_str(&format!(
+                    "    <image id=\"{}-image\" name=\"{}\"><init_from>{}.png</init_from></image>\n",
+                    sanitize(texture),
+                    escape(texture),
+                    escape(texture)
+                ));
+            }
+            dae.push_str("  </library_images>\n");
+        }
+
+        dae.push_str("  <library_effects>\n");
+        for material in &self.materials {
+            let id = sanitize(&material.name);
+            dae.push_str(&format!("    <effect id=\"{}-effect\">\n", id));
+            dae.push_str("      <profile_COMMON>\n");
+            let diffuse = if let Some(texture) = &material.texture {
+                let texture = sanitize(texture);
+                dae.push_str(&format!(
+                    "        <newparam sid=\"{0}-surface\"><surface type=\"2D\"><init_from>{0}-image</init_from></surface></newparam>\n",
+                    texture
+                ));
+                dae.push_str(&format!(
+                    "        <newparam sid=\"{0}-sampler\"><sampler2D><source>{0}-surface</source></sampler2D></newparam>\n",
+                    texture
+                ));
+                format!(
+                    "<texture texture=\"{}-sampler\" texcoord=\"UVMap\"/>",
+                    texture
+                )
+            } else {
+                "<color>0.8 0.8 0.8 1</color>".to_string()
+            };
+            dae.push_str("        <technique sid=\"common\">\n");
+            dae.push_str(&format!(
+                "          <lambert><diffuse>{}</diffuse></lambert>\n",
+                diffuse
+            ));
+            dae.push_str("        </technique>\n");
+            dae.push_str("      </profile_COMMON>\n");
+            dae.push_str("    </effect>\n");
+        }
+        dae.push_str("  </library_effects>\n");
+
+        dae.push_str("  <library_materials>\n");
+        for material in &self.materials {
+            let id = sanitize(&material.name);
+            dae.push_str(&format!(
+                "    <material id=\"{0}-material\" name=\"{1}\"><instance_effect url=\"#{0}-effect\"/></material>\n",
+                id,
+                escape(&material.name)
+            ));
+        }
+        dae.push_str("  </library_materials>\n");
+    }
+
+    fn write_node(
+        &self,
+        dae: &mut String,
+        node: &SceneNode,
+        depth: usize,
+        node_ids: &NodeIds,
+        next: &mut usize,
+        placed: &mut HashSet<usize>,
+    ) {
+        let indent = "  ".repeat(depth);
+        let node_type = if node.kind == NodeKind::Joint {
+            "JOINT"
+        } else {
+            "NODE"
+        };
+        let id = &node_ids.ids[*next];
+        *next += 1;
+        dae.push_str(&format!(
+            "{}<node id=\"{}\" name=\"{}\" sid=\"{}\" type=\"{}\">\n",
+            indent,
+            id,
+            escape(&node.name),
+            id,
+            node_type
+        ));
+        dae.push_str(&format!(
+            "{}  <matrix sid=\"transform\">{}</matrix>\n",
+            indent,
+            matrix_text(&node.transform)
+        ));
+        for &i in &node.objects {
+            self.write_instance(dae, i, depth + 1, node_ids);
+            placed.insert(i);
+        }
+        for child in &node.children {
+            self.write_node(dae, child, depth + 1, node_ids, next, placed);
+        }
+        dae.push_str(&format!("{}</node>\n", indent));
+    }
+
+    fn write_instance(&self, dae: &mut String, i: usize, depth: usize, node_ids: &NodeIds) {
+        let indent = "  ".repeat(depth);
+        let object = &self.objects[i];
+        let instances: String = material_symbols(object)
+            .into_iter()
+            .filter_map(|(symbol, material)| {
+                let material = material.filter(|m| self.material(m).is_some())?;
+                Some(format!(
+                    "<instance_material symbol=\"{}\" target=\"#{}-material\"><bind_vertex_input semantic=\"UVMap\" input_semantic=\"TEXCOORD\" input_set=\"0\"/></instance_material>",
+                    symbol,
+                    sanitize(material)
+                ))
+            })
+            .collect();
+        let bind_material = if instances.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<bind_material><technique_common>{}</technique_common></bind_material>",
+                instances
+            )
+        };
+
+        match &self.skin {
+            Some(skin) if object.is_skinned() => {
+                dae.push_str(&format!(
+                    "{}<instance_controller url=\"#mesh{}-skin\"><skeleton>#{}</skeleton>{}</instance_controller>\n",
+                    indent,
+                    i,
+                    node_ids.joint(skin.joints.first().map(String::as_str).unwrap_or_default()),
+                    bind_material
+                ));
+            }
+            _ => {
+                dae.push_str(&format!(
+                    "{}<instance_geometry url=\"#mesh{}\">{}</instance_geometry>\n",
+                    indent, i, bind_material
+                ));
+            }
+        }
+    }
+}
+
+/// XML ids of the scene nodes. PSSG ids may be missing or repeated, or only
+/// differ in characters [`sanitize`] replaces, so duplicates get a suffix.
+struct NodeIds {
+    /// The id of every node, in the order [`Entity::write_node`] visits them.
+    ids: Vec<String>,
+    /// The id of the first node with each PSSG id, for joint references.
+    by_pssg_id: HashMap<String, String>,
+}
+
+impl NodeIds {
+    fn new(entity: &Entity) -> Self {
+        let mut used: HashSet<String> = (0..entity.objects.len())
+            .flat_map(|i| [format!("object{}", i), format!("mesh{}", i)])
+            .chain(["Scene".to_string()])
+            .collect();
+        let mut node_ids = NodeIds {
+            ids: Vec::new(),
+            by_pssg_id: HashMap::new(),
+        };
+        if let Some(root) = &entity.root {
+            node_ids.add(root, &mut used);
+        }
+        node_ids
+    }
+
+    fn add(&mut self, node: &SceneNode, used: &mut HashSet<String>) {
+        let base = if node.id.is_empty() {
+            "node".to_string()
+        } else {
+            sanitize(&node.id)
+        };
+        let mut id = base.clone();
+        let mut n = 2;
+        while !used.insert(id.clone()) {
+            id = format!("{}_{}", base, n);
+            n += 1;
+        }
+        if !node.id.is_empty() {
+            self.by_pssg_id
+                .entry(node.id.clone())
+                .or_insert_with(|| id.clone());
+        }
+        self.ids.push(id);
+        for child in &node.children {
+            self.add(child, used);
+        }
+    }
+
+    /// The id of the node a skin joint names.
+    fn joint(&self, joint: &str) -> String {
+        self.by_pssg_id
+            .get(joint)
+            .cloned()
+            .unwrap_or_else(|| sanitize(joint))
+    }
+}
+
+fn write_geometry(dae: &mut String, i: usize, object: &Object) {
+    let id = format!("mesh{}", i);
+    dae.push_str(&format!(
+        "    <geometry id=\"{}\" name=\"{}\">\n",
+        id,
+        escape(&object.name)
+    ));
+    dae.push_str("      <mesh>\n");
+    write_source(
+        dae,
+        &format!("{}-positions", id),
+        &object.vertices.concat(),
+        &["X", "Y", "Z"],
+    );
+    let has_uvs = !object.uvs.is_empty() && object.uvs.len() == object.vertices.len();
+    if has_uvs {
+        write_source(
+            dae,
+            &format!("{}-uvs", id),
+            &object.uvs.concat(),
+            &["S", "T"],
+        );
+    }
+    // Per-vertex normals and colors share the vertex indices, so they go in
+    // <vertices> next to the positions.
+    let mut vertex_inputs = format!(
+        "<input semantic=\"POSITION\" source=\"#{}-positions\"/>",
+        id
+    );
+    if object.has_normals() {
+        write_source(
+            dae,
+            &format!("{}-normals", id),
+            &object.normals.concat(),
+            &["X", "Y", "Z"],
+        );
+        vertex_inputs.push_str(&format!(
+            "<input semantic=\"NORMAL\" source=\"#{}-normals\"/>",
+            id
+        ));
+    }
+    if object.has_colors() {
+        write_source(
+            dae,
+            &format!("{}-colors", id),
+            &object.colors.concat(),
+            &["R", "G", "B", "A"],
+        );
+        vertex_inputs.push_str(&format!(
+            "<input semantic=\"COLOR\" source=\"#{}-colors\"/>",
+            id
+        ));
+    }
+    dae.push_str(&format!(
+        "        <vertices id=\"{}-vertices\">{}</vertices>\n",
+        id, vertex_inputs
+    ));
+    let symbols = material_symbols(object);
+    for (range, (symbol, _)) in object.face_ranges().into_iter().zip(symbols) {
+        dae.push_str(&format!(
+            "        <triangles material=\"{}\" count=\"{}\">\n",
+            symbol,
+            range.len()
+        ));
+        dae.push_str(&format!(
+            "          <input semantic=\"VERTEX\" source=\"#{}-vertices\" offset=\"0\"/>\n",
+            id
+        ));
+        if has_uvs {
+            dae.push_str(&format!(
+                "          <input semantic=\"TEXCOORD\" source=\"#{}-uvs\" offset=\"0\" set=\"0\"/>\n",
+                id
+            ));
+        }
+        dae.push_str(&format!(
+            "          <p>{}</p>\n",
+            join(object.faces[range].concat())
+        ));
+        dae.push_str("        </triangles>\n");
+    }
+    dae.push_str("      </mesh>\n");
+    dae.push_str("    </geometry>\n");
+}
+
+/// The material symbol and material of every face range of `object`.
+fn material_symbols(object: &Object) -> Vec<(String, Option<&str>)> {
+    if object.submeshes.is_empty() {
+        return vec![("material".to_string(), object.material.as_deref())];
+    }
+    object
+        .submeshes
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (format!("material{}", i), s.material.as_deref()))
+        .collect()
+}
+
+fn write_controller(
+    dae: &mut String,
+    i: usize,
+    object: &Object,
+    joints: &[String],
+    inverse_bind_matrices: &[Matrix],
+    node_ids: &NodeIds,
+) {
+    let id = format!("mesh{}-skin", i);
+    dae.push_str(&format!("    <controller id=\"{}\">\n", id));
+    dae.push_str(&format!("      <skin source=\"#mesh{}\">\n", i));
+    dae.push_str(&format!(
+        "        <bind_shape_matrix>{}</bind_shape_matrix>\n",
+        matrix_text(&crate::math::IDENTITY)
+    ));
+
+    let names: Vec<String> = joints.iter().map(|j| node_ids.joint(j)).collect();
+    dae.push_str(&format!("        <source id=\"{}-joints\">\n", id));
+    dae.push_str(&format!(
+        "          <Name_array id=\"{}-joints-array\" count=\"{}\">{}</Name_array>\n",
+        id,
+        names.len(),
+        names.join(" ")
+    ));
+    dae.push_str(&format!(
+        "          <technique_common><accessor source=\"#{}-joints-array\" count=\"{}\" stride=\"1\"><param name=\"JOINT\" type=\"name\"/></accessor></technique_common>\n",
+        id,
+        names.len()
+    ));
+    dae.push_str("        </source>\n");
+
+    let bind_poses: Vec<f32> = inverse_bind_matrices
+        .iter()
+        .flat_map(|m| flatten(&transpose(m)))
+        .collect();
+    dae.push_str(&format!("        <source id=\"{}-bind_poses\">\n", id));
+    dae.push_str(&format!(
+        "          <float_array id=\"{}-bind_poses-array\" count=\"{}\">{}</float_array>\n",
+        id,
+        bind_poses.len(),
+        join(bind_poses)
+    ));
+    dae.push_str(&format!(
+        "          <technique_common><accessor source=\"#{}-bind_poses-array\" count=\"{}\" stride=\"16\"><param name=\"TRANSFORM\" type=\"float4x4\"/></accessor></technique_common>\n",
+        id,
+        inverse_bind_matrices.len()
+    ));
+    dae.push_str("        </source>\n");
+
+    let mut weights = Vec::new();
+    let mut vcount = Vec::new();
+    let mut v = Vec::new();
+    for (joint, weight) in object.joints.iter().zip(&object.weights) {
+        let mut count = 0;
+        for (j, w) in joint.iter().zip(weight) {
+            if *w > 0.0 {
+                v.push(*j as usize);
+                v.push(weights.len());
+                weights.push(*w);
+                count += 1;
+            }
+        }
+        vcount.push(count);
+    }
+    write_source(dae, &format!("{}-weights", id), &weights, &["WEIGHT"]);
+
+    dae.push_str(&format!(
+        "        <joints><input semantic=\"JOINT\" source=\"#{0}-joints\"/><input semantic=\"INV_BIND_MATRIX\" source=\"#{0}-bind_poses\"/></joints>\n",
+        id
+    ));
+    dae.push_str(&format!(
+        "        <vertex_weights count=\"{}\">\n",
+        vcount.len()
+    ));
+    dae.push_str(&format!(
+        "          <input semantic=\"JOINT\" source=\"#{}-joints\" offset=\"0\"/>\n",
+        id
+    ));
+    dae.push_str(&format!(
+        "          <input semantic=\"WEIGHT\" source=\"#{}-weights\" offset=\"1\"/>\n",
+        id
+    ));
+    dae.push_str(&format!("          <vcount>{}</vcount>\n", join(vcount)));
+    dae.push_str(&format!("          <v>{}</v>\n", join(v)));
+    dae.push_str("        </vertex_weights>\n");
+    dae.push_str("      </skin>\n");
+    dae.push_str("    </controller>\n");
+}
+
+fn write_source(dae: &mut String, id: &str, data: &[f32], params: &[&str]) {
+    dae.push_str(&format!("        <source id=\"{}\">\n", id));
+    dae.push_str(&format!(
+        "          <float_array id=\"{}-array\" count=\"{}\">{}</float_array>\n",
+        id,
+        data.len(),
+        join(data.iter())
+    ));
+    let stride = params.len();
+    let params: String = params
+        .iter()
+        .map(|p| format!("<param name=\"{}\" type=\"float\"/>", p))
+        .collect();
+    dae.push_str(&format!(
+        "          <technique_common><accessor source=\"#{}-array\" count=\"{}\" stride=\"{}\">{}</accessor></technique_common>\n",
+        id,
+        data.len() / stride,
+        stride,
+        params
+    ));
+    dae.push_str("        </source>\n");
+}
+
+fn matrix_text(m: &Matrix) -> String {
+    join(flatten(&transpose(m)))
+}
+
+fn join<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    values
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Turns an arbitrary PSSG id into a valid XML id.
+fn sanitize(id: &str) -> String {
+    let id: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        id
+    } else {
+        format!("_{}", id)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{
+    entity::Entity,
+    error::ParseError,
+    level::Level,
+    math::{multiply, transform_point, Matrix, IDENTITY},
+    scene::SceneNode,
+};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+impl FromStr for UpAxis {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "y" => Ok(UpAxis::Y),
+            "z" => Ok(UpAxis::Z),
+            _ => Err(ParseError::new(&format!("Unknown up axis {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for UpAxis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UpAxis::Y => write!(f, "Y"),
+            UpAxis::Z => write!(f, "Z"),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Handedness {
+    Right,
+    Left,
+}
+
+/// Axis convention and unit scale of geometry and transforms.
+///
+/// The game uses Y-up, right-handed coordinates ([`CoordinateSystem::GAME`]).
+/// Z-up maps the game's Z, X and Y axes to X, Y and Z, which is the swizzle
+/// the Blender importer applies to level transforms. Left-handed systems
+/// mirror the forward axis (Z for Y-up, Y for Z-up), and `scale` multiplies
+/// positions and translations.
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct CoordinateSystem {
+    up: UpAxis,
+    handedness: Handedness,
+    scale: f32,
+}
+
+impl CoordinateSystem {
+    /// Fails unless `scale` is finite and positive.
+    pub fn new(up: UpAxis, handedness: Handedness, scale: f32) -> Result<Self, ParseError> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(ParseError::new(&format!(
+                "Scale must be a positive number, got {}",
+                scale
+            )));
+        }
+        Ok(Self {
+            up,
+            handedness,
+            scale,
+        })
+    }
+
+    pub fn up(&self) -> UpAxis {
+        self.up
+    }
+
+    pub fn handedness(&self) -> Handedness {
+        self.handedness
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub const GAME: Self = Self {
+        up: UpAxis::Y,
+        handedness: Handedness::Right,
+        scale: 1.0,
+    };
+
+    pub const BLENDER: Self = Self {
+        up: UpAxis::Z,
+        handedness: Handedness::Right,
+        scale: 1.0,
+    };
+
+    /// Matrix taking game-space points to this system.
+    pub fn from_game(&self) -> Matrix {
+        let mut axes = match self.up {
+            UpAxis::Y => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            UpAxis::Z => [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        };
+        if self.handedness == Handedness::Left {
+            let forward = match self.up {
+                UpAxis::Y => 2,
+                UpAxis::Z => 1,
+            };
+            axes[forward] = axes[forward].map(|v| -v);
+        }
+
+        // Axis i of this system is column i of the matrix (row-vector convention).
+        let mut matrix = IDENTITY;
+        for (i, axis) in axes.iter().enumerate() {
+            for (j, value) in axis.iter().enumerate() {
+                matrix[j][i] = value * self.scale;
+            }
+        }
+        matrix
+    }
+
+    /// Matrix taking points in this system back to game space.
+    pub fn to_game(&self) -> Matrix {
+        let from_game = self.from_game();
+        let scale = self.scale * self.scale;
+        let mut matrix = IDENTITY;
+        for (i, row) in matrix.iter_mut().take(3).enumerate() {
+            for (j, value) in row.iter_mut().take(3).enumerate() {
+                *value = from_game[j][i] / scale;
+            }
+        }
+        matrix
+    }
+
+    /// Matrix taking points in this system to `other`.
+    pub fn conversion_to(&self, other: &CoordinateSystem) -> Matrix {
+        multiply(&self.to_game(), &other.from_game())
+    }
+}
+
+impl Default for CoordinateSystem {
+    fn default() -> Self {
+        Self::GAME
+    }
+}
+
+/// Changes the basis of a transform: `to` takes points into the new system
+/// and `from` takes them back.
+fn convert_matrix(matrix: &Matrix, to: &Matrix, from: &Matrix) -> Matrix {
+    multiply(&multiply(from, matrix), to)
+}
+
+fn convert_nodes(node: &mut SceneNode, to: &Matrix, from: &Matrix) {
+    node.transform = convert_matrix(&node.transform, to, from);
+    node.bounding_box = node.bounding_box.map(|b| b.transformed(to));
+    for child in &mut node.children {
+        convert_nodes(child, to, from);
+    }
+}
+
+impl Entity {
+    /// Converts geometry, node transforms and skin matrices into `target`.
+    pub fn convert(&mut self, target: &CoordinateSystem) {
+        if self.coordinate_system == *target {
+            return;
+        }
+        let to = self.coordinate_system.conversion_to(target);
+        let from = target.conversion_to(&self.coordinate_system);
+
+        for object in &mut self.objects {
+            object.transform(&to);
+        }
+        self.translation = transform_point(&self.translation, &to);
+        if let Some(root) = &mut self.root {
+            convert_nodes(root, &to, &from);
+        }
+        if let Some(skin) = &mut self.skin {
+            for matrix in &mut skin.inverse_bind_matrices {
+                *matrix = convert_matrix(matrix, &to, &from);
+            }
+        }
+        self.coordinate_system = *target;
+    }
+}
+
+impl Level {
+    /// Converts every mesh and instance transform into `target`.
+    pub fn convert(&mut self, target: &CoordinateSystem) {
+        if self.coordinate_system == *target {
+            return;
+        }
+        let to = self.coordinate_system.conversion_to(target);
+        let from = target.conversion_to(&self.coordinate_system);
+
+        for instance in &mut self.instances {
+            instance.transform = convert_matrix(&instance.transform, &to, &from);
+        }
+        for (_, _, entity) in &mut self.entities {
+            entity.convert(target);
+        }
+        self.coordinate_system = *target;
+    }
+}
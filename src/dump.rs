@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{
+    error::ParseError,
+    parse::{read, stream_format},
+    structure::{load_xml_file, Library, TypeInfo},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum DumpFormat {
+    Json,
+    Yaml,
+}
+
+#[derive(Debug)]
+pub struct DumpOptions {
+    pub format: DumpFormat,
+    /// Maximum number of whitespace-separated values of `DATABLOCKDATA` /
+    /// `INDEXSOURCEDATA` kept per payload; for hex payloads a value is a byte.
+    pub max_payload: Option<usize>,
+    /// Number of decoded values to include per data block and index source.
+    pub preview: Option<usize>,
+}
+
+impl Default for DumpOptions {
+    fn default() -> Self {
+        Self {
+            format: DumpFormat::Json,
+            max_payload: Some(64),
+            preview: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DatabaseDump {
+    file: String,
+    #[serde(rename = "TYPEINFO")]
+    type_info: Vec<TypeInfo>,
+    #[serde(rename = "LIBRARY")]
+    libraries: Vec<Library>,
+    /// Decoded values by node type (`DATABLOCK` or `RENDERINDEXSOURCE`) and
+    /// id, as the two may share ids.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    previews: BTreeMap<&'static str, BTreeMap<String, Vec<f32>>>,
+    /// Why the preview of a data block or index source was skipped, keyed
+    /// like `previews`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    preview_errors: BTreeMap<&'static str, BTreeMap<String, String>>,
+}
+
+pub fn dump(xml_file: &Path, options: &DumpOptions) -> Result<String, ParseError> {
+    let pssg_file = load_xml_file(xml_file)?;
+    let mut libraries = pssg_file.database.libraries;
+    let mut previews: BTreeMap<_, BTreeMap<_, _>> = BTreeMap::new();
+    let mut preview_errors: BTreeMap<_, BTreeMap<_, _>> = BTreeMap::new();
+    let mut preview = |kind: &'static str,
+                       id: &str,
+                       values: Result<Vec<f32>, ParseError>,
+                       count: usize| match values {
+        Ok(values) => {
+            previews
+                .entry(kind)
+                .or_default()
+                .insert(id.to_string(), values.into_iter().take(count).collect());
+        }
+        Err(e) => {
+            warn!("Skipping preview of {} {}: {}", kind, id, e);
+            preview_errors
+                .entry(kind)
+                .or_default()
+                .insert(id.to_string(), e.to_string());
+        }
+    };
+
+    for library in &mut libraries {
+        for block in &mut library.data_blocks {
+            if let Some(data) = &mut block.data {
+                if let Some(count) = options.preview {
+                    let values = stream_format(&block.stream.data_type)
+                        .and_then(|(data_type, _)| read(&data.text, data_type));
+                    preview("DATABLOCK", &block.id, values, count);
+                }
+                if let Some(max) = options.max_payload {
+                    data.text = truncate_payload(&data.text, max);
+                }
+            }
+        }
+
+        for source in library
+            .segment_sets
+            .iter_mut()
+            .flat_map(|s| s.render_data_sources.iter_mut())
+        {
+            let index_source = &mut source.index_source;
+            if let Some(data) = &mut index_source.index_data {
+                if let (Some(count), Some(id)) = (options.preview, &index_source.id) {
+                    let values = read::<u32>(&data.text, &index_source.data_type)
+                        .map(|values| values.into_iter().map(|v| v as f32).collect());
+                    preview("RENDERINDEXSOURCE", id, values, count);
+                }
+                if let Some(max) = options.max_payload {
+                    data.text = truncate_payload(&data.text, max);
+                }
+            }
+        }
+    }
+
+    let dump = DatabaseDump {
+        file: xml_file.to_string_lossy().to_string(),
+        type_info: pssg_file.database.type_info,
+        libraries,
+        previews,
+        preview_errors,
+    };
+
+    match options.format {
+        DumpFormat::Json => serde_json::to_string_pretty(&dump)
+            .map_err(|e| ParseError::new(&format!("Failed to serialize JSON: {e}"))),
+        DumpFormat::Yaml => serde_yaml::to_string(&dump)
+            .map_err(|e| ParseError::new(&format!("Failed to serialize YAML: {e}"))),
+    }
+}
+
+fn truncate_payload(text: &str, max: usize) -> String {
+    let total = text.split_whitespace().count();
+    if total <= max {
+        return text.trim().to_string();
+    }
+    let kept: Vec<&str> = text.split_whitespace().take(max).collect();
+    format!("{} ... ({} of {} values)", kept.join(" "), max, total)
+}
@@ -1,37 +1,198 @@
 use crate::{
+    coords::CoordinateSystem,
     error::ParseError,
+    math::{multiply, Matrix, IDENTITY},
     object::Object,
-    structure::{DataBlock, RenderDataSource, RootNode},
+    parse::ParseOptions,
+    scene::{Material, SceneNode, Skin},
+    structure::{self, Node, PssgContents, PssgFile, RootNode, TypeCount},
 };
 
 type Translation = [f32; 3];
 
+#[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct Entity {
     pub objects: Vec<Object>,
     pub translation: Translation,
+    pub root: Option<SceneNode>,
+    pub materials: Vec<Material>,
+    pub skin: Option<Skin>,
+    pub type_counts: Vec<TypeCount>,
+    pub warnings: Vec<String>,
+    /// The space geometry and transforms are in; see [`Entity::convert`].
+    pub coordinate_system: CoordinateSystem,
 }
 
 impl Entity {
-    pub fn new(
-        data_blocks: Vec<DataBlock>,
-        render_data_sources: Vec<RenderDataSource>,
-        root_node: Option<RootNode>,
+    /// Builds the entity from a loaded PSSG file; [`crate::parse`] loads and
+    /// builds in one step.
+    pub fn from_pssg(pssg_file: PssgFile) -> Result<Self, ParseError> {
+        let options = ParseOptions::default();
+        Self::from_contents(structure::contents(pssg_file, &options)?, &options)
+    }
+
+    /// An entity of `objects` in game space, with no scene hierarchy or
+    /// materials; [`crate::export`] writes each object unplaced.
+    pub fn new(objects: Vec<Object>) -> Self {
+        Self {
+            objects,
+            translation: [0.0; 3],
+            root: None,
+            materials: Vec::new(),
+            skin: None,
+            type_counts: Vec::new(),
+            warnings: Vec::new(),
+            coordinate_system: CoordinateSystem::GAME,
+        }
+    }
+
+    pub(crate) fn from_contents(
+        contents: PssgContents,
+        options: &ParseOptions,
     ) -> Result<Self, ParseError> {
+        let mut warnings = contents.warnings;
         let mut objects = Vec::new();
-        for source in render_data_sources {
-            objects.push(Object::new(&data_blocks, source)?);
+        for source in contents.render_data_sources {
+            objects.push(Object::from_source(
+                &contents.data_blocks,
+                source,
+                options,
+                &mut warnings,
+            )?);
         }
 
-        let translation = Self::get_translation(&root_node)?;
+        let translation = Self::get_translation(&contents.root_node, options, &mut warnings)?;
 
-        Ok(Self {
+        let mut materials = Vec::new();
+        let root_node = contents.root_node.as_ref().map(|r| &r.node);
+        if options.loads_materials() {
+            materials = contents
+                .shader_instances
+                .iter()
+                .map(Material::from_shader)
+                .collect();
+            if let Some(node) = root_node {
+                Self::assign_materials(node, &mut objects);
+            }
+        }
+        let root = root_node.map(|n| SceneNode::from_node(n, &objects));
+        let skin = root_node
+            .filter(|_| options.loads_skeletons())
+            .and_then(|n| Skin::find(n, &contents.skeletons));
+
+        let entity = Self {
             objects,
             translation,
-        })
+            root,
+            materials,
+            skin,
+            type_counts: contents.type_counts,
+            warnings,
+            coordinate_system: CoordinateSystem::GAME,
+        };
+        if options.is_strict() {
+            let issues = entity.validate();
+            if !issues.is_empty() {
+                return Err(ParseError::new(&issues.join("; ")));
+            }
+        }
+        Ok(entity)
     }
 
-    fn get_translation(root_node: &Option<RootNode>) -> Result<Translation, ParseError> {
+    /// Checks the decoded geometry for inconsistencies, returning one message per problem.
+    pub fn validate(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if self.objects.is_empty() {
+            issues.push("No objects found".to_string());
+        }
+        for object in &self.objects {
+            let vertex_count = object.vertices.len();
+            if vertex_count == 0 {
+                issues.push(format!("Object {} has no vertices", object.name));
+            }
+            if object.faces.is_empty() {
+                issues.push(format!("Object {} has no faces", object.name));
+            }
+            if !object.uvs.is_empty() && object.uvs.len() != vertex_count {
+                issues.push(format!(
+                    "Object {} has {} UVs for {} vertices",
+                    object.name,
+                    object.uvs.len(),
+                    vertex_count
+                ));
+            }
+            if !object.normals.is_empty() && object.normals.len() != vertex_count {
+                issues.push(format!(
+                    "Object {} has {} normals for {} vertices",
+                    object.name,
+                    object.normals.len(),
+                    vertex_count
+                ));
+            }
+            let out_of_range = object
+                .faces
+                .iter()
+                .flatten()
+                .filter(|i| **i as usize >= vertex_count)
+                .count();
+            if out_of_range > 0 {
+                issues.push(format!(
+                    "Object {} has {} face indices out of range",
+                    object.name, out_of_range
+                ));
+            }
+        }
+        issues
+    }
+
+    pub fn material(&self, name: &str) -> Option<&Material> {
+        self.materials.iter().find(|m| m.name == name)
+    }
+
+    pub fn object(&self, name: &str) -> Option<&Object> {
+        self.objects.iter().find(|o| o.name == name)
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.objects.iter().map(Object::vertex_count).sum()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.objects.iter().map(Object::face_count).sum()
+    }
+
+    fn assign_materials(node: &Node, objects: &mut [Object]) {
+        for instance in &node.stream_instances {
+            let (Some(indices), Some(shader)) = (&instance.indices, &instance.shader) else {
+                continue;
+            };
+            let indices = indices.replace("#", "");
+            if let Some(object) = objects.iter_mut().find(|o| o.name == indices) {
+                object.material = Some(shader.replace("#", ""));
+            }
+        }
+        for child in &node.children {
+            Self::assign_materials(child, objects);
+        }
+    }
+
+    /// The translation of the first `RENDERNODE`, or of the one `options`
+    /// names.
+    fn get_translation(
+        root_node: &Option<RootNode>,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Translation, ParseError> {
         if let Some(root_node) = root_node {
+            if let Some(id) = options.translation_node() {
+                match Self::find_render_node(&root_node.node.children, id, &IDENTITY) {
+                    Some(transform) => {
+                        return Ok([transform[3][0], transform[3][1], transform[3][2]])
+                    }
+                    None => options.warn(warnings, format!("RENDERNODE {} not found", id))?,
+                }
+            }
             if let Some(render_node) = root_node.render_nodes.first() {
                 let transform = &render_node.transform;
                 let translation = [transform[3][0], transform[3][1], transform[3][2]];
@@ -40,4 +201,17 @@ impl Entity {
         }
         Ok([0.0, 0.0, 0.0])
     }
+
+    /// The transform of the `RENDERNODE` with `id` below `nodes`, relative to
+    /// the root node.
+    fn find_render_node(nodes: &[Node], id: &str, parent: &Matrix) -> Option<Matrix> {
+        nodes.iter().find_map(|node| {
+            let local = node.transform.as_ref().map_or(IDENTITY, |t| t.matrix());
+            let transform = multiply(&local, parent);
+            if node.node_type == "RENDERNODE" && node.id.as_deref() == Some(id) {
+                return Some(transform);
+            }
+            Self::find_render_node(&node.children, id, &transform)
+        })
+    }
 }
@@ -9,6 +9,10 @@ impl ParseError {
             msg: msg.to_string(),
         }
     }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
 }
 
 impl std::fmt::Display for ParseError {
@@ -16,3 +20,5 @@ impl std::fmt::Display for ParseError {
         write!(f, "{}", self.msg)
     }
 }
+
+impl std::error::Error for ParseError {}
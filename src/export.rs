@@ -0,0 +1,331 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{
+    atlas::Atlas, coords::CoordinateSystem, entity::Entity, error::ParseError, gltf,
+    merge::SubmeshMode, normals::NormalOptions, object::Object, optimize::OptimizeReport,
+    simplify::SimplifyOptions, texture::Image, topology::RepairOptions, transform::TransformMode,
+    weld::WeldOptions,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum Format {
+    Obj,
+    Collada,
+    Usda,
+    Gltf,
+    Glb,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Obj => "obj",
+            Format::Collada => "dae",
+            Format::Usda => "usda",
+            Format::Gltf => "gltf",
+            Format::Glb => "glb",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "obj" => Ok(Format::Obj),
+            "dae" | "collada" => Ok(Format::Collada),
+            "usda" | "usd" => Ok(Format::Usda),
+            "gltf" => Ok(Format::Gltf),
+            "glb" => Ok(Format::Glb),
+            _ => Err(ParseError::new(&format!("Unknown format {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportOptions {
+    pub format: Format,
+    /// Directory to write to; defaults to the directory of the input file.
+    pub out_dir: Option<PathBuf>,
+    /// Merge all objects into one mesh instead of writing them separately.
+    pub merge: bool,
+    /// Face ranges kept as submeshes when merging.
+    pub submeshes: SubmeshMode,
+    /// Weld duplicate vertices before writing.
+    pub weld: Option<WeldOptions>,
+    /// Repair the mesh topology after welding.
+    pub repair: Option<RepairOptions>,
+    /// When and how to generate normals.
+    pub normals: NormalOptions,
+    /// Atlas texture (PNG) to cut into one texture per object, remapping
+    /// the UVs into the crops.
+    pub split_atlas: Option<PathBuf>,
+    /// Atlas metadata to map the UVs of standalone textures into.
+    pub join_atlas: Option<PathBuf>,
+    /// Convert geometry and transforms into this system before writing.
+    pub coordinate_system: Option<CoordinateSystem>,
+    /// Write node transforms as matrices or bake them into the geometry.
+    pub transforms: TransformMode,
+    /// Reorder faces and vertices for the vertex cache and fetch locality.
+    pub optimize: bool,
+    /// Simplified levels of detail to write after the full model.
+    pub lods: Vec<SimplifyOptions>,
+    /// Write LODs as `name_LOD<n>` files even for glTF, which otherwise
+    /// stores them in the same file through `MSFT_lod`.
+    pub separate_lods: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Obj,
+            out_dir: None,
+            merge: false,
+            submeshes: SubmeshMode::Object,
+            weld: None,
+            repair: None,
+            normals: NormalOptions::default(),
+            split_atlas: None,
+            join_atlas: None,
+            coordinate_system: None,
+            transforms: TransformMode::Keep,
+            optimize: false,
+            lods: Vec::new(),
+            separate_lods: false,
+        }
+    }
+}
+
+/// What [`export`] wrote.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct ExportReport {
+    pub written: Vec<PathBuf>,
+    /// Cache statistics of every object [`ExportOptions::optimize`]
+    /// reordered, labelled `<model>/<object>` with `<name>_LOD<n>` as the
+    /// model of a LOD.
+    pub optimized: Vec<(String, OptimizeReport)>,
+}
+
+/// Writes `entity` as `name.<ext>` (or `name-<n>.obj` per object) into
+/// `out_dir`, followed by its LODs.
+pub fn export(
+    entity: &Entity,
+    name: &str,
+    out_dir: &Path,
+    options: &ExportOptions,
+) -> Result<ExportReport, ParseError> {
+    fs::create_dir_all(out_dir)
+        .map_err(|e| ParseError::new(&format!("Failed to create {:?}: {e}", out_dir)))?;
+
+    let mut written = Vec::new();
+    let mut optimized = Vec::new();
+    let converted;
+    let convert = options
+        .coordinate_system
+        .is_some_and(|system| system != entity.coordinate_system);
+    let merge = options.merge;
+    let modify = merge
+        || options.optimize
+        || options.weld.is_some()
+        || options.repair.is_some()
+        || options.split_atlas.is_some()
+        || options.join_atlas.is_some()
+        || options.transforms == TransformMode::Bake
+        || entity.needs_normals(&options.normals);
+    let entity = if convert || modify {
+        let mut copy = entity.clone();
+        copy.apply_transform_mode(options.transforms);
+        if merge {
+            copy.merge(options.submeshes);
+        }
+        if let Some(weld) = &options.weld {
+            copy.weld(weld);
+        }
+        if let Some(repair) = &options.repair {
+            copy.repair(repair);
+        }
+        copy.apply_normals(&options.normals);
+        if let Some(path) = &options.split_atlas {
+            split_atlas(&mut copy, path, name, out_dir, &mut written)?;
+        }
+        if let Some(path) = &options.join_atlas {
+            copy.join_atlas(&Atlas::load(path)?);
+        }
+        if let Some(system) = &options.coordinate_system {
+            copy.convert(system);
+        }
+        if options.optimize {
+            let reports = copy.optimize();
+            optimized.extend(labelled(name, &copy, reports));
+        }
+        converted = copy;
+        &converted
+    } else {
+        entity
+    };
+
+    let mut lods: Vec<Entity> = options.lods.iter().map(|o| entity.simplified(o)).collect();
+    if options.optimize {
+        for (i, lod) in lods.iter_mut().enumerate() {
+            let reports = lod.optimize();
+            optimized.extend(labelled(&format!("{}_LOD{}", name, i + 1), lod, reports));
+        }
+    }
+    if matches!(options.format, Format::Gltf | Format::Glb) && !options.separate_lods {
+        let path = out_dir.join(format!("{}.{}", name, options.format.extension()));
+        written.extend(gltf::save(&entity.to_gltf_with_lods(name, &lods), &path)?);
+        return Ok(ExportReport { written, optimized });
+    }
+
+    write(entity, name, out_dir, options.format, &mut written)?;
+    for (i, lod) in lods.iter().enumerate() {
+        let name = format!("{}_LOD{}", name, i + 1);
+        write(lod, &name, out_dir, options.format, &mut written)?;
+    }
+    Ok(ExportReport { written, optimized })
+}
+
+/// Pairs the reports of [`Entity::optimize`] with `<model>/<object>` labels.
+fn labelled(
+    model: &str,
+    entity: &Entity,
+    reports: Vec<OptimizeReport>,
+) -> Vec<(String, OptimizeReport)> {
+    entity
+        .objects
+        .iter()
+        .zip(reports)
+        .map(|(object, report)| (format!("{}/{}", model, object.name), report))
+        .collect()
+}
+
+/// Cuts the atlas at `path` into per-object textures written next to the
+/// model, with metadata locating them in the atlas.
+fn split_atlas(
+    entity: &mut Entity,
+    path: &Path,
+    name: &str,
+    out_dir: &Path,
+    written: &mut Vec<PathBuf>,
+) -> Result<(), ParseError> {
+    let atlas = Image::load_png(path)?;
+    let atlas_name = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let metadata = Atlas::find(path)?;
+    let (crops, metadata) = entity.split_atlas(&atlas, &atlas_name, metadata.as_ref(), name);
+    for (texture, image) in crops {
+        let path = out_dir.join(format!("{}.png", texture));
+        image.save_png(&path)?;
+        written.push(path);
+    }
+    let path = out_dir.join(format!("{}.atlas.json", name));
+    metadata.save(&path)?;
+    written.push(path);
+    Ok(())
+}
+
+/// The materials the submeshes use, with their textures as `<texture>.png`
+/// like the COLLADA export; `None` if no submesh has a material.
+fn mtl(entity: &Entity) -> Option<String> {
+    let mut names: Vec<&str> = Vec::new();
+    for submesh in entity.objects.iter().flat_map(|o| &o.submeshes) {
+        if let Some(material) = submesh.material.as_deref() {
+            if !names.contains(&material) {
+                names.push(material);
+            }
+        }
+    }
+    if names.is_empty() {
+        return None;
+    }
+
+    let mut mtl = String::new();
+    for name in names {
+        mtl.push_str(&format!("newmtl {}\n", name));
+        let texture = entity
+            .materials
+            .iter()
+            .find(|m| m.name == name)
+            .and_then(|m| m.texture.as_ref());
+        if let Some(texture) = texture {
+            mtl.push_str(&format!("map_Kd {}.png\n", texture));
+        }
+        mtl.push('\n');
+    }
+    Some(mtl)
+}
+
+fn write(
+    entity: &Entity,
+    name: &str,
+    out_dir: &Path,
+    format: Format,
+    written: &mut Vec<PathBuf>,
+) -> Result<(), ParseError> {
+    match format {
+        Format::Obj => {
+            let mtllib = match mtl(entity) {
+                Some(mtl) => {
+                    let path = out_dir.join(format!("{}.mtl", name));
+                    write_file(&path, &mtl)?;
+                    written.push(path);
+                    Some(format!("{}.mtl", name))
+                }
+                None => None,
+            };
+            let obj = |object: &Object| match &mtllib {
+                Some(mtllib) => object.to_obj_with_mtllib(mtllib),
+                None => object.to_obj(),
+            };
+            if entity.objects.len() > 1 {
+                for (i, object) in entity.objects.iter().enumerate() {
+                    let path = out_dir.join(format!("{}-{}.obj", name, i + 1));
+                    write_file(&path, &obj(object))?;
+                    written.push(path);
+                }
+            } else {
+                let path = out_dir.join(format!("{}.obj", name));
+                write_file(&path, &entity.objects.first().map(obj).unwrap_or_default())?;
+                written.push(path);
+            }
+        }
+        Format::Collada => {
+            let path = out_dir.join(format!("{}.dae", name));
+            write_file(&path, &entity.to_collada())?;
+            written.push(path);
+        }
+        Format::Usda => {
+            let path = out_dir.join(format!("{}.usda", name));
+            write_file(&path, &entity.to_usda())?;
+            written.push(path);
+        }
+        Format::Gltf | Format::Glb => {
+            let path = out_dir.join(format!("{}.{}", name, format.extension()));
+            written.extend(gltf::save(&entity.to_gltf(name), &path)?);
+        }
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), ParseError> {
+    let mut file = File::create(path)
+        .map_err(|e| ParseError::new(&format!("Failed to create file {:?}: {e}", path)))?;
+    file.write_all(contents.as_bytes())
+        .map_err(|e| ParseError::new(&format!("Failed to write file {:?}: {e}", path)))
+}
@@ -5,13 +5,23 @@ use std::sync::Once;
 
 use tracing::info;
 
+use crate::assets::AssetIndex;
+use crate::coords::{CoordinateSystem, Handedness, UpAxis};
 use crate::entity::Entity;
+use crate::error::ParseError;
+use crate::level::{Level, LevelOptions};
+use crate::math::flatten;
+use crate::merge::SubmeshMode;
+use crate::normals::{NormalGeneration, NormalMode, NormalOptions, NormalWeighting};
 use crate::parse;
 
 static mut VERSION: *const c_char = std::ptr::null();
 static INIT: Once = Once::new();
 
+/// Only used through the C ABI.
+#[allow(unnameable_types)]
 #[repr(C)]
+#[derive(Debug)]
 pub struct ParsedModelData {
     object_count: usize,
     vertices_ptr: *const c_float,
@@ -21,6 +31,14 @@ pub struct ParsedModelData {
     uvs_len_ptr: *const usize,
     faces_len_ptr: *const usize,
     translation_ptr: *const c_float,
+    /// 16 floats per object: its world transform in PSSG layout.
+    transforms_ptr: *const c_float,
+    normals_ptr: *const c_float,
+    normals_len_ptr: *const usize,
+    /// 10 floats per object and then for the whole entity: box min, box
+    /// max, sphere center and radius. Objects are in their own space, the
+    /// entity in world space; empty objects are all zeros.
+    bounds_ptr: *const c_float,
     vertices: Vec<f32>,
     uvs: Vec<f32>,
     faces: Vec<u32>,
@@ -28,30 +46,112 @@ pub struct ParsedModelData {
     uvs_len: Vec<usize>,
     faces_len: Vec<usize>,
     translation: Vec<f32>,
+    transforms: Vec<f32>,
+    normals: Vec<f32>,
+    normals_len: Vec<usize>,
+    bounds: Vec<f32>,
+}
+
+/// Options for `ffi_parse_with`. `up_axis` is 0 for Y-up (the game's) and
+/// 1 for Z-up; `bake_transforms` moves every object into world space and
+/// `merge` joins them into one object (grouped by material). `normals` is
+/// 0 to generate normals only where missing, 1 always and 2 never;
+/// `crease_angle` is in degrees.
+#[repr(C)]
+pub struct FfiOptions {
+    up_axis: c_uint,
+    left_handed: bool,
+    scale: c_float,
+    bake_transforms: bool,
+    merge: bool,
+    normals: c_uint,
+    flat_normals: bool,
+    crease_angle: c_float,
+}
+
+impl FfiOptions {
+    fn coordinate_system(&self) -> Result<CoordinateSystem, ParseError> {
+        CoordinateSystem::new(
+            if self.up_axis == 1 {
+                UpAxis::Z
+            } else {
+                UpAxis::Y
+            },
+            if self.left_handed {
+                Handedness::Left
+            } else {
+                Handedness::Right
+            },
+            self.scale,
+        )
+    }
+
+    fn normal_options(&self) -> NormalOptions {
+        NormalOptions {
+            generate: match self.normals {
+                1 => NormalGeneration::Always,
+                2 => NormalGeneration::Never,
+                _ => NormalGeneration::Missing,
+            },
+            mode: if self.flat_normals {
+                NormalMode::Flat
+            } else {
+                NormalMode::Smooth {
+                    weighting: NormalWeighting::Angle,
+                    crease_angle: Some(self.crease_angle),
+                }
+            },
+        }
+    }
 }
 
 impl Entity {
     pub fn to_ffi(&self) -> Box<ParsedModelData> {
         let mut vertices_flat = Vec::new();
+        let mut normals_flat = Vec::new();
         let mut uvs_flat = Vec::new();
         let mut faces_flat = Vec::new();
 
         let mut vertices_len = Vec::new();
+        let mut normals_len = Vec::new();
         let mut uvs_len = Vec::new();
         let mut faces_len = Vec::new();
 
         let translation = self.translation.to_vec();
+        let transforms: Vec<f32> = self.object_transforms().iter().flat_map(flatten).collect();
+        let bounds: Vec<f32> = self
+            .objects
+            .iter()
+            .map(|o| o.bounds())
+            .chain([self.bounds()])
+            .flat_map(|b| match b {
+                Some(b) => [b.aabb.min, b.aabb.max, b.sphere.center]
+                    .concat()
+                    .into_iter()
+                    .chain([b.sphere.radius])
+                    .collect(),
+                None => vec![0.0; 10],
+            })
+            .collect();
 
         for object in &self.objects {
             let object_vertices: Vec<f32> = object.vertices.iter().copied().flatten().collect();
             let object_uvs: Vec<f32> = object.uvs.iter().copied().flatten().collect();
             let object_faces: Vec<u32> = object.faces.iter().copied().flatten().collect();
 
+            let object_normals: Vec<f32> = if object.normals.len() == object.vertices.len() {
+                object.normals.iter().copied().flatten().collect()
+            } else {
+                Vec::new()
+            };
+
             vertices_len.push(object_vertices.len());
+            normals_len.push(object_normals.len());
             uvs_len.push(object_uvs.len());
             faces_len.push(object_faces.len());
 
             vertices_flat.extend(object_vertices);
+            normals_flat.extend(object_normals);
             uvs_flat.extend(object_uvs);
             faces_flat.extend(object_faces);
         }
@@ -75,6 +175,10 @@ impl Entity {
             uvs_len_ptr: uvs_len.as_ptr(),
             faces_len_ptr: faces_len.as_ptr(),
             translation_ptr: translation.as_ptr(),
+            transforms_ptr: transforms.as_ptr(),
+            normals_ptr: normals_flat.as_ptr(),
+            normals_len_ptr: normals_len.as_ptr(),
+            bounds_ptr: bounds.as_ptr(),
             vertices: vertices_flat,
             uvs: uvs_flat,
             faces: faces_flat,
@@ -82,6 +186,10 @@ impl Entity {
             uvs_len,
             faces_len,
             translation,
+            transforms,
+            normals: normals_flat,
+            normals_len,
+            bounds,
         })
     }
 }
@@ -109,11 +217,95 @@ pub extern "C" fn ffi_parse(xml_file_path: *const c_char) -> *mut ParsedModelDat
         return std::ptr::null_mut();
     }
 
-    let result = entity.unwrap().to_ffi();
+    let mut entity = entity.unwrap();
+    entity.apply_normals(&NormalOptions::default());
+    let result = entity.to_ffi();
 
     Box::into_raw(result)
 }
 
+/// Assembles a level and writes it to `out_path` as glTF/GLB, or as USD if
+/// the path ends in `.usda`. `excluded` is a newline-separated list of mesh
+/// names and `tex_dir` may be null.
+#[no_mangle]
+pub extern "C" fn ffi_export_level(
+    dmi_path: *const c_char,
+    xml_dir: *const c_char,
+    tex_dir: *const c_char,
+    excluded: *const c_char,
+    out_path: *const c_char,
+) -> bool {
+    let (Some(dmi_path), Some(xml_dir), Some(out_path)) =
+        (string(dmi_path), string(xml_dir), string(out_path))
+    else {
+        return false;
+    };
+
+    let options = LevelOptions {
+        xml_dir: xml_dir.into(),
+        texture_dir: string(tex_dir).map(Into::into),
+        excluded: string(excluded)
+            .map(|e| e.lines().map(str::to_string).collect())
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    let level = match Level::load(Path::new(&dmi_path), &options) {
+        Ok(level) => level,
+        Err(e) => {
+            info!("Failed to load level: {:?}", e);
+            return false;
+        }
+    };
+    for error in &level.errors {
+        info!("{}", error);
+    }
+
+    if let Err(e) = level.save(Path::new(&out_path)) {
+        info!("Failed to export level: {:?}", e);
+        return false;
+    }
+    true
+}
+
+/// Like `ffi_parse`, converting the model as described by `options` (which
+/// may be null).
+#[no_mangle]
+pub extern "C" fn ffi_parse_with(
+    xml_file_path: *const c_char,
+    options: *const FfiOptions,
+) -> *mut ParsedModelData {
+    let Some(xml_file) = string(xml_file_path) else {
+        return std::ptr::null_mut();
+    };
+    let mut entity = match parse(Path::new(&xml_file)) {
+        Ok(entity) => entity,
+        Err(e) => {
+            info!("Failed to parse file: {:?}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    if let Some(options) = unsafe { options.as_ref() } {
+        let coordinate_system = match options.coordinate_system() {
+            Ok(system) => system,
+            Err(e) => {
+                info!("Invalid options: {:?}", e);
+                return std::ptr::null_mut();
+            }
+        };
+        if options.bake_transforms {
+            entity.bake_transforms();
+        }
+        if options.merge {
+            entity.merge(SubmeshMode::Material);
+        }
+        entity.apply_normals(&options.normal_options());
+        entity.convert(&coordinate_system);
+    } else {
+        entity.apply_normals(&NormalOptions::default());
+    }
+    Box::into_raw(entity.to_ffi())
+}
+
 #[no_mangle]
 pub extern "C" fn ffi_free(result: *mut ParsedModelData) {
     if !result.is_null() {
@@ -122,3 +314,75 @@ pub extern "C" fn ffi_free(result: *mut ParsedModelData) {
         }
     }
 }
+
+/// Indexes a mesh and a texture directory; either may be null.
+#[no_mangle]
+pub extern "C" fn ffi_asset_index_new(
+    xml_dir: *const c_char,
+    tex_dir: *const c_char,
+) -> *mut AssetIndex {
+    let xml_dir = string(xml_dir);
+    let tex_dir = string(tex_dir);
+    let index = AssetIndex::scan(
+        xml_dir.as_deref().map(Path::new),
+        tex_dir.as_deref().map(Path::new),
+    );
+    for duplicate in index.duplicates() {
+        info!("Duplicate {}", duplicate);
+    }
+    Box::into_raw(Box::new(index))
+}
+
+/// Returns the mesh file for `name`, or null. Free with `ffi_string_free`.
+#[no_mangle]
+pub extern "C" fn ffi_asset_index_resolve_mesh(
+    index: *const AssetIndex,
+    name: *const c_char,
+) -> *mut c_char {
+    resolve(index, name, AssetIndex::resolve_mesh)
+}
+
+/// Returns the texture file for a shader parameter texture name, or null.
+/// Free with `ffi_string_free`.
+#[no_mangle]
+pub extern "C" fn ffi_asset_index_resolve_texture(
+    index: *const AssetIndex,
+    name: *const c_char,
+) -> *mut c_char {
+    resolve(index, name, AssetIndex::resolve_texture)
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_asset_index_free(index: *mut AssetIndex) {
+    if !index.is_null() {
+        unsafe {
+            drop(Box::from_raw(index));
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ffi_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        unsafe {
+            drop(CString::from_raw(string));
+        }
+    }
+}
+
+fn resolve(
+    index: *const AssetIndex,
+    name: *const c_char,
+    resolve: for<'a> fn(&'a AssetIndex, &str) -> Option<&'a Path>,
+) -> *mut c_char {
+    let (Some(index), Some(name)) = (unsafe { index.as_ref() }, string(name)) else {
+        return std::ptr::null_mut();
+    };
+    resolve(index, &name)
+        .and_then(|path| CString::new(path.to_string_lossy().as_bytes()).ok())
+        .map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+fn string(ptr: *const c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| unsafe { CStr::from_ptr(ptr) }.to_string_lossy().to_string())
+}
@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::{
+    entity::Entity,
+    error::ParseError,
+    level::Level,
+    math::{flatten, Matrix, IDENTITY},
+    object::Object,
+    scene::SceneNode,
+};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// A finished glTF 2.0 asset: the JSON document and its single binary buffer.
+#[derive(Debug)]
+pub struct Gltf {
+    json: Value,
+    buffer: Vec<u8>,
+}
+
+impl Gltf {
+    /// Serializes as `.gltf` JSON, referencing the buffer at `bin_uri`.
+    pub fn to_gltf(&self, bin_uri: &str) -> String {
+        let mut json = self.json.clone();
+        if self.has_buffer() {
+            json["buffers"][0]["uri"] = json!(bin_uri);
+        }
+        serde_json::to_string_pretty(&json).expect("Failed to serialize glTF")
+    }
+
+    pub fn to_glb(&self) -> Vec<u8> {
+        let mut json = serde_json::to_vec(&self.json).expect("Failed to serialize glTF");
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut bin = self.buffer.clone();
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+
+        let bin_chunk = if self.has_buffer() { 8 + bin.len() } else { 0 };
+        let length = 12 + 8 + json.len() + bin_chunk;
+        let mut glb = Vec::with_capacity(length);
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((length as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(json);
+        if self.has_buffer() {
+            glb.extend((bin.len() as u32).to_le_bytes());
+            glb.extend(b"BIN\0");
+            glb.extend(bin);
+        }
+        glb
+    }
+
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Whether there is any binary data; a scene without geometry has no
+    /// buffer at all, since glTF does not allow empty ones.
+    pub fn has_buffer(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+}
+
+/// Vertex attributes and one index accessor per face range.
+type Geometry = (Value, Vec<usize>);
+/// Mesh name, object index and the texture of each face range.
+type MeshKey = (String, usize, Vec<Option<String>>);
+
+/// Collects entities into one glTF scene. Geometry is keyed by mesh name, so
+/// every placement of the same mesh reuses the same buffers and glTF meshes.
+#[derive(Debug)]
+pub struct GltfBuilder {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    materials: Vec<Value>,
+    images: Vec<Value>,
+    textures: Vec<Value>,
+    scene_nodes: Vec<usize>,
+    lods: bool,
+    geometry_cache: HashMap<(String, usize), Option<Geometry>>,
+    mesh_cache: HashMap<MeshKey, Option<usize>>,
+    material_cache: HashMap<Option<String>, usize>,
+}
+
+impl GltfBuilder {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            buffer_views: Vec::new(),
+            accessors: Vec::new(),
+            meshes: Vec::new(),
+            nodes: Vec::new(),
+            materials: Vec::new(),
+            images: Vec::new(),
+            textures: Vec::new(),
+            scene_nodes: Vec::new(),
+            lods: false,
+            geometry_cache: HashMap::new(),
+            mesh_cache: HashMap::new(),
+            material_cache: HashMap::new(),
+        }
+    }
+
+    /// Places `entity` in the scene. `texture_uri` overrides the textures of
+    /// the entity's own materials. Returns the index of the placement node.
+    pub fn add_instance(
+        &mut self,
+        name: &str,
+        entity: &Entity,
+        transform: &Matrix,
+        texture_uri: Option<&str>,
+    ) -> usize {
+        let index = self.instance_node(name, entity, transform, texture_uri);
+        self.scene_nodes.push(index);
+        index
+    }
+
+    /// Adds lower detail versions of the instance at node `instance` through
+    /// the `MSFT_lod` extension, most detailed first. `name` must be the
+    /// name the instance was added with.
+    pub fn add_lods(
+        &mut self,
+        instance: usize,
+        name: &str,
+        lods: &[Entity],
+        transform: &Matrix,
+        texture_uri: Option<&str>,
+    ) {
+        if lods.is_empty() {
+            return;
+        }
+        let ids: Vec<usize> = lods
+            .iter()
+            .enumerate()
+            .map(|(i, lod)| {
+                let lod_name = format!("{}_LOD{}", name, i + 1);
+                self.instance_node(&lod_name, lod, transform, texture_uri)
+            })
+            .collect();
+        self.nodes[instance]["extensions"] = json!({ "MSFT_lod": { "ids": ids } });
+        self.lods = true;
+    }
+
+    fn instance_node(
+        &mut self,
+        name: &str,
+        entity: &Entity,
+        transform: &Matrix,
+        texture_uri: Option<&str>,
+    ) -> usize {
+        let meshes: Vec<Option<usize>> = entity
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| {
+                let uri = |material: Option<&str>| {
+                    texture_uri.map(str::to_string).or_else(|| {
+                        material
+                            .and_then(|m| entity.material(m))
+                            .and_then(|m| m.texture.as_ref())
+                            .map(|t| percent_encode(&format!("{}.png", t), false))
+                    })
+                };
+                let uris = if object.submeshes.is_empty() {
+                    vec![uri(object.material.as_deref())]
+                } else {
+                    object
+                        .submeshes
+                        .iter()
+                        .map(|s| uri(s.material.as_deref()))
+                        .collect()
+                };
+                self.mesh(name, i, object, uris)
+            })
+            .collect();
+
+        let mut placed = vec![false; entity.objects.len()];
+        let mut children = Vec::new();
+        if let Some(root) = &entity.root {
+            children.push(self.add_node(root, &meshes, &mut placed));
+        }
+        for (i, mesh) in meshes.iter().enumerate() {
+            if let (false, Some(mesh)) = (placed[i], mesh) {
+                children.push(self.push_node(json!({
+                    "name": entity.objects[i].name,
+                    "mesh": mesh,
+                })));
+            }
+        }
+
+        let mut node = json!({ "name": name, "children": children });
+        if *transform != IDENTITY {
+            node["matrix"] = json!(flatten(transform));
+        }
+        self.push_node(node)
+    }
+
+    pub fn finish(self) -> Gltf {
+        let mut json = json!({
+            "asset": {
+                "version": "2.0",
+                "generator": format!("journey-model-parser {}", env!("CARGO_PKG_VERSION")),
+            },
+            "scene": 0,
+            "scenes": [{}],
+        });
+        // glTF arrays must not be empty, so an empty scene leaves them out.
+        if !self.scene_nodes.is_empty() {
+            json["scenes"][0]["nodes"] = json!(self.scene_nodes);
+        }
+        for (key, values) in [
+            ("nodes", self.nodes),
+            ("meshes", self.meshes),
+            ("accessors", self.accessors),
+            ("bufferViews", self.buffer_views),
+        ] {
+            if !values.is_empty() {
+                json[key] = json!(values);
+            }
+        }
+        if !self.buffer.is_empty() {
+            json["buffers"] = json!([{ "byteLength": self.buffer.len() }]);
+        }
+        if self.lods {
+            json["extensionsUsed"] = json!(["MSFT_lod"]);
+        }
+        if !self.materials.is_empty() {
+            json["materials"] = json!(self.materials);
+        }
+        if !self.images.is_empty() {
+            json["images"] = json!(self.images);
+            json["textures"] = json!(self.textures);
+            json["samplers"] = json!([{ "wrapS": 10497, "wrapT": 10497 }]);
+        }
+        Gltf {
+            json,
+            buffer: self.buffer,
+        }
+    }
+
+    fn add_node(
+        &mut self,
+        node: &SceneNode,
+        meshes: &[Option<usize>],
+        placed: &mut [bool],
+    ) -> usize {
+        let mut children: Vec<usize> = node
+            .children
+            .iter()
+            .map(|c| self.add_node(c, meshes, placed))
+            .collect();
+
+        let mut own_meshes = node.objects.iter().filter_map(|&i| {
+            placed[i] = true;
+            meshes[i]
+        });
+        let mesh = own_meshes.next();
+        for extra in own_meshes {
+            children.push(self.push_node(json!({ "mesh": extra })));
+        }
+
+        let mut value = json!({ "name": node.name });
+        if node.transform != IDENTITY {
+            value["matrix"] = json!(flatten(&node.transform));
+        }
+        if let Some(mesh) = mesh {
+            value["mesh"] = json!(mesh);
+        }
+        if !children.is_empty() {
+            value["children"] = json!(children);
+        }
+        self.push_node(value)
+    }
+
+    fn push_node(&mut self, node: Value) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn mesh(
+        &mut self,
+        name: &str,
+        i: usize,
+        object: &Object,
+        uris: Vec<Option<String>>,
+    ) -> Option<usize> {
+        let key = (name.to_string(), i, uris.clone());
+        if let Some(mesh) = self.mesh_cache.get(&key) {
+            return *mesh;
+        }
+
+        let mesh = self.geometry(name, i, object).map(|(attributes, indices)| {
+            let primitives: Vec<Value> = indices
+                .into_iter()
+                .zip(uris)
+                .map(|(indices, uri)| {
+                    json!({
+                        "attributes": attributes,
+                        "indices": indices,
+                        "material": self.material(uri),
+                        "mode": 4,
+                    })
+                })
+                .collect();
+            self.meshes.push(json!({
+                "name": object.name,
+                "primitives": primitives,
+            }));
+            self.meshes.len() - 1
+        });
+
+        self.mesh_cache.insert(key, mesh);
+        mesh
+    }
+
+    /// Writes the vertex and index data of an object once, with one index
+    /// accessor per submesh; meshes that only differ in material share the
+    /// same accessors.
+    fn geometry(&mut self, name: &str, i: usize, object: &Object) -> Option<(Value, Vec<usize>)> {
+        let key = (name.to_string(), i);
+        if let Some(geometry) = self.geometry_cache.get(&key) {
+            return geometry.clone();
+        }
+
+        let geometry = if object.vertices.is_empty() || object.faces.is_empty() {
+            None
+        } else {
+            let vertex_count = object.vertices.len();
+            let mut attributes = json!({
+                "POSITION": self.vec3_accessor(&object.vertices, true),
+            });
+            if object.normals.len() == vertex_count {
+                attributes["NORMAL"] = json!(self.vec3_accessor(&object.normals, false));
+            }
+            if object.uvs.len() == vertex_count {
+                let data: Vec<f32> = object.uvs.iter().flatten().copied().collect();
+                attributes["TEXCOORD_0"] = json!(self.float_accessor(&data, "VEC2", None));
+            }
+            if object.colors.len() == vertex_count {
+                let data: Vec<f32> = object.colors.iter().flatten().copied().collect();
+                attributes["COLOR_0"] = json!(self.float_accessor(&data, "VEC4", None));
+            }
+            let indices = object
+                .face_ranges()
+                .into_iter()
+                .map(|range| self.index_accessor(&object.faces[range]))
+                .collect();
+            Some((attributes, indices))
+        };
+
+        self.geometry_cache.insert(key, geometry.clone());
+        geometry
+    }
+
+    fn material(&mut self, uri: Option<String>) -> usize {
+        if let Some(material) = self.material_cache.get(&uri) {
+            return *material;
+        }
+
+        let material = match &uri {
+            Some(uri) => {
+                self.images.push(json!({ "uri": uri }));
+                self.textures
+                    .push(json!({ "sampler": 0, "source": self.images.len() - 1 }));
+                json!({
+                    "name": uri,
+                    "pbrMetallicRoughness": {
+                        "baseColorTexture": { "index": self.textures.len() - 1 },
+                        "metallicFactor": 0.0,
+                    },
+                    "alphaMode": "MASK",
+                })
+            }
+            None => json!({
+                "name": "default",
+                "pbrMetallicRoughness": { "metallicFactor": 0.0 },
+            }),
+        };
+        self.materials.push(material);
+        self.material_cache.insert(uri, self.materials.len() - 1);
+        self.materials.len() - 1
+    }
+
+    fn vec3_accessor(&mut self, data: &[[f32; 3]], bounds: bool) -> usize {
+        let bounds = bounds.then(|| {
+            let mut min = data[0];
+            let mut max = data[0];
+            for v in data {
+                for i in 0..3 {
+                    min[i] = min[i].min(v[i]);
+                    max[i] = max[i].max(v[i]);
+                }
+            }
+            (min, max)
+        });
+        let flat: Vec<f32> = data.iter().flatten().copied().collect();
+        self.float_accessor(&flat, "VEC3", bounds)
+    }
+
+    fn float_accessor(
+        &mut self,
+        data: &[f32],
+        accessor_type: &str,
+        bounds: Option<([f32; 3], [f32; 3])>,
+    ) -> usize {
+        let components = match accessor_type {
+            "VEC2" => 2,
+            "VEC3" => 3,
+            _ => 4,
+        };
+        let bytes: Vec<u8> = data.iter().flat_map(|f| f.to_le_bytes()).collect();
+        let view = self.buffer_view(&bytes, ARRAY_BUFFER);
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": data.len() / components,
+            "type": accessor_type,
+        });
+        if let Some((min, max)) = bounds {
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn index_accessor(&mut self, faces: &[[u32; 3]]) -> usize {
+        let bytes: Vec<u8> = faces
+            .iter()
+            .flatten()
+            .flat_map(|i| i.to_le_bytes())
+            .collect();
+        let view = self.buffer_view(&bytes, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": UNSIGNED_INT,
+            "count": faces.len() * 3,
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn buffer_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        while !self.buffer.len().is_multiple_of(4) {
+            self.buffer.push(0);
+        }
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.buffer.extend(bytes);
+        self.buffer_views.len() - 1
+    }
+}
+
+impl Default for GltfBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Entity {
+    pub fn to_gltf(&self, name: &str) -> Gltf {
+        self.to_gltf_with_lods(name, &[])
+    }
+
+    /// Like [`Entity::to_gltf`], with `lods` as `MSFT_lod` levels.
+    pub fn to_gltf_with_lods(&self, name: &str, lods: &[Entity]) -> Gltf {
+        let mut builder = GltfBuilder::new();
+        let instance = builder.add_instance(name, self, &IDENTITY, None);
+        builder.add_lods(instance, name, lods, &IDENTITY, None);
+        builder.finish()
+    }
+}
+
+impl Level {
+    /// Builds one scene with every unique mesh stored once and each instance
+    /// placed through a node. Texture paths are written relative to
+    /// `base_dir` when they lie below it.
+    pub fn to_gltf(&self, base_dir: Option<&Path>) -> Gltf {
+        let mut builder = GltfBuilder::new();
+        for instance in &self.instances {
+            let (name, _, entity) = &self.entities[instance.entity];
+            let uri = instance
+                .texture_path
+                .as_deref()
+                .map(|path| image_uri(path, base_dir));
+            builder.add_instance(name, entity, &instance.transform, uri.as_deref());
+        }
+        builder.finish()
+    }
+
+    /// Writes the level to `path`, as GLB if the extension is `.glb`,
+    /// otherwise as `.gltf` with a `.bin` buffer next to it.
+    pub fn save_gltf(&self, path: &Path) -> Result<Vec<PathBuf>, ParseError> {
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        save(&self.to_gltf(Some(base_dir)), path)
+    }
+}
+
+/// Writes `gltf` as GLB or as `.gltf` plus `.bin`, depending on the extension.
+pub fn save(gltf: &Gltf, path: &Path) -> Result<Vec<PathBuf>, ParseError> {
+    let write = |path: &Path, contents: &[u8]| {
+        fs::write(path, contents)
+            .map_err(|e| ParseError::new(&format!("Failed to write file {:?}: {e}", path)))
+    };
+
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("glb"))
+    {
+        write(path, &gltf.to_glb())?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let bin_path = path.with_extension("bin");
+    let bin_uri = bin_path
+        .file_name()
+        .map(|n| percent_encode(&n.to_string_lossy(), false))
+        .unwrap_or_default();
+    write(path, gltf.to_gltf(&bin_uri).as_bytes())?;
+    if !gltf.has_buffer() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    write(&bin_path, gltf.buffer())?;
+    Ok(vec![path.to_path_buf(), bin_path])
+}
+
+/// A URI for `path`: relative to `base_dir` when possible, otherwise a
+/// `file:` URI.
+fn image_uri(path: &Path, base_dir: Option<&Path>) -> String {
+    if let Some(relative) = base_dir.and_then(|base| relative_path(path, base)) {
+        return percent_encode(&relative.to_string_lossy().replace('\\', "/"), false);
+    }
+    let path = path.to_string_lossy().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{}", percent_encode(&path, true))
+    } else if path.get(1..2) == Some(":") {
+        format!("file:///{}", percent_encode(&path, true))
+    } else {
+        percent_encode(&path, false)
+    }
+}
+
+/// Percent-encodes everything but unreserved characters and `/` (and `:`
+/// in absolute paths, for Windows drive letters).
+fn percent_encode(path: &str, absolute: bool) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            b':' if absolute => uri.push(':'),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Expresses `path` relative to `base` when they share a root.
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let rooted = |c: &Component| matches!(c, Component::Prefix(_) | Component::RootDir);
+    if path.first().map(rooted) != base.first().map(rooted)
+        || (common == 0 && path.first().is_some_and(rooted))
+    {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    relative.extend(&path[common..]);
+    Some(relative)
+}
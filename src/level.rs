@@ -0,0 +1,272 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tracing::{info, warn};
+
+use crate::{
+    assets::AssetIndex,
+    coords::CoordinateSystem,
+    entity::Entity,
+    error::ParseError,
+    lua::{parse_lua, LuaTable, LuaValue},
+    math::Matrix,
+    normals::NormalOptions,
+    parse,
+};
+
+/// Shader parameters that name the texture of an instance, in order of preference.
+const TEXTURE_KEYS: [&str; 5] = [
+    "texColor",
+    "texCham",
+    "tex",
+    "textureAtlasA",
+    "textureAtlasB",
+];
+
+#[derive(Debug)]
+pub struct LevelOptions {
+    /// Directory searched (recursively) for the PSSG XML files of the meshes.
+    pub xml_dir: PathBuf,
+    /// Directory searched (recursively) for `.png` textures.
+    pub texture_dir: Option<PathBuf>,
+    /// Mesh names to skip, with or without the `P_` prefix.
+    pub excluded: Vec<String>,
+    /// Texture used for instances without shader parameters (cloth).
+    pub cloth_texture: String,
+    /// When and how to generate normals for the meshes.
+    pub normals: NormalOptions,
+}
+
+impl Default for LevelOptions {
+    fn default() -> Self {
+        Self {
+            xml_dir: PathBuf::new(),
+            texture_dir: None,
+            excluded: Vec::new(),
+            cloth_texture: "ClothAtlas".to_string(),
+            normals: NormalOptions::default(),
+        }
+    }
+}
+
+/// One placed decoration mesh.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Instance {
+    pub mesh: String,
+    /// Index into [`Level::entities`].
+    pub entity: usize,
+    /// Texture name from the shader parameters.
+    pub texture: Option<String>,
+    /// Resolved texture file, if a texture directory was given.
+    pub texture_path: Option<PathBuf>,
+    /// Placement in the level's coordinate system (game space unless
+    /// converted), in PSSG matrix layout. The entity's own translation is
+    /// applied before this.
+    pub transform: Matrix,
+}
+
+impl Instance {
+    /// An untextured instance of `entity`, placed by `transform`.
+    pub fn new(mesh: &str, entity: usize, transform: Matrix) -> Self {
+        Self {
+            mesh: mesh.to_string(),
+            entity,
+            texture: None,
+            texture_path: None,
+            transform,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Level {
+    /// Parsed meshes, each loaded once: `(mesh name, source file, entity)`.
+    pub entities: Vec<(String, PathBuf, Entity)>,
+    pub instances: Vec<Instance>,
+    /// Meshes or textures that could not be resolved or parsed.
+    pub errors: Vec<String>,
+    /// The space meshes and instance transforms are in; see [`Level::convert`].
+    pub coordinate_system: CoordinateSystem,
+}
+
+impl Level {
+    /// A level in game space without errors.
+    pub fn new(entities: Vec<(String, PathBuf, Entity)>, instances: Vec<Instance>) -> Self {
+        Self {
+            entities,
+            instances,
+            errors: Vec::new(),
+            coordinate_system: CoordinateSystem::GAME,
+        }
+    }
+
+    /// Assembles a level from a `DecorationMeshInstances.lua` file, indexing
+    /// the directories in `options`.
+    pub fn load(dmi_file: &Path, options: &LevelOptions) -> Result<Self, ParseError> {
+        let index = AssetIndex::scan(Some(&options.xml_dir), options.texture_dir.as_deref());
+        Self::load_with(dmi_file, &index, options)
+    }
+
+    /// Assembles a level, resolving meshes and textures through `index`.
+    /// The directories in `options` are ignored.
+    pub fn load_with(
+        dmi_file: &Path,
+        index: &AssetIndex,
+        options: &LevelOptions,
+    ) -> Result<Self, ParseError> {
+        let source = fs::read_to_string(dmi_file)
+            .map_err(|e| ParseError::new(&format!("Failed to read {:?}: {e}", dmi_file)))?;
+        let globals = parse_lua(&source)?;
+        let table = globals
+            .get("DecorationMeshInstances")
+            .and_then(LuaValue::as_table)
+            .ok_or_else(|| ParseError::new("DecorationMeshInstances table not found"))?;
+
+        let mut level = Level::new(Vec::new(), Vec::new());
+
+        for entry in table.values().filter_map(LuaValue::as_table) {
+            let Some(mesh) = entry.get("Mesh").and_then(LuaValue::as_str) else {
+                level.errors.push("Instance without Mesh".to_string());
+                continue;
+            };
+            if options
+                .excluded
+                .iter()
+                .any(|e| e == mesh || *e == mesh.replace("P_", ""))
+            {
+                continue;
+            }
+
+            let Some(transform) = entry
+                .get("Transformation")
+                .and_then(LuaValue::as_table)
+                .and_then(parse_transformation)
+            else {
+                level
+                    .errors
+                    .push(format!("Failed to parse transformation of {}", mesh));
+                continue;
+            };
+
+            let texture = instance_texture(entry, &options.cloth_texture);
+            if texture.is_none() {
+                warn!("No texture for {}", mesh);
+            }
+            let texture_path = texture
+                .as_deref()
+                .and_then(|t| index.resolve_texture(t))
+                .map(Path::to_path_buf);
+
+            let Some(entity) = level.entity_index(mesh, index, &options.normals) else {
+                continue;
+            };
+
+            level.instances.push(Instance {
+                mesh: mesh.to_string(),
+                entity,
+                texture,
+                texture_path,
+                transform,
+            });
+        }
+
+        info!(
+            "Assembled {} instances of {} meshes",
+            level.instances.len(),
+            level.entities.len()
+        );
+        Ok(level)
+    }
+
+    pub fn entity(&self, instance: &Instance) -> &Entity {
+        &self.entities[instance.entity].2
+    }
+
+    /// Writes the level to `path` as a USD layer if the extension is
+    /// `.usda`, otherwise as glTF (see [`Level::save_gltf`]).
+    pub fn save(&self, path: &Path) -> Result<Vec<PathBuf>, ParseError> {
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("usda") || e.eq_ignore_ascii_case("usd"))
+        {
+            self.save_usda(path)?;
+            return Ok(vec![path.to_path_buf()]);
+        }
+        self.save_gltf(path)
+    }
+
+    fn entity_index(
+        &mut self,
+        mesh: &str,
+        index: &AssetIndex,
+        normals: &NormalOptions,
+    ) -> Option<usize> {
+        if let Some(i) = self.entities.iter().position(|(name, _, _)| name == mesh) {
+            return Some(i);
+        }
+        let Some(path) = index.resolve_mesh(mesh) else {
+            self.errors.push(format!("No mesh file found for {}", mesh));
+            return None;
+        };
+        match parse(path) {
+            Ok(mut entity) => {
+                entity.apply_normals(normals);
+                self.entities
+                    .push((mesh.to_string(), path.to_path_buf(), entity));
+                Some(self.entities.len() - 1)
+            }
+            Err(e) => {
+                self.errors
+                    .push(format!("Failed to parse {:?}: {}", path, e));
+                None
+            }
+        }
+    }
+}
+
+/// Reads the 4x4 `Transformation` table (rows of columns) as-is; the Lua
+/// matrices already use the PSSG layout with the translation in row 3.
+fn parse_transformation(table: &LuaTable) -> Option<Matrix> {
+    let numbers: Vec<f32> = table
+        .values()
+        .filter_map(LuaValue::as_table)
+        .flat_map(|row| row.values().filter_map(LuaValue::as_number))
+        .map(|n| n as f32)
+        .collect();
+    if numbers.len() != 16 {
+        return None;
+    }
+
+    let mut matrix = [[0.0; 4]; 4];
+    for (i, row) in matrix.iter_mut().enumerate() {
+        row.copy_from_slice(&numbers[i * 4..i * 4 + 4]);
+    }
+    Some(matrix)
+}
+
+/// Picks the texture from `ShaderParams`; instances with empty shader
+/// parameters are cloth and use `cloth_texture`.
+fn instance_texture(entry: &LuaTable, cloth_texture: &str) -> Option<String> {
+    let params = entry.get("ShaderParams").and_then(LuaValue::as_table)?;
+    if params.is_empty() {
+        return Some(cloth_texture.to_string());
+    }
+
+    let params: Vec<(&str, &str)> = params
+        .values()
+        .filter_map(LuaValue::as_table)
+        .filter_map(|p| {
+            let name = p.get("ParamName")?.as_str()?;
+            let value = p.get("ParamVal")?.as_str()?;
+            Some((name, value))
+        })
+        .filter(|(_, value)| *value != "Blank")
+        .collect();
+
+    TEXTURE_KEYS
+        .iter()
+        .find_map(|key| params.iter().find(|(name, _)| name == key))
+        .map(|(_, value)| value.to_string())
+}
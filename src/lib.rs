@@ -1,20 +1,139 @@
+//! Reads the PSSG models of Journey and converts them to common formats.
+//!
+//! [`parse`] loads a model file (pssgConverter XML, binary PSSG, gzipped, or
+//! an entry inside an `.ipa`) into an [`Entity`]: its [`Object`]s with their
+//! vertex streams and faces, the [`SceneNode`] hierarchy, [`Material`]s and
+//! [`Skin`]; [`parse_with`] takes [`ParseOptions`] to change how strictly
+//! and how much of the file is decoded. Entities can be inspected ([`Entity::summary`],
+//! [`Entity::topology`], [`Entity::bounds`]), edited (welding, merging,
+//! simplifying, coordinate conversion) and written with [`export`] or
+//! [`write_entity`].
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let entity = journey_model_parser::parse(Path::new("P_Rock.xml"))?;
+//! for object in &entity.objects {
+//!     println!("{}: {} faces", object.name, object.face_count());
+//! }
+//! # Ok::<(), journey_model_parser::ParseError>(())
+//! ```
+//!
+//! # Stability
+//!
+//! The API is everything reachable from the crate root; the module layout is
+//! private and may change. The deserialized PSSG schema is in [`raw`]. It
+//! follows the file format rather than this crate's design, so expect it to
+//! change more often.
+//!
+//! The crate follows semver, treating `0.x` minor versions as major ones.
+//! Within a minor version:
+//!
+//! - Types the parser produces are `#[non_exhaustive]` and can gain fields,
+//!   so they cannot be built with struct literals. The model types have
+//!   `new` constructors taking their essential fields: [`Entity::new`],
+//!   [`Object::new`], [`Submesh::new`], [`SceneNode::new`],
+//!   [`Material::new`], [`Skin::new`], [`Texture::new`],
+//!   [`ImageBlock::new`], [`Level::new`] and [`Instance::new`]. Their fields
+//!   stay public for reading and editing. [`StreamInfo`], [`Summary`] and the
+//!   reports only describe parsed files and are only built by the crate.
+//! - Plain values such as [`Aabb`], [`AtlasRegion`] and [`Matrix`] are
+//!   exhaustive.
+//! - Enums that are `#[non_exhaustive]` can gain variants, so matches on them
+//!   need a wildcard arm.
+//! - Options structs such as [`ExportOptions`] implement [`Default`] and can
+//!   gain fields with defaults that keep the old behaviour. Build them with
+//!   `..Default::default()`.
+//! - The C functions used by `blender_import.py` are not part of the Rust API.
+
+#![warn(missing_debug_implementations, unnameable_types)]
+
+mod archive;
+mod assets;
+mod atlas;
+mod batch;
+mod bounds;
+mod collada;
+mod coords;
+mod dump;
 mod entity;
 mod error;
+mod export;
 mod ffi;
+mod gltf;
+mod level;
+mod lua;
+mod math;
+mod merge;
+mod normals;
 mod object;
+mod optimize;
 mod parse;
+mod pssg;
+mod render;
+mod scene;
+mod simplify;
 mod structure;
+mod summary;
+mod texture;
+mod topology;
+mod transform;
+mod usd;
+mod weld;
+mod write;
+mod xml;
+
+/// The PSSG database as deserialized from the file, before it is turned into
+/// an [`Entity`].
+pub mod raw {
+    pub use crate::structure::{
+        load_bytes, DataBlock, DataBlockData, DataBlockStream, IndexSourceData, Library, Node,
+        PssgDatabase, RenderDataSource, RenderIndexSource, RenderNode, RenderStream,
+        RenderStreamInstance, RootNode, SegmentSet, ShaderInput, ShaderInstance, Skeleton,
+        SkinJoint, Transform, TypeInfo,
+    };
+    pub use crate::xml::Element;
+}
 
-use std::fs::File;
-use std::io::Write;
 use std::path::Path;
 use std::sync::Once;
 
-use entity::Entity;
-use error::ParseError;
 use tracing::info;
 
-use object::Object;
+pub use archive::Archive;
+pub use assets::{texture_file_name, AssetIndex, AssetKind, Duplicate, Fallback, Resolution};
+pub use atlas::{Atlas, AtlasRegion};
+pub use batch::{convert_dir, find_pssg_files, BatchOptions, BatchReport, FileReport};
+pub use bounds::{Aabb, Bounds, BoundsMismatch, Sphere, BOUNDS_TOLERANCE};
+pub use coords::{CoordinateSystem, Handedness, UpAxis};
+pub use dump::{dump, DumpFormat, DumpOptions};
+pub use entity::Entity;
+pub use error::ParseError;
+pub use export::{export, ExportOptions, ExportReport, Format};
+pub use gltf::{Gltf, GltfBuilder};
+pub use level::{Instance, Level, LevelOptions};
+pub use lua::{parse_lua, LuaTable, LuaValue};
+pub use math::Matrix;
+pub use merge::SubmeshMode;
+pub use normals::{NormalGeneration, NormalMode, NormalOptions, NormalWeighting};
+pub use object::{Object, StreamInfo, Submesh};
+pub use optimize::{cache_stats, CacheStats, OptimizeReport};
+pub use parse::{Attribute, CountMismatch, Endianness, ParseOptions, Validation};
+pub use pssg::model_name;
+pub use render::{render_catalogue, CatalogueOptions, RenderMode, RenderOptions};
+pub use scene::{Material, NodeKind, SceneNode, Skin};
+pub use simplify::SimplifyOptions;
+pub use structure::{load_xml_file, PssgFile, TypeCount};
+pub use summary::{DataBlockSummary, NodeSummary, ObjectSummary, Summary};
+pub use texture::{
+    export_textures, load_textures, Image, ImageBlock, MipSelection, TexelFormat, Texture,
+    TextureOptions,
+};
+pub use topology::{RepairOptions, RepairReport, TopologyReport};
+pub use transform::TransformMode;
+pub use usd::UsdStage;
+pub use weld::{WeldMode, WeldOptions};
+pub use write::{write_entity, write_entity_with, PssgDocument};
 
 static INIT: Once = Once::new();
 
@@ -22,42 +141,52 @@ fn initialize_tracing() {
     INIT.call_once(|| {
         tracing_subscriber::fmt()
             .with_max_level(tracing::Level::INFO)
+            .with_writer(std::io::stderr)
             .init();
     });
 }
 
-fn write_obj(object: &Object, path: &Path) {
-    let mut file = File::create(path).expect("Failed to create file");
-    file.write_all(object.to_obj().as_bytes())
-        .expect("Failed to write to file");
+/// Parses a model file: pssgConverter XML, binary PSSG (optionally gzipped),
+/// or an entry inside an `.ipa`/`.zip` archive given as `archive/entry`.
+pub fn parse(xml_file: &Path) -> Result<Entity, ParseError> {
+    parse_with(xml_file, &ParseOptions::default())
 }
 
-pub fn parse(xml_file: &Path) -> Result<Entity, ParseError> {
+/// Parses a model file like [`parse`], decoding it as `options` describe.
+pub fn parse_with(xml_file: &Path, options: &ParseOptions) -> Result<Entity, ParseError> {
     initialize_tracing();
     info!("Parsing file {:?}", xml_file);
-    let (data_blocks, render_index_sources, root_node) = structure::parse_xml_file(xml_file);
-    Entity::new(data_blocks, render_index_sources, root_node)
+    let contents = structure::parse_xml_file(xml_file, options)?;
+    Entity::from_contents(contents, options)
 }
 
 pub fn convert_file(xml_file: &Path) {
-    let entity = parse(xml_file);
-
-    if let Err(e) = entity {
-        info!("Failed to parse file: {:?}", e);
-        return;
+    if let Err(e) = convert_file_with(xml_file, &ExportOptions::default()) {
+        info!("Failed to convert file: {:?}", e);
     }
+}
 
-    let entity = entity.unwrap();
-
-    let path = xml_file.file_stem().unwrap().to_string_lossy().to_string();
+pub fn convert_file_with(
+    xml_file: &Path,
+    options: &ExportOptions,
+) -> Result<ExportReport, ParseError> {
+    let entity = parse(xml_file)?;
 
-    for (i, object) in entity.objects.iter().enumerate() {
-        let obj_file = if entity.objects.len() == 1 {
-            format!("{}.obj", path)
-        } else {
-            format!("{}-{}.obj", path, i + 1)
-        };
-        let obj_path = xml_file.with_file_name(obj_file);
-        write_obj(object, &obj_path);
+    let name = pssg::model_name(xml_file);
+    if name.is_empty() {
+        return Err(ParseError::new(&format!(
+            "Invalid file name {:?}",
+            xml_file
+        )));
     }
+    let out_dir = match &options.out_dir {
+        Some(out_dir) => out_dir.clone(),
+        None => {
+            // Entries inside an archive are written next to the archive.
+            let file = archive::split_path(xml_file).map_or(xml_file, |(archive, _)| archive);
+            file.parent().unwrap_or(Path::new("")).to_path_buf()
+        }
+    };
+
+    export(&entity, &name, &out_dir, options)
 }
@@ -0,0 +1,501 @@
+use std::collections::HashMap;
+
+use crate::error::ParseError;
+
+/// A value of the data-only Lua subset: literals and table constructors.
+#[derive(Clone, PartialEq, Debug)]
+pub enum LuaValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Table(LuaTable),
+}
+
+impl LuaValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LuaValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            LuaValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&LuaTable> {
+        match self {
+            LuaValue::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// Table entries in source order. Positional entries get keys `1, 2, ...`.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LuaTable {
+    entries: Vec<(LuaValue, LuaValue)>,
+}
+
+impl LuaTable {
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k.as_str() == Some(key))
+            .map(|(_, v)| v)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(LuaValue, LuaValue)> {
+        self.entries.iter()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &LuaValue> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn set(&mut self, key: LuaValue, value: LuaValue) {
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.entries.push((key, value)),
+        }
+    }
+}
+
+/// Evaluates a data-only Lua chunk: assignments of literals and tables to
+/// globals (`Name = ...`, `Name.field = ...`, `Name[key] = ...`). Returns the
+/// resulting globals.
+pub fn parse_lua(source: &str) -> Result<HashMap<String, LuaValue>, ParseError> {
+    let mut parser = Parser {
+        src: source.as_bytes(),
+        pos: 0,
+    };
+    let mut globals = HashMap::new();
+
+    loop {
+        parser.skip_whitespace();
+        if parser.at_end() {
+            break;
+        }
+        if parser.eat(b";") {
+            continue;
+        }
+
+        let mut name = parser.identifier()?;
+        if name == "local" {
+            parser.skip_whitespace();
+            name = parser.identifier()?;
+        }
+        if name == "return" {
+            parser.value()?;
+            continue;
+        }
+
+        let mut path = Vec::new();
+        loop {
+            parser.skip_whitespace();
+            if parser.eat(b".") {
+                parser.skip_whitespace();
+                path.push(LuaValue::String(parser.identifier()?));
+            } else if parser.eat(b"[") {
+                path.push(parser.value()?);
+                parser.expect(b"]")?;
+            } else {
+                break;
+            }
+        }
+        parser.expect(b"=")?;
+        let value = parser.value()?;
+
+        match path.split_last() {
+            None => {
+                globals.insert(name, value);
+            }
+            Some((key, parents)) => {
+                let mut table = match globals.get_mut(&name) {
+                    Some(LuaValue::Table(t)) => t,
+                    _ => return Err(parser.error(&format!("{} is not a table", name))),
+                };
+                for parent in parents {
+                    table = match table.entries.iter_mut().find(|(k, _)| k == parent) {
+                        Some((_, LuaValue::Table(t))) => t,
+                        _ => return Err(parser.error("Indexing a non-table value")),
+                    };
+                }
+                table.set(key.clone(), value);
+            }
+        }
+    }
+
+    Ok(globals)
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.src.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &[u8]) -> bool {
+        self.src[self.pos..].starts_with(s)
+    }
+
+    fn eat(&mut self, s: &[u8]) -> bool {
+        if self.starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &[u8]) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", String::from_utf8_lossy(s))))
+        }
+    }
+
+    fn error(&self, msg: &str) -> ParseError {
+        let line = self.src[..self.pos.min(self.src.len())]
+            .iter()
+            .filter(|c| **c == b'\n')
+            .count()
+            + 1;
+        ParseError::new(&format!("Lua line {}: {}", line, msg))
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            if self.eat(b"--") {
+                if self.long_string().is_none() {
+                    while self.peek().is_some_and(|c| c != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The level of the long bracket (`[[`, `[==[`, ...) starting here, if
+    /// there is one.
+    fn long_bracket_level(&self) -> Option<usize> {
+        let rest = &self.src[self.pos..];
+        if rest.first() != Some(&b'[') {
+            return None;
+        }
+        let level = rest[1..].iter().take_while(|c| **c == b'=').count();
+        (rest.get(level + 1) == Some(&b'[')).then_some(level)
+    }
+
+    /// Consumes the long string starting here, if there is one.
+    fn long_string(&mut self) -> Option<String> {
+        let level = self.long_bracket_level()?;
+        self.pos += level + 2;
+        let close = format!("]{}]", "=".repeat(level));
+        let start = self.pos;
+        while !self.at_end() && !self.starts_with(close.as_bytes()) {
+            self.pos += 1;
+        }
+        let body = String::from_utf8_lossy(&self.src[start..self.pos]).to_string();
+        self.pos = (self.pos + close.len()).min(self.src.len());
+        Some(body)
+    }
+
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos || self.src[start].is_ascii_digit() {
+            self.pos = start;
+            return Err(self.error("Expected identifier"));
+        }
+        Ok(String::from_utf8_lossy(&self.src[start..self.pos]).to_string())
+    }
+
+    fn value(&mut self) -> Result<LuaValue, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.table(),
+            Some(b'"') | Some(b'\'') => self.string().map(LuaValue::String),
+            Some(b'[') => match self.long_string() {
+                Some(body) => Ok(LuaValue::String(body)),
+                None => Err(self.error("Unexpected '['")),
+            },
+            Some(b'-') => {
+                self.pos += 1;
+                match self.value()? {
+                    LuaValue::Number(n) => Ok(LuaValue::Number(-n)),
+                    _ => Err(self.error("Cannot negate a non-number")),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == b'.' => self.number(),
+            Some(_) => match self.identifier()?.as_str() {
+                "true" => Ok(LuaValue::Bool(true)),
+                "false" => Ok(LuaValue::Bool(false)),
+                "nil" => Ok(LuaValue::Nil),
+                other => Err(self.error(&format!("Unsupported expression '{}'", other))),
+            },
+            None => Err(self.error("Unexpected end of file")),
+        }
+    }
+
+    fn table(&mut self) -> Result<LuaValue, ParseError> {
+        self.expect(b"{")?;
+        let mut table = LuaTable::default();
+        let mut index = 1;
+
+        loop {
+            self.skip_whitespace();
+            if self.eat(b"}") {
+                break;
+            }
+
+            if self.peek() == Some(b'[') && self.long_bracket_level().is_none() {
+                self.pos += 1;
+                let key = self.value()?;
+                self.expect(b"]")?;
+                self.expect(b"=")?;
+                let value = self.value()?;
+                table.set(key, value);
+            } else if let Some(key) = self.field_name() {
+                self.expect(b"=")?;
+                let value = self.value()?;
+                table.set(LuaValue::String(key), value);
+            } else {
+                let value = self.value()?;
+                table.set(LuaValue::Number(index as f64), value);
+                index += 1;
+            }
+
+            self.skip_whitespace();
+            if !self.eat(b",") && !self.eat(b";") {
+                self.expect(b"}")?;
+                break;
+            }
+        }
+
+        Ok(LuaValue::Table(table))
+    }
+
+    /// Consumes `name` if it is followed by `=` (but not `==`).
+    fn field_name(&mut self) -> Option<String> {
+        let start = self.pos;
+        let name = self.identifier().ok()?;
+        if matches!(name.as_str(), "true" | "false" | "nil") {
+            self.pos = start;
+            return None;
+        }
+        self.skip_whitespace();
+        if self.starts_with(b"=") && !self.starts_with(b"==") {
+            Some(name)
+        } else {
+            self.pos = start;
+            None
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        let quote = self.peek().unwrap_or_default();
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("Unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                c if c == quote => break,
+                b'\\' => bytes.push(self.escape()?),
+                c => bytes.push(c),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    /// Decodes the escape sequence after a backslash.
+    fn escape(&mut self) -> Result<u8, ParseError> {
+        let escaped = self
+            .peek()
+            .ok_or_else(|| self.error("Unterminated string"))?;
+        self.pos += 1;
+        Ok(match escaped {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'v' => 0x0b,
+            b'x' => {
+                let digits = self.src.get(self.pos..self.pos + 2).unwrap_or_default();
+                let byte = std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|d| u8::from_str_radix(d, 16).ok())
+                    .ok_or_else(|| self.error("Invalid \\x escape"))?;
+                self.pos += 2;
+                byte
+            }
+            // `\ddd`: up to three decimal digits.
+            c if c.is_ascii_digit() => {
+                let mut value = u32::from(c - b'0');
+                for _ in 0..2 {
+                    match self.peek() {
+                        Some(d) if d.is_ascii_digit() => {
+                            value = value * 10 + u32::from(d - b'0');
+                            self.pos += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                u8::try_from(value).map_err(|_| self.error("Decimal escape too large"))?
+            }
+            other => other,
+        })
+    }
+
+    fn number(&mut self) -> Result<LuaValue, ParseError> {
+        let start = self.pos;
+        if self.eat(b"0x") || self.eat(b"0X") {
+            let digits = self.pos;
+            while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                self.pos += 1;
+            }
+            let text = String::from_utf8_lossy(&self.src[digits..self.pos]);
+            return i64::from_str_radix(&text, 16)
+                .map(|n| LuaValue::Number(n as f64))
+                .map_err(|_| self.error("Invalid hex number"));
+        }
+        while let Some(c) = self.peek() {
+            let exponent_sign = (c == b'-' || c == b'+')
+                && matches!(self.src.get(self.pos - 1), Some(b'e') | Some(b'E'));
+            if c.is_ascii_digit() || c == b'.' || c == b'e' || c == b'E' || exponent_sign {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let text = String::from_utf8_lossy(&self.src[start..self.pos]);
+        text.parse::<f64>()
+            .map(LuaValue::Number)
+            .map_err(|_| self.error(&format!("Invalid number '{}'", text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn global(source: &str, name: &str) -> LuaValue {
+        parse_lua(source).unwrap().remove(name).unwrap()
+    }
+
+    fn string(s: &str) -> LuaValue {
+        LuaValue::String(s.to_string())
+    }
+
+    #[test]
+    fn positional_and_keyed_entries() {
+        let table = global(
+            r#"T = { "a", x = 1, ["y z"] = true, [10] = nil, "b" }"#,
+            "T",
+        );
+        let table = table.as_table().unwrap();
+        let entries: Vec<_> = table.iter().cloned().collect();
+        assert_eq!(
+            entries,
+            vec![
+                (LuaValue::Number(1.0), string("a")),
+                (string("x"), LuaValue::Number(1.0)),
+                (string("y z"), LuaValue::Bool(true)),
+                (LuaValue::Number(10.0), LuaValue::Nil),
+                (LuaValue::Number(2.0), string("b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_strings_as_values() {
+        let table = global("T = { [[one]], [==[two]]]==], k = [[three]] }", "T");
+        let values: Vec<_> = table.as_table().unwrap().values().cloned().collect();
+        assert_eq!(
+            values,
+            vec![string("one"), string("two]]"), string("three")]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let value = global(r#"S = "\065\66x\0\x41\"\\\n\t""#, "S");
+        assert_eq!(value, string("ABx\0A\"\\\n\t"));
+        assert_eq!(global(r"S = '\1234'", "S"), string("{4"));
+        assert!(parse_lua(r#"S = "\256""#).is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        let table = global("T = { 1, -2.5, 3e1, 1E-2, 0x1F, -0x10, .5 }", "T");
+        let numbers: Vec<f64> = table
+            .as_table()
+            .unwrap()
+            .values()
+            .map(|v| v.as_number().unwrap())
+            .collect();
+        assert_eq!(numbers, vec![1.0, -2.5, 30.0, 0.01, 31.0, -16.0, 0.5]);
+    }
+
+    #[test]
+    fn comments() {
+        let source = "-- line\nT = { --[[ block\n comment ]] 1, --[==[ x ]==] 2 } -- end";
+        assert_eq!(global(source, "T").as_table().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn field_and_index_assignments() {
+        let source = "local T = { A = {} }\nT.A.b = 1; T[\"c\"] = 'x'\nT[2] = {}";
+        let table = global(source, "T");
+        let table = table.as_table().unwrap();
+        let a = table.get("A").and_then(LuaValue::as_table).unwrap();
+        assert_eq!(a.get("b"), Some(&LuaValue::Number(1.0)));
+        assert_eq!(table.get("c"), Some(&string("x")));
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_lua("T = \"open").is_err());
+        assert!(parse_lua("T = { 1, 2").is_err());
+        assert!(parse_lua("T = f(1)").is_err());
+        assert!(parse_lua("x.y = 1").is_err());
+    }
+}
@@ -0,0 +1,107 @@
+/// 4x4 matrix in PSSG layout: row-vector convention, translation in row 3.
+pub type Matrix = [[f32; 4]; 4];
+
+pub const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub fn transpose(m: &Matrix) -> Matrix {
+    let mut out = [[0.0; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+    out
+}
+
+/// Flattens the matrix row by row, which for PSSG layout is column-major order.
+pub fn flatten(m: &Matrix) -> [f32; 16] {
+    let mut out = [0.0; 16];
+    for (i, row) in m.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(row);
+    }
+    out
+}
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+pub fn transform_point(p: &[f32; 3], m: &Matrix) -> [f32; 3] {
+    let mut out = [m[3][0], m[3][1], m[3][2]];
+    for (j, value) in out.iter_mut().enumerate() {
+        *value += p[0] * m[0][j] + p[1] * m[1][j] + p[2] * m[2][j];
+    }
+    out
+}
+
+pub fn transform_vector(v: &[f32; 3], m: &Matrix) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (j, value) in out.iter_mut().enumerate() {
+        *value = v[0] * m[0][j] + v[1] * m[1][j] + v[2] * m[2][j];
+    }
+    out
+}
+
+/// Determinant of the upper 3x3 (rotation and scale) part.
+pub fn determinant(m: &Matrix) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// The matrix that transforms normals under `m`: the inverse transpose of its
+/// upper 3x3 part, without translation.
+pub fn normal_matrix(m: &Matrix) -> Matrix {
+    let det = determinant(m);
+    if det == 0.0 {
+        return IDENTITY;
+    }
+    let c =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let mut out = IDENTITY;
+    out[0][0] = c(1, 2, 1, 2) / det;
+    out[0][1] = -c(1, 2, 0, 2) / det;
+    out[0][2] = c(1, 2, 0, 1) / det;
+    out[1][0] = -c(0, 2, 1, 2) / det;
+    out[1][1] = c(0, 2, 0, 2) / det;
+    out[1][2] = -c(0, 2, 0, 1) / det;
+    out[2][0] = c(0, 1, 1, 2) / det;
+    out[2][1] = -c(0, 1, 0, 2) / det;
+    out[2][2] = c(0, 1, 0, 1) / det;
+    out
+}
+
+pub fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length == 0.0 {
+        return v;
+    }
+    [v[0] / length, v[1] / length, v[2] / length]
+}
@@ -0,0 +1,186 @@
+use std::str::FromStr;
+
+use tracing::warn;
+
+use crate::{
+    entity::Entity,
+    error::ParseError,
+    object::{Object, Submesh},
+    scene::SceneNode,
+};
+
+/// Which face ranges a merged object keeps.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub enum SubmeshMode {
+    /// One range for all faces.
+    None,
+    /// One range per source object.
+    #[default]
+    Object,
+    /// One range per material; faces are grouped by material.
+    Material,
+}
+
+impl FromStr for SubmeshMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(SubmeshMode::None),
+            "object" => Ok(SubmeshMode::Object),
+            "material" => Ok(SubmeshMode::Material),
+            _ => Err(ParseError::new(&format!("Unknown submesh mode {}", s))),
+        }
+    }
+}
+
+impl Entity {
+    /// Concatenates all objects into one, rebasing the face indices.
+    ///
+    /// Geometry stays in each object's local space; use [`Entity::merge`] to
+    /// place it first. UVs and colors missing from some objects are filled
+    /// with defaults, while normals and skin data are only kept if every
+    /// object has them.
+    pub fn merged(&self, mode: SubmeshMode) -> Object {
+        let objects: Vec<&Object> = match mode {
+            SubmeshMode::Material => {
+                let mut materials: Vec<Option<&String>> = Vec::new();
+                for object in &self.objects {
+                    if !materials.contains(&object.material.as_ref()) {
+                        materials.push(object.material.as_ref());
+                    }
+                }
+                materials
+                    .into_iter()
+                    .flat_map(|m| {
+                        self.objects
+                            .iter()
+                            .filter(move |o| o.material.as_ref() == m)
+                    })
+                    .collect()
+            }
+            _ => self.objects.iter().collect(),
+        };
+
+        let complete =
+            |len: fn(&Object) -> usize| objects.iter().all(|o| len(o) == o.vertices.len());
+        let keep_normals = complete(|o| o.normals.len());
+        let keep_skin = complete(|o| o.joints.len()) && complete(|o| o.weights.len());
+        let keep_uvs = objects.iter().any(|o| !o.uvs.is_empty());
+        let keep_colors = objects.iter().any(|o| !o.colors.is_empty());
+
+        let mut merged = Object {
+            name: self
+                .objects
+                .first()
+                .map(|o| o.name.clone())
+                .unwrap_or_default(),
+            streams: Vec::new(),
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            faces: Vec::new(),
+            primitive: "triangles".to_string(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            material: self.objects.first().and_then(|o| o.material.clone()),
+            submeshes: Vec::new(),
+        };
+
+        for object in objects {
+            let offset = merged.vertices.len() as u32;
+            let count = object.vertices.len();
+            let start = merged.faces.len();
+
+            merged.vertices.extend(&object.vertices);
+            if keep_normals {
+                merged.normals.extend(&object.normals);
+            }
+            if keep_skin {
+                merged.joints.extend(&object.joints);
+                merged.weights.extend(&object.weights);
+            }
+            if keep_uvs {
+                merged.uvs.extend(fill(&object.uvs, count, [0.0; 2]));
+            }
+            if keep_colors {
+                merged.colors.extend(fill(&object.colors, count, [1.0; 4]));
+            }
+            merged
+                .faces
+                .extend(object.faces.iter().map(|f| f.map(|i| i + offset)));
+
+            let faces = start..merged.faces.len();
+            if faces.is_empty() {
+                continue;
+            }
+            match (mode, merged.submeshes.last_mut()) {
+                (SubmeshMode::None, _) => {}
+                (SubmeshMode::Material, Some(last)) if last.material == object.material => {
+                    last.faces.end = faces.end;
+                }
+                _ => merged.submeshes.push(Submesh {
+                    name: match mode {
+                        SubmeshMode::Material => object
+                            .material
+                            .clone()
+                            .unwrap_or_else(|| "default".to_string()),
+                        _ => object.name.clone(),
+                    },
+                    material: object.material.clone(),
+                    faces,
+                }),
+            }
+        }
+
+        if self
+            .objects
+            .iter()
+            .any(|o| o.normals.len() == o.vertices.len())
+            && !keep_normals
+        {
+            warn!(
+                "Dropping normals of {}: not every object has them",
+                merged.name
+            );
+        }
+        merged
+    }
+
+    /// Replaces the objects with their merged form. Transforms are baked
+    /// first so a single node can draw the result; a lone object is baked
+    /// too, so merged entities are always in world space.
+    pub fn merge(&mut self, mode: SubmeshMode) {
+        self.bake_transforms();
+        if self.objects.len() < 2 {
+            return;
+        }
+        let merged = self.merged(mode);
+        self.objects = vec![merged];
+        if let Some(root) = &mut self.root {
+            clear_objects(root);
+            root.objects.push(0);
+        }
+    }
+}
+
+fn fill<T: Copy, const N: usize>(
+    values: &[[T; N]],
+    count: usize,
+    default: [T; N],
+) -> impl Iterator<Item = [T; N]> + '_ {
+    values
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(default))
+        .take(count)
+}
+
+fn clear_objects(node: &mut SceneNode) {
+    node.objects.clear();
+    for child in &mut node.children {
+        clear_objects(child);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::{
+    entity::Entity,
+    error::ParseError,
+    math::{cross, dot, normalize, sub},
+    object::Object,
+};
+
+/// How face normals are weighted when averaged into a vertex normal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub enum NormalWeighting {
+    /// By face area; large faces dominate.
+    Area,
+    /// By the angle of the face at the vertex, independent of tessellation.
+    #[default]
+    Angle,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum NormalMode {
+    /// Averages the normals of the faces around each position. Faces that
+    /// meet at more than `crease_angle` degrees are not averaged together,
+    /// splitting the vertex so the edge stays sharp.
+    Smooth {
+        weighting: NormalWeighting,
+        crease_angle: Option<f32>,
+    },
+    /// One normal per face.
+    Flat,
+}
+
+impl Default for NormalMode {
+    fn default() -> Self {
+        NormalMode::Smooth {
+            weighting: NormalWeighting::Angle,
+            crease_angle: Some(30.0),
+        }
+    }
+}
+
+/// When normals are generated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub enum NormalGeneration {
+    /// Only for objects without a normal stream.
+    #[default]
+    Missing,
+    /// For every object, replacing decoded normals.
+    Always,
+    /// Never; objects without normals are written without them.
+    Never,
+}
+
+impl FromStr for NormalGeneration {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "missing" => Ok(NormalGeneration::Missing),
+            "always" => Ok(NormalGeneration::Always),
+            "never" => Ok(NormalGeneration::Never),
+            _ => Err(ParseError::new(&format!("Unknown normal generation {}", s))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct NormalOptions {
+    pub generate: NormalGeneration,
+    pub mode: NormalMode,
+}
+
+impl NormalOptions {
+    fn applies_to(&self, object: &Object) -> bool {
+        let generate = match self.generate {
+            NormalGeneration::Missing => object.normals.len() != object.vertices.len(),
+            NormalGeneration::Always => true,
+            NormalGeneration::Never => false,
+        };
+        generate && !object.faces.is_empty()
+    }
+}
+
+impl Object {
+    /// Computes normals from the faces, splitting vertices where corners of
+    /// the same vertex need different normals.
+    ///
+    /// Smoothing works on positions rather than vertex indices, so vertices
+    /// duplicated along UV seams end up with the same normal.
+    pub fn generate_normals(&mut self, mode: &NormalMode) {
+        let count = self.vertices.len();
+        if self.faces.iter().flatten().any(|&i| i as usize >= count) {
+            return;
+        }
+        let corners = self.corner_normals(mode);
+
+        // The normals already given to each vertex, with the vertex that got it.
+        let mut assigned: Vec<Vec<([f32; 3], u32)>> = vec![Vec::new(); count];
+        self.normals = vec![[0.0; 3]; count];
+        for (f, normals) in corners.iter().enumerate() {
+            for (k, normal) in normals.iter().enumerate() {
+                let v = self.faces[f][k] as usize;
+                let existing = assigned[v]
+                    .iter()
+                    .find(|(n, _)| close(n, normal))
+                    .map(|(_, i)| *i);
+                let index = match existing {
+                    Some(index) => index,
+                    None if assigned[v].is_empty() => {
+                        self.normals[v] = *normal;
+                        v as u32
+                    }
+                    None => {
+                        let index = self.duplicate_vertex(v);
+                        self.normals.push(*normal);
+                        index
+                    }
+                };
+                if existing.is_none() {
+                    assigned[v].push((*normal, index));
+                }
+                self.faces[f][k] = index;
+            }
+        }
+    }
+
+    /// The normal of every face corner.
+    fn corner_normals(&self, mode: &NormalMode) -> Vec<[[f32; 3]; 3]> {
+        let face_normals: Vec<[f32; 3]> = self
+            .faces
+            .iter()
+            .map(|f| {
+                let [a, b, c] = f.map(|i| self.vertices[i as usize]);
+                cross(sub(b, a), sub(c, a))
+            })
+            .collect();
+
+        let (weighting, crease_angle) = match mode {
+            NormalMode::Flat => {
+                return face_normals.iter().map(|n| [normalize(*n); 3]).collect();
+            }
+            NormalMode::Smooth {
+                weighting,
+                crease_angle,
+            } => (weighting, crease_angle),
+        };
+        let crease_cos = crease_angle.map(|a| a.to_radians().cos());
+
+        // Vertices sharing a position share their faces.
+        let mut positions: HashMap<[u32; 3], usize> = HashMap::new();
+        let groups: Vec<usize> = self
+            .vertices
+            .iter()
+            .map(|p| {
+                let next = positions.len();
+                *positions.entry(p.map(f32::to_bits)).or_insert(next)
+            })
+            .collect();
+        let mut incident: Vec<Vec<(usize, usize)>> = vec![Vec::new(); positions.len()];
+        for (f, face) in self.faces.iter().enumerate() {
+            for (k, &v) in face.iter().enumerate() {
+                incident[groups[v as usize]].push((f, k));
+            }
+        }
+
+        let unit: Vec<[f32; 3]> = face_normals.iter().map(|n| normalize(*n)).collect();
+        let weighted = |f: usize, k: usize| -> [f32; 3] {
+            match weighting {
+                NormalWeighting::Area => face_normals[f],
+                NormalWeighting::Angle => {
+                    let face = self.faces[f].map(|i| self.vertices[i as usize]);
+                    let angle = corner_angle(face[k], face[(k + 1) % 3], face[(k + 2) % 3]);
+                    unit[f].map(|v| v * angle)
+                }
+            }
+        };
+
+        self.faces
+            .iter()
+            .enumerate()
+            .map(|(f, face)| {
+                let mut normals = [[0.0; 3]; 3];
+                for (k, &v) in face.iter().enumerate() {
+                    let mut sum = [0.0; 3];
+                    for &(g, kk) in &incident[groups[v as usize]] {
+                        if crease_cos.is_some_and(|c| dot(unit[f], unit[g]) < c) {
+                            continue;
+                        }
+                        let n = weighted(g, kk);
+                        sum = [sum[0] + n[0], sum[1] + n[1], sum[2] + n[2]];
+                    }
+                    normals[k] = normalize(sum);
+                }
+                normals
+            })
+            .collect()
+    }
+
+    /// Appends a copy of vertex `v` with all its per-vertex attributes except
+    /// the normal, returning the new index.
+    fn duplicate_vertex(&mut self, v: usize) -> u32 {
+        let count = self.vertices.len();
+        if self.colors.len() == count {
+            self.colors.push(self.colors[v]);
+        }
+        if self.uvs.len() == count {
+            self.uvs.push(self.uvs[v]);
+        }
+        if self.joints.len() == count {
+            self.joints.push(self.joints[v]);
+        }
+        if self.weights.len() == count {
+            self.weights.push(self.weights[v]);
+        }
+        self.vertices.push(self.vertices[v]);
+        count as u32
+    }
+}
+
+impl Entity {
+    /// Generates normals as `options` asks, returning how many objects got
+    /// new normals.
+    pub fn apply_normals(&mut self, options: &NormalOptions) -> usize {
+        let mut generated = 0;
+        for object in &mut self.objects {
+            if options.applies_to(object) {
+                object.generate_normals(&options.mode);
+                generated += 1;
+            }
+        }
+        generated
+    }
+
+    /// Whether [`Entity::apply_normals`] would change any object.
+    pub fn needs_normals(&self, options: &NormalOptions) -> bool {
+        self.objects.iter().any(|o| options.applies_to(o))
+    }
+}
+
+fn close(a: &[f32; 3], b: &[f32; 3]) -> bool {
+    a.iter().zip(b).all(|(a, b)| (a - b).abs() <= 1e-5)
+}
+
+/// The angle at `corner` of the triangle (`corner`, `a`, `b`).
+fn corner_angle(corner: [f32; 3], a: [f32; 3], b: [f32; 3]) -> f32 {
+    let a = normalize(sub(a, corner));
+    let b = normalize(sub(b, corner));
+    dot(a, b).clamp(-1.0, 1.0).acos()
+}
@@ -1,33 +1,214 @@
-use tracing::warn;
+use std::ops::Range;
+use std::slice::ChunksExact;
 
 use crate::{
     error::ParseError,
-    parse::read,
+    math::{determinant, normal_matrix, normalize, transform_point, transform_vector, Matrix},
+    parse::{read, stream_format, Attribute, ParseOptions},
     structure::{DataBlock, RenderDataSource, RenderIndexSource},
 };
 
 type Vertices = Vec<[f32; 3]>;
+type Normals = Vec<[f32; 3]>;
+type Colors = Vec<[f32; 4]>;
 type UVs = Vec<[f32; 2]>;
 type Faces = Vec<[u32; 3]>;
+type Joints = Vec<[u32; 4]>;
+type Weights = Vec<[f32; 4]>;
 
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct StreamInfo {
+    pub block_id: String,
+    pub render_type: String,
+    pub data_type: String,
+}
+
+/// A range of faces with its own name and material, as kept by
+/// [`Entity::merged`](crate::Entity::merged).
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Submesh {
+    pub name: String,
+    pub material: Option<String>,
+    pub faces: Range<usize>,
+}
+
+impl Submesh {
+    pub fn new(name: &str, material: Option<&str>, faces: Range<usize>) -> Self {
+        Self {
+            name: name.to_string(),
+            material: material.map(str::to_string),
+            faces,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct Object {
+    pub name: String,
+    pub streams: Vec<StreamInfo>,
     pub vertices: Vertices,
+    pub normals: Normals,
+    pub colors: Colors,
     pub uvs: UVs,
     pub faces: Faces,
+    pub primitive: String,
+    pub joints: Joints,
+    pub weights: Weights,
+    pub material: Option<String>,
+    /// Face ranges of merged objects; empty if the object is one mesh.
+    pub submeshes: Vec<Submesh>,
+}
+
+/// The largest value of an unsigned normalized integer type, or `None` for
+/// floating point types.
+pub(crate) fn unorm_scale(data_type: &str) -> Option<f32> {
+    match data_type {
+        "uchar" => Some(u8::MAX as f32),
+        "ushort" => Some(u16::MAX as f32),
+        _ => None,
+    }
+}
+
+/// Warns about an optional stream that failed to decode.
+fn skip_stream(
+    block: &DataBlock,
+    error: ParseError,
+    options: &ParseOptions,
+    warnings: &mut Vec<String>,
+) -> Result<(), ParseError> {
+    options.warn(
+        warnings,
+        format!(
+            "Skipping {} stream {}: {}",
+            block.stream.render_type,
+            block.id,
+            error.message()
+        ),
+    )
+}
+
+/// The name objects decoded from `source` get: the index source id, or
+/// else the data source id.
+pub(crate) fn source_name(source: &RenderDataSource) -> Option<&str> {
+    source.index_source.id.as_deref().or(source.id.as_deref())
 }
 
 impl Object {
-    pub fn new(blocks: &[DataBlock], sources: RenderDataSource) -> Result<Self, ParseError> {
-        let (vertices, uvs, faces) = Self::decode(blocks, sources)?;
-        Ok(Self {
+    /// A triangle mesh with no other vertex data; fill in the attribute
+    /// fields to add normals, UVs, colors or skinning.
+    pub fn new(name: &str, vertices: Vertices, faces: Faces) -> Self {
+        Self {
+            name: name.to_string(),
+            streams: Vec::new(),
             vertices,
-            uvs,
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
             faces,
-        })
+            primitive: "triangles".to_string(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            material: None,
+            submeshes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_source(
+        blocks: &[DataBlock],
+        source: RenderDataSource,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, ParseError> {
+        let name = source_name(&source).unwrap_or_default().to_string();
+        let mut object = Self {
+            name,
+            streams: Vec::new(),
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            faces: Vec::new(),
+            primitive: source.index_source.primitive.clone(),
+            joints: Vec::new(),
+            weights: Vec::new(),
+            material: None,
+            submeshes: Vec::new(),
+        };
+        object.decode(blocks, source, options, warnings)?;
+        Ok(object)
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    /// Whether every vertex has a normal.
+    pub fn has_normals(&self) -> bool {
+        !self.normals.is_empty() && self.normals.len() == self.vertices.len()
+    }
+
+    /// Whether every vertex has a UV.
+    pub fn has_uvs(&self) -> bool {
+        !self.uvs.is_empty() && self.uvs.len() == self.vertices.len()
     }
 
+    /// Whether every vertex has a color.
+    pub fn has_colors(&self) -> bool {
+        !self.colors.is_empty() && self.colors.len() == self.vertices.len()
+    }
+
+    pub fn is_skinned(&self) -> bool {
+        !self.joints.is_empty() && self.joints.len() == self.weights.len()
+    }
+
+    /// The face ranges drawn separately: the submeshes, or all faces at once.
+    pub fn face_ranges(&self) -> Vec<Range<usize>> {
+        if self.submeshes.is_empty() {
+            std::iter::once(0..self.faces.len()).collect()
+        } else {
+            self.submeshes.iter().map(|s| s.faces.clone()).collect()
+        }
+    }
+
+    /// Transforms positions and normals by `matrix`, reversing the winding of
+    /// the faces if it mirrors the geometry.
+    pub fn transform(&mut self, matrix: &Matrix) {
+        for vertex in &mut self.vertices {
+            *vertex = transform_point(vertex, matrix);
+        }
+        let normals = normal_matrix(matrix);
+        for normal in &mut self.normals {
+            *normal = normalize(transform_vector(normal, &normals));
+        }
+        if determinant(matrix) < 0.0 {
+            for face in &mut self.faces {
+                face.swap(1, 2);
+            }
+        }
+    }
+
+    /// OBJ text without materials; submeshes become groups.
     pub fn to_obj(&self) -> String {
+        self.obj(None)
+    }
+
+    /// OBJ text whose submeshes use the materials of the `mtllib` file.
+    pub fn to_obj_with_mtllib(&self, mtllib: &str) -> String {
+        self.obj(Some(mtllib))
+    }
+
+    fn obj(&self, mtllib: Option<&str>) -> String {
         let mut obj = String::new();
+        if let Some(mtllib) = mtllib {
+            obj.push_str(&format!("mtllib {}\n", mtllib));
+        }
         obj.push_str(&format!("# Vertices: {}\n", self.vertices.len()));
         for vertex in &self.vertices {
             obj.push_str(&format!("v {} {} {}\n", vertex[0], vertex[1], vertex[2]));
@@ -38,47 +219,116 @@ impl Object {
             obj.push_str(&format!("vt {} {}\n", uv[0], uv[1]));
         }
 
+        let has_normals = !self.normals.is_empty() && self.normals.len() == self.vertices.len();
+        if has_normals {
+            obj.push_str(&format!("\n# Normals: {}\n", self.normals.len()));
+            for normal in &self.normals {
+                obj.push_str(&format!("vn {} {} {}\n", normal[0], normal[1], normal[2]));
+            }
+        }
+        let corner = |i: u32| {
+            let i = i + 1;
+            match (has_normals, self.uvs.len() == self.vertices.len()) {
+                (false, _) => i.to_string(),
+                (true, true) => format!("{0}/{0}/{0}", i),
+                (true, false) => format!("{0}//{0}", i),
+            }
+        };
+
         obj.push_str(&format!("\n# Faces: {}\n", self.faces.len()));
-        for face in &self.faces {
+        for (i, face) in self.faces.iter().enumerate() {
+            if let Some(submesh) = self.submeshes.iter().find(|s| s.faces.start == i) {
+                obj.push_str(&format!("g {}\n", submesh.name));
+                if let Some(material) = submesh.material.as_ref().filter(|_| mtllib.is_some()) {
+                    obj.push_str(&format!("usemtl {}\n", material));
+                }
+            }
             obj.push_str(&format!(
                 "f {} {} {}\n",
-                face[0] + 1,
-                face[1] + 1,
-                face[2] + 1
+                corner(face[0]),
+                corner(face[1]),
+                corner(face[2])
             ));
         }
         obj
     }
 
     fn decode(
+        &mut self,
         blocks: &[DataBlock],
         source: RenderDataSource,
-    ) -> Result<(Vertices, UVs, Faces), ParseError> {
-        let mut vertices = Vec::new();
-        let mut uvs = Vec::new();
-        let mut faces = Vec::new();
-
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), ParseError> {
         for render_stream in &source.streams {
             let block_id = render_stream.data_block.replace("#", "");
             if let Some(block) = blocks.iter().find(|b| b.id == block_id) {
+                let attribute = match block.stream.render_type.as_str() {
+                    "Normal" | "SkinnableNormal" => Some(Attribute::Normal),
+                    "Color" => Some(Attribute::Color),
+                    "ST" => Some(Attribute::Uv),
+                    "SkinIndices" | "SkinWeights" => Some(Attribute::Skin),
+                    _ => None,
+                };
+                if attribute.is_some_and(|a| !options.decodes(a)) {
+                    continue;
+                }
+                let stream = StreamInfo {
+                    block_id: block.id.clone(),
+                    render_type: block.stream.render_type.clone(),
+                    data_type: block.stream.data_type.clone(),
+                };
                 match block.stream.render_type.as_str() {
                     "Vertex" | "SkinnableVertex" => {
-                        vertices.extend(Self::decode_vertices(block)?);
+                        self.vertices
+                            .extend(Self::decode_vertices(block, options, warnings)?);
+                    }
+                    // Normals and colors are optional: a stream that can't be
+                    // decoded is skipped, and normals are generated instead.
+                    "Normal" | "SkinnableNormal" => {
+                        match Self::decode_normals(block, options, warnings) {
+                            Ok(normals) => self.normals.extend(normals),
+                            Err(e) => {
+                                skip_stream(block, e, options, warnings)?;
+                                continue;
+                            }
+                        }
                     }
+                    "Color" => match Self::decode_colors(block, options, warnings) {
+                        Ok(colors) => self.colors.extend(colors),
+                        Err(e) => {
+                            skip_stream(block, e, options, warnings)?;
+                            continue;
+                        }
+                    },
                     "ST" => {
-                        uvs.extend(Self::decode_uvs(block)?);
+                        self.uvs.extend(Self::decode_uvs(block, options, warnings)?);
+                    }
+                    "SkinIndices" => {
+                        self.joints
+                            .extend(Self::decode_skin_indices(block, options, warnings)?);
+                    }
+                    "SkinWeights" => {
+                        self.weights
+                            .extend(Self::decode_skin_weights(block, options, warnings)?);
                     }
                     _ => {}
                 }
+                self.streams.push(stream);
             }
         }
 
-        faces.extend(Self::decode_faces(&source.index_source)?);
+        self.faces
+            .extend(Self::decode_faces(&source.index_source, options, warnings)?);
 
-        Ok((vertices, uvs, faces))
+        Ok(())
     }
 
-    fn decode_vertices(block: &DataBlock) -> Result<Vertices, ParseError> {
+    fn decode_vertices(
+        block: &DataBlock,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vertices, ParseError> {
         let data: Vec<f32> = read(
             &block
                 .data
@@ -88,14 +338,88 @@ impl Object {
             "float",
         )?;
 
-        let vertices: Vec<[f32; 3]> = data.chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
-        if block.element_count != vertices.len() {
-            return Err(ParseError::new("Vertex count does not match element count"));
-        }
+        let vertices: Vec<[f32; 3]> = elements(&data, 3, "Vertex")?
+            .map(|c| [c[0], c[1], c[2]])
+            .collect();
+        check_count(block, vertices.len(), "Vertex", options, warnings)?;
         Ok(vertices)
     }
 
-    fn decode_uvs(block: &DataBlock) -> Result<UVs, ParseError> {
+    fn decode_normals(
+        block: &DataBlock,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Normals, ParseError> {
+        let (data_type, count) = stream_format(&block.stream.data_type)?;
+        if count < 3 {
+            return Err(ParseError::new(&format!(
+                "Unsupported normal data type {}",
+                block.stream.data_type
+            )));
+        }
+        let data: Vec<f32> = read(
+            &block
+                .data
+                .as_ref()
+                .ok_or_else(|| ParseError::new("Missing normal block data"))?
+                .text,
+            data_type,
+        )?;
+
+        // Integer normals are unsigned normalized values mapped to [-1, 1].
+        let normals: Normals = match unorm_scale(data_type) {
+            Some(scale) => elements(&data, count, "Normal")?
+                .map(|c| normalize([c[0], c[1], c[2]].map(|v| v / scale * 2.0 - 1.0)))
+                .collect(),
+            None => elements(&data, count, "Normal")?
+                .map(|c| [c[0], c[1], c[2]])
+                .collect(),
+        };
+        check_count(block, normals.len(), "Normal", options, warnings)?;
+        Ok(normals)
+    }
+
+    fn decode_colors(
+        block: &DataBlock,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Colors, ParseError> {
+        let (data_type, count) = stream_format(&block.stream.data_type)?;
+        let data: Vec<f32> = read(
+            &block
+                .data
+                .as_ref()
+                .ok_or_else(|| ParseError::new("Missing color block data"))?
+                .text,
+            data_type,
+        )?;
+
+        let colors: Colors = if block.stream.data_type == "uint_color_argb" {
+            elements(&data, 4, "Color")?
+                .map(|c| [c[1] / 255.0, c[2] / 255.0, c[3] / 255.0, c[0] / 255.0])
+                .collect()
+        } else {
+            let scale = unorm_scale(data_type).unwrap_or(1.0);
+            elements(&data, count, "Color")?
+                .map(|c| {
+                    let mut color = [1.0; 4];
+                    for (o, v) in color.iter_mut().zip(c) {
+                        *o = *v / scale;
+                    }
+                    color
+                })
+                .collect()
+        };
+
+        check_count(block, colors.len(), "Color", options, warnings)?;
+        Ok(colors)
+    }
+
+    fn decode_uvs(
+        block: &DataBlock,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<UVs, ParseError> {
         let data: Vec<f32> = read(
             &block
                 .data
@@ -106,22 +430,79 @@ impl Object {
         )?;
 
         let uvs: Vec<[f32; 2]> = if block.stream.data_type == "half2" {
-            data.chunks(2).map(|c| [c[0], c[1]]).collect()
+            elements(&data, 2, "UV")?.map(|c| [c[0], c[1]]).collect()
         } else {
-            data.chunks(4).map(|c| [c[0], c[1]]).collect()
+            elements(&data, 4, "UV")?.map(|c| [c[0], c[1]]).collect()
         };
 
-        if block.element_count != uvs.len() {
-            return Err(ParseError::new(&format!(
-                "UV count {} does not match element count {}",
-                uvs.len(),
-                block.element_count
-            )));
-        }
+        check_count(block, uvs.len(), "UV", options, warnings)?;
         Ok(uvs)
     }
 
-    fn decode_faces(source: &RenderIndexSource) -> Result<Faces, ParseError> {
+    fn decode_skin_indices(
+        block: &DataBlock,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Joints, ParseError> {
+        let (data_type, count) = stream_format(&block.stream.data_type)?;
+        let data: Vec<u32> = read(
+            &block
+                .data
+                .as_ref()
+                .ok_or_else(|| ParseError::new("Missing skin index block data"))?
+                .text,
+            data_type,
+        )?;
+
+        let joints: Joints = elements(&data, count, "Skin index")?
+            .map(|c| {
+                let mut joint = [0; 4];
+                for (j, v) in joint.iter_mut().zip(c) {
+                    *j = *v;
+                }
+                joint
+            })
+            .collect();
+
+        check_count(block, joints.len(), "Skin index", options, warnings)?;
+        Ok(joints)
+    }
+
+    fn decode_skin_weights(
+        block: &DataBlock,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Weights, ParseError> {
+        let (data_type, count) = stream_format(&block.stream.data_type)?;
+        let data: Vec<f32> = read(
+            &block
+                .data
+                .as_ref()
+                .ok_or_else(|| ParseError::new("Missing skin weight block data"))?
+                .text,
+            data_type,
+        )?;
+        let scale = unorm_scale(data_type).unwrap_or(1.0);
+
+        let weights: Weights = elements(&data, count, "Skin weight")?
+            .map(|c| {
+                let mut weight = [0.0; 4];
+                for (w, v) in weight.iter_mut().zip(c) {
+                    *w = *v / scale;
+                }
+                weight
+            })
+            .collect();
+
+        check_count(block, weights.len(), "Skin weight", options, warnings)?;
+        Ok(weights)
+    }
+
+    fn decode_faces(
+        source: &RenderIndexSource,
+        options: &ParseOptions,
+        warnings: &mut Vec<String>,
+    ) -> Result<Faces, ParseError> {
         let data = read(
             &source
                 .index_data
@@ -130,27 +511,57 @@ impl Object {
                 .text,
             &source.data_type,
         )?;
-        let mut faces = Vec::new();
+        let faces: Faces = data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
 
-        for i in (0..data.len()).step_by(3) {
-            faces.push([data[i], data[i + 1], data[i + 2]]);
-        }
-        if source.count % 3 != 0 {
-            warn!(
-                "{}",
-                format!("Face count ({}) is not a multiple of 3", source.count)
-            );
+        if !source.count.is_multiple_of(3) {
+            let warning = format!("Face count ({}) is not a multiple of 3", source.count);
+            options.count_mismatch_found(warnings, warning, false)?;
         }
         if source.count / 3 != faces.len() {
-            warn!(
-                "{}",
-                format!(
-                    "Face count ({}) does not match face data ({})",
-                    source.count / 3,
-                    faces.len()
-                )
+            let warning = format!(
+                "Face count ({}) does not match face data ({})",
+                source.count / 3,
+                faces.len()
             );
+            options.count_mismatch_found(warnings, warning, false)?;
         }
         Ok(faces)
     }
 }
+
+/// Splits decoded stream data into the `count` values of each element,
+/// failing if the data ends partway through one.
+fn elements<'a, T>(
+    data: &'a [T],
+    count: usize,
+    name: &str,
+) -> Result<ChunksExact<'a, T>, ParseError> {
+    let chunks = data.chunks_exact(count);
+    if !chunks.remainder().is_empty() {
+        return Err(ParseError::new(&format!(
+            "{} data has {} values, which is not a multiple of {}",
+            name,
+            data.len(),
+            count
+        )));
+    }
+    Ok(chunks)
+}
+
+/// Checks a decoded stream against the `elementCount` of its data block.
+fn check_count(
+    block: &DataBlock,
+    count: usize,
+    name: &str,
+    options: &ParseOptions,
+    warnings: &mut Vec<String>,
+) -> Result<(), ParseError> {
+    if block.element_count == count {
+        return Ok(());
+    }
+    let message = format!(
+        "{} count {} does not match element count {}",
+        name, count, block.element_count
+    );
+    options.count_mismatch_found(warnings, message, true)
+}
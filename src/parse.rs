@@ -1,22 +1,291 @@
+use tracing::warn;
+
 use crate::error::ParseError;
+use crate::xml::Element;
+
+/// Byte order of hex-encoded payloads. Files from pssgConverter and binary
+/// PSSG files of the PS3 game are big-endian.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Validation {
+    /// Skips data blocks and index sources without data and keeps going
+    /// with a warning where the file is inconsistent.
+    #[default]
+    Lenient,
+    /// Fails on every warning, on streams or index sources without data and
+    /// on anything [`Entity::validate`](crate::Entity::validate) reports.
+    Strict,
+}
+
+/// What happens when a count in the file disagrees with the data it
+/// describes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CountMismatch {
+    /// Every mismatch is an error.
+    Error,
+    /// A data block whose `elementCount` disagrees with its data is an
+    /// error, as its elements can't be matched to vertices; index and
+    /// `TYPEINFO` counts are warnings.
+    #[default]
+    ErrorOnStreams,
+    /// Every mismatch is a warning and the decoded data is kept as is.
+    Warn,
+}
+
+/// Optional vertex attributes. Positions and faces are always decoded.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum Attribute {
+    Normal,
+    Color,
+    Uv,
+    /// Joint indices and weights.
+    Skin,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 4] = [
+        Attribute::Normal,
+        Attribute::Color,
+        Attribute::Uv,
+        Attribute::Skin,
+    ];
+}
+
+/// Controls how [`parse_with`](crate::parse_with) decodes a file. The
+/// default matches [`parse`](crate::parse).
+///
+/// ```no_run
+/// use journey_model_parser::{parse_with, Attribute, ParseOptions, Validation};
+///
+/// let options = ParseOptions::new()
+///     .validation(Validation::Strict)
+///     .attributes([Attribute::Uv])
+///     .skeletons(false);
+/// let entity = parse_with("P_Rock.xml".as_ref(), &options)?;
+/// # Ok::<(), journey_model_parser::ParseError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    validation: Validation,
+    count_mismatch: CountMismatch,
+    endianness: Endianness,
+    attributes: Vec<Attribute>,
+    libraries: Option<Vec<String>>,
+    skeletons: bool,
+    materials: bool,
+    render_node: Option<String>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            validation: Validation::Lenient,
+            count_mismatch: CountMismatch::ErrorOnStreams,
+            endianness: Endianness::Big,
+            attributes: Attribute::ALL.to_vec(),
+            libraries: None,
+            skeletons: true,
+            materials: true,
+            render_node: None,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    pub fn count_mismatch(mut self, count_mismatch: CountMismatch) -> Self {
+        self.count_mismatch = count_mismatch;
+        self
+    }
+
+    /// Byte order of hex payloads: data blocks, index sources, transforms
+    /// and bounding boxes.
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Decodes only these optional attributes; the streams of the others
+    /// are left out of the objects.
+    pub fn attributes(mut self, attributes: impl IntoIterator<Item = Attribute>) -> Self {
+        self.attributes = attributes.into_iter().collect();
+        self
+    }
+
+    /// Reads only the libraries of these types, such as `SEGMENTSET`,
+    /// `RENDERINTERFACEBOUND`, `SHADERINSTANCE` or `NODE`; all by default.
+    pub fn libraries<S: Into<String>>(mut self, libraries: impl IntoIterator<Item = S>) -> Self {
+        self.libraries = Some(libraries.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Whether skeletons are read into [`Entity::skin`](crate::Entity::skin).
+    pub fn skeletons(mut self, skeletons: bool) -> Self {
+        self.skeletons = skeletons;
+        self
+    }
+
+    /// Whether shader instances are read into materials and assigned to
+    /// objects.
+    pub fn materials(mut self, materials: bool) -> Self {
+        self.materials = materials;
+        self
+    }
+
+    /// Takes the entity's translation from the `RENDERNODE` with this id,
+    /// wherever it sits in the node tree, instead of the first one.
+    pub fn render_node(mut self, id: impl Into<String>) -> Self {
+        self.render_node = Some(id.into());
+        self
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.validation == Validation::Strict
+    }
+
+    pub(crate) fn decodes(&self, attribute: Attribute) -> bool {
+        self.attributes.contains(&attribute)
+    }
+
+    pub(crate) fn includes_library(&self, library_type: &str) -> bool {
+        self.libraries
+            .as_ref()
+            .is_none_or(|l| l.iter().any(|t| t == library_type))
+    }
+
+    pub(crate) fn loads_skeletons(&self) -> bool {
+        self.skeletons
+    }
+
+    pub(crate) fn loads_materials(&self) -> bool {
+        self.materials
+    }
+
+    pub(crate) fn translation_node(&self) -> Option<&str> {
+        self.render_node.as_deref()
+    }
+
+    pub(crate) fn is_little_endian(&self) -> bool {
+        self.endianness == Endianness::Little
+    }
+
+    /// Records a warning, or fails with it when validation is strict.
+    pub(crate) fn warn(
+        &self,
+        warnings: &mut Vec<String>,
+        warning: String,
+    ) -> Result<(), ParseError> {
+        if self.is_strict() {
+            return Err(ParseError::new(&warning));
+        }
+        warn!("{}", warning);
+        warnings.push(warning);
+        Ok(())
+    }
+
+    /// Handles a count that disagrees with its data; `stream` is set for
+    /// data block element counts.
+    pub(crate) fn count_mismatch_found(
+        &self,
+        warnings: &mut Vec<String>,
+        message: String,
+        stream: bool,
+    ) -> Result<(), ParseError> {
+        match self.count_mismatch {
+            CountMismatch::Error => Err(ParseError::new(&message)),
+            CountMismatch::ErrorOnStreams if stream => Err(ParseError::new(&message)),
+            _ => self.warn(warnings, message),
+        }
+    }
+}
 
 pub fn read<T>(data: &str, data_type: &str) -> Result<Vec<T>, ParseError>
 where
     T: From<u8> + From<u16> + std::str::FromStr,
     <T as std::str::FromStr>::Err: std::fmt::Display,
 {
-    if data
-        .chars()
+    if is_hex(data) {
+        return read_hex(data, data_type);
+    }
+    read_decimal(data)
+}
+
+/// How a payload is written in the XML, so it can be re-encoded the same way.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Hex { lowercase: bool },
+    Decimal,
+}
+
+impl Encoding {
+    pub fn of(data: &str) -> Self {
+        if is_hex(data) {
+            Encoding::Hex {
+                lowercase: data.chars().any(|c| c.is_ascii_lowercase()),
+            }
+        } else {
+            Encoding::Decimal
+        }
+    }
+}
+
+pub fn write<T>(values: &[T], data_type: &str, encoding: Encoding) -> Result<String, ParseError>
+where
+    T: Into<f64> + Copy,
+{
+    match encoding {
+        Encoding::Hex { lowercase } => write_hex(values, data_type, lowercase),
+        Encoding::Decimal => Ok(write_decimal(values, data_type)),
+    }
+}
+
+fn is_hex(data: &str) -> bool {
+    data.chars()
         .all(|c| c.is_ascii_hexdigit() || c.is_whitespace())
         && data.split_whitespace().all(|s| {
             s.len() == 2
                 && !((s.len() > 1 && s.starts_with("0") && s.chars().all(|c| c.is_ascii_digit()))
                     && (data.contains("e+") || data.contains("e-")))
         })
-    {
-        return read_hex(data, data_type);
-    }
-    read_decimal(data)
+}
+
+/// Splits a stream data type such as `float3` or `half2` into the scalar type
+/// understood by [`read`] and its component count.
+pub fn stream_format(data_type: &str) -> Result<(&'static str, usize), ParseError> {
+    let format = match data_type {
+        "uint_color_argb" | "uchar4n" => ("uchar", 4),
+        _ => {
+            let base = data_type.trim_end_matches(|c: char| c.is_ascii_digit());
+            let count = data_type[base.len()..].parse::<usize>().unwrap_or(1);
+            let base = match base {
+                "float" => "float",
+                "half" => "half",
+                "uchar" => "uchar",
+                "ushort" => "ushort",
+                _ => return Err(ParseError::new(&format!("Unknown data type {}", data_type))),
+            };
+            if count == 0 {
+                return Err(ParseError::new(&format!("Invalid data type {}", data_type)));
+            }
+            (base, count)
+        }
+    };
+    Ok(format)
 }
 
 fn read_hex<T>(data: &str, data_type: &str) -> Result<Vec<T>, ParseError>
@@ -70,6 +339,60 @@ where
         .collect()
 }
 
+fn write_hex<T>(values: &[T], data_type: &str, lowercase: bool) -> Result<String, ParseError>
+where
+    T: Into<f64> + Copy,
+{
+    let mut bytes = Vec::new();
+    for value in values {
+        let value: f64 = (*value).into();
+        match data_type {
+            "uchar" => bytes.push(value.round().clamp(0.0, u8::MAX as f64) as u8),
+            "ushort" => {
+                if value < 0.0 || value > u16::MAX as f64 {
+                    return Err(ParseError::new(&format!(
+                        "Value {} does not fit into ushort",
+                        value
+                    )));
+                }
+                bytes.extend((value as u16).to_be_bytes());
+            }
+            "half" => bytes.extend(f32_to_half(value as f32).to_be_bytes()),
+            "float" => bytes.extend((value as f32).to_be_bytes()),
+            _ => return Err(ParseError::new(&format!("Unknown data type {}", data_type))),
+        }
+    }
+
+    Ok(bytes
+        .iter()
+        .map(|b| {
+            if lowercase {
+                format!("{:02x}", b)
+            } else {
+                format!("{:02X}", b)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+fn write_decimal<T>(values: &[T], data_type: &str) -> String
+where
+    T: Into<f64> + Copy,
+{
+    values
+        .iter()
+        .map(|v| {
+            let v: f64 = (*v).into();
+            match data_type {
+                "uchar" | "ushort" => format!("{}", v.round() as i64),
+                _ => format!("{}", v as f32),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn read_decimal<T>(data: &str) -> Result<Vec<T>, ParseError>
 where
     T: std::str::FromStr,
@@ -97,3 +420,74 @@ fn half_to_f32(half: u16) -> f32 {
 
     f32::from_bits(bits)
 }
+
+fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exp = exponent - 127 + 15;
+    if exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exp <= 0 {
+        if exp < -10 {
+            return sign;
+        }
+        let mantissa = (mantissa | 0x80_0000) >> (1 - exp);
+        return sign | ((mantissa + 0x1000) >> 13) as u16;
+    }
+
+    let half = sign as u32 | ((exp as u32) << 10) | (mantissa >> 13);
+    // round to nearest, carrying into the exponent if necessary
+    (half + ((mantissa >> 12) & 1)) as u16
+}
+
+/// Rewrites little-endian hex payloads below `element` as big-endian, the
+/// byte order the rest of the parser reads. Swapping is its own inverse, so
+/// this also turns big-endian payloads back into little-endian ones.
+pub(crate) fn to_big_endian(element: &mut Element) {
+    element.visit_mut(&mut |e| {
+        let (data_type, data) = match e.name.as_str() {
+            "TRANSFORM" | "BOUNDINGBOX" => {
+                swap_bytes(&mut e.text, 4);
+                return;
+            }
+            "DATABLOCK" => (
+                e.child("DATABLOCKSTREAM")
+                    .and_then(|s| s.attribute("dataType"))
+                    .and_then(|t| stream_format(t).ok())
+                    .map(|(t, _)| t),
+                "DATABLOCKDATA",
+            ),
+            "RENDERINDEXSOURCE" => (e.attribute("format"), "INDEXSOURCEDATA"),
+            _ => return,
+        };
+        let stride = match data_type {
+            Some("float") => 4,
+            Some("ushort" | "half") => 2,
+            _ => return,
+        };
+        if let Some(data) = e.child_mut(data) {
+            swap_bytes(&mut data.text, stride);
+        }
+    });
+}
+
+fn swap_bytes(text: &mut String, stride: usize) {
+    if !is_hex(text) {
+        return;
+    }
+    let bytes: Vec<&str> = text.split_whitespace().collect();
+    *text = bytes
+        .chunks(stride)
+        .flat_map(|c| c.iter().rev())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+}
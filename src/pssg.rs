@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+use crate::{error::ParseError, xml::Element};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const PSSG_MAGIC: &[u8; 4] = b"PSSG";
+
+/// Extensions of files that may hold a model: pssgConverter XML, binary PSSG
+/// and gzipped binary PSSG as shipped on iOS.
+pub const MODEL_EXTENSIONS: [&str; 3] = ["xml", "pssg", "gz"];
+
+/// Checks the start of a (possibly gzipped) file for a PSSG header.
+pub fn is_pssg<R: Read>(reader: R) -> bool {
+    let mut reader = BufReader::new(reader);
+    let gzipped = reader
+        .fill_buf()
+        .is_ok_and(|head| head.starts_with(&GZIP_MAGIC));
+
+    let mut head = Vec::new();
+    let read = if gzipped {
+        GzDecoder::new(reader).take(512).read_to_end(&mut head)
+    } else {
+        reader.take(512).read_to_end(&mut head)
+    };
+    read.is_ok()
+        && (head.starts_with(PSSG_MAGIC) || String::from_utf8_lossy(&head).contains("<PSSGFILE"))
+}
+
+/// The model name of a file: `P_Rock.pssg.gz` and `P_Rock.xml` are both `P_Rock`.
+pub fn model_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let name = match name.len().checked_sub(3) {
+        Some(i) if name.is_char_boundary(i) && name[i..].eq_ignore_ascii_case(".gz") => &name[..i],
+        _ => &name,
+    };
+    match name.rfind('.') {
+        Some(i) if i > 0 => name[..i].to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Turns the contents of a model file into pssgConverter-style XML,
+/// decompressing and converting binary PSSG where needed.
+pub fn to_xml(bytes: Vec<u8>) -> Result<String, ParseError> {
+    let bytes = decompress(bytes)?;
+    if bytes.starts_with(PSSG_MAGIC) {
+        return Ok(read_binary(&bytes)?.to_xml());
+    }
+    String::from_utf8(bytes).map_err(|e| ParseError::new(&format!("Invalid UTF-8 in XML: {e}")))
+}
+
+/// Reads the contents of a model file as a generic element tree.
+pub fn to_element(bytes: Vec<u8>) -> Result<Element, ParseError> {
+    let bytes = decompress(bytes)?;
+    if bytes.starts_with(PSSG_MAGIC) {
+        return read_binary(&bytes);
+    }
+    Element::read(bytes.as_slice())
+}
+
+fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, ParseError> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(bytes);
+    }
+    let mut decompressed = Vec::new();
+    GzDecoder::new(bytes.as_slice())
+        .read_to_end(&mut decompressed)
+        .map_err(|e| ParseError::new(&format!("Failed to decompress: {e}")))?;
+    Ok(decompressed)
+}
+
+/// Nodes whose payload is raw data, written as hex.
+const DATA_NODES: [&str; 9] = [
+    "BOUNDINGBOX",
+    "DATABLOCKDATA",
+    "INDEXSOURCEDATA",
+    "INVERSEBINDMATRIX",
+    "SHADERINPUT",
+    "SKELETONINVERSEBINDMATRIX",
+    "TEXTUREIMAGEBLOCKDATA",
+    "TRANSFORM",
+    "MODIFIERNETWORKINSTANCEUNIQUEMODIFIERINPUT",
+];
+
+/// Nodes whose payload is a list of child nodes.
+const CONTAINER_NODES: [&str; 22] = [
+    "CUBEMAPTEXTURE",
+    "DATABLOCK",
+    "JOINTNODE",
+    "LIBRARY",
+    "LODRENDERNODE",
+    "NODE",
+    "PSSGDATABASE",
+    "RENDERDATASOURCE",
+    "RENDERINDEXSOURCE",
+    "RENDERNODE",
+    "RENDERSTREAMINSTANCE",
+    "ROOTNODE",
+    "SEGMENTSET",
+    "SHADERGROUP",
+    "SHADERINSTANCE",
+    "SHADERPROGRAM",
+    "SKELETON",
+    "SKINJOINT",
+    "SKINNODE",
+    "TEXTURE",
+    "TEXTUREIMAGEBLOCK",
+    "TYPEINFO",
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum AttributeType {
+    String,
+    Uint,
+    /// One or more floats, written space-separated.
+    Float,
+}
+
+/// The type of a known attribute; the binary schema only has names.
+fn attribute_type(name: &str) -> Option<AttributeType> {
+    Some(match name {
+        "id" | "nickname" | "typeName" | "creator" | "type" | "shaderGroup" | "texture"
+        | "indices" | "shader" | "source" | "dataBlock" | "renderType" | "dataType"
+        | "primitive" | "format" | "skeleton" | "joint" | "texelFormat" | "typename"
+        | "filename" | "name" | "className" => AttributeType::String,
+        "typeCount"
+        | "streamCount"
+        | "size"
+        | "elementCount"
+        | "allocationStrategy"
+        | "offset"
+        | "stride"
+        | "count"
+        | "segmentCount"
+        | "sourceCount"
+        | "stopTraversal"
+        | "parameterCount"
+        | "parameterSavedCount"
+        | "parameterID"
+        | "width"
+        | "height"
+        | "depth"
+        | "numberMipMapLevels"
+        | "imageBlockCount"
+        | "maxElementCount"
+        | "jointCount"
+        | "matrixCount"
+        | "transient"
+        | "wrapS"
+        | "wrapT"
+        | "wrapR"
+        | "minFilter"
+        | "magFilter"
+        | "automipmap"
+        | "subStream"
+        | "streamOffset" => AttributeType::Uint,
+        "scale" | "up" | "lodDistance" => AttributeType::Float,
+        _ => return None,
+    })
+}
+
+/// Reads a binary PSSG file into the element tree pssgConverter would
+/// produce, wrapped in a `PSSGFILE` element.
+///
+/// The binary format only names nodes and attributes, so payloads and
+/// attribute values are typed by name through [`DATA_NODES`],
+/// [`CONTAINER_NODES`] and [`attribute_type`]. Unknown nodes are read as
+/// child nodes if their payload parses as such and as data otherwise;
+/// unknown attributes of four bytes as integers, length-prefixed ones as
+/// strings and anything else as hex.
+pub fn read_binary(bytes: &[u8]) -> Result<Element, ParseError> {
+    let mut reader = BinaryReader { bytes, pos: 0 };
+    if reader.take(4)? != PSSG_MAGIC {
+        return Err(ParseError::new("Missing PSSG header"));
+    }
+    let _size = reader.u32()?;
+    let _attribute_count = reader.u32()?;
+    let node_count = reader.u32()?;
+
+    let mut schema = Schema::default();
+    for _ in 0..node_count {
+        let id = reader.u32()?;
+        let name = reader.string()?;
+        schema.nodes.insert(id, name);
+        let attribute_count = reader.u32()?;
+        for _ in 0..attribute_count {
+            let id = reader.u32()?;
+            let name = reader.string()?;
+            schema.attributes.insert(id, name);
+        }
+    }
+
+    let mut root = Element::new("PSSGFILE");
+    root.attributes
+        .push(("version".to_string(), "1.0.0.0".to_string()));
+    while reader.pos < bytes.len() {
+        root.children.push(read_node(&mut reader, &schema)?);
+    }
+    Ok(root)
+}
+
+#[derive(Default)]
+struct Schema {
+    nodes: HashMap<u32, String>,
+    attributes: HashMap<u32, String>,
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| ParseError::new("Unexpected end of PSSG data"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+}
+
+fn read_node(reader: &mut BinaryReader, schema: &Schema) -> Result<Element, ParseError> {
+    let id = reader.u32()?;
+    let name = schema
+        .nodes
+        .get(&id)
+        .ok_or_else(|| ParseError::new(&format!("Unknown PSSG node id {}", id)))?;
+    let size = reader.u32()? as usize;
+    let payload = reader.take(size)?;
+
+    let mut node = BinaryReader {
+        bytes: payload,
+        pos: 0,
+    };
+    let attribute_size = node.u32()? as usize;
+    let mut attributes = BinaryReader {
+        bytes: node.take(attribute_size)?,
+        pos: 0,
+    };
+
+    let mut element = Element::new(name);
+    while attributes.pos < attributes.bytes.len() {
+        let id = attributes.u32()?;
+        let name = schema
+            .attributes
+            .get(&id)
+            .ok_or_else(|| ParseError::new(&format!("Unknown PSSG attribute id {}", id)))?;
+        let len = attributes.u32()? as usize;
+        let value = attribute_value(name, attributes.take(len)?)?;
+        element.attributes.push((name.clone(), value));
+    }
+
+    let rest = &node.bytes[node.pos..];
+    if DATA_NODES.contains(&name.as_str()) {
+        element.text = hex_bytes(rest);
+    } else if CONTAINER_NODES.contains(&name.as_str()) {
+        element.children = read_children(rest, schema)?;
+    } else {
+        match read_children(rest, schema) {
+            Ok(children) => element.children = children,
+            Err(_) => element.text = hex_bytes(rest),
+        }
+    }
+    Ok(element)
+}
+
+fn read_children(bytes: &[u8], schema: &Schema) -> Result<Vec<Element>, ParseError> {
+    let mut reader = BinaryReader { bytes, pos: 0 };
+    let mut children = Vec::new();
+    while reader.pos < bytes.len() {
+        children.push(read_node(&mut reader, schema)?);
+    }
+    Ok(children)
+}
+
+fn attribute_value(name: &str, bytes: &[u8]) -> Result<String, ParseError> {
+    let invalid = || ParseError::new(&format!("Invalid value for PSSG attribute {}", name));
+    match attribute_type(name) {
+        Some(AttributeType::String) => string_value(bytes).ok_or_else(invalid),
+        Some(AttributeType::Uint) => match *bytes {
+            [a, b, c, d] => Ok(u32::from_be_bytes([a, b, c, d]).to_string()),
+            _ => Err(invalid()),
+        },
+        Some(AttributeType::Float) if !bytes.is_empty() && bytes.len().is_multiple_of(4) => {
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|c| f32::from_be_bytes([c[0], c[1], c[2], c[3]]).to_string())
+                .collect::<Vec<_>>()
+                .join(" "))
+        }
+        Some(AttributeType::Float) => Err(invalid()),
+        None => Ok(match *bytes {
+            [a, b, c, d] => u32::from_be_bytes([a, b, c, d]).to_string(),
+            _ => string_value(bytes).unwrap_or_else(|| hex_bytes(bytes)),
+        }),
+    }
+}
+
+/// A length-prefixed UTF-8 string filling all of `bytes`.
+fn string_value(bytes: &[u8]) -> Option<String> {
+    let (len, text) = bytes.split_first_chunk::<4>()?;
+    if u32::from_be_bytes(*len) as usize != text.len() {
+        return None;
+    }
+    std::str::from_utf8(text).ok().map(str::to_string)
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes binary PSSG with the schema built up from the names used.
+    #[derive(Default)]
+    struct Writer {
+        nodes: Vec<String>,
+        attributes: Vec<String>,
+    }
+
+    fn id(names: &mut Vec<String>, name: &str) -> u32 {
+        match names.iter().position(|n| n == name) {
+            Some(i) => i as u32 + 1,
+            None => {
+                names.push(name.to_string());
+                names.len() as u32
+            }
+        }
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut bytes = u32s(&[s.len() as u32]);
+        bytes.extend(s.as_bytes());
+        bytes
+    }
+
+    impl Writer {
+        fn node(&mut self, name: &str, attributes: &[(&str, Vec<u8>)], payload: &[u8]) -> Vec<u8> {
+            let mut attribute_bytes = Vec::new();
+            for (name, value) in attributes {
+                attribute_bytes.extend(u32s(&[id(&mut self.attributes, name), value.len() as u32]));
+                attribute_bytes.extend(value);
+            }
+            let mut body = u32s(&[attribute_bytes.len() as u32]);
+            body.extend(attribute_bytes);
+            body.extend(payload);
+
+            let mut bytes = u32s(&[id(&mut self.nodes, name), body.len() as u32]);
+            bytes.extend(body);
+            bytes
+        }
+
+        fn file(&self, root: &[u8]) -> Vec<u8> {
+            let mut bytes = PSSG_MAGIC.to_vec();
+            let mut schema = Vec::new();
+            for (i, name) in self.nodes.iter().enumerate() {
+                schema.extend(u32s(&[i as u32 + 1]));
+                schema.extend(string(name));
+                // Every attribute is listed under the first node.
+                let attributes: &[String] = if i == 0 { &self.attributes } else { &[] };
+                schema.extend(u32s(&[attributes.len() as u32]));
+                for (j, attribute) in attributes.iter().enumerate() {
+                    schema.extend(u32s(&[j as u32 + 1]));
+                    schema.extend(string(attribute));
+                }
+            }
+            let size = 8 + schema.len() + root.len();
+            bytes.extend(u32s(&[
+                size as u32,
+                self.attributes.len() as u32,
+                self.nodes.len() as u32,
+            ]));
+            bytes.extend(schema);
+            bytes.extend(root);
+            bytes
+        }
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn attributes_are_typed_by_name() {
+        let mut w = Writer::default();
+        let root = w.node(
+            "PSSGDATABASE",
+            &[
+                ("scale", floats(&[1.0, 0.5, 2.0])),
+                ("id", string("")),
+                ("typeCount", u32s(&[3])),
+                ("creator", string("abcd")),
+            ],
+            &[],
+        );
+        let element = read_binary(&w.file(&root)).unwrap();
+        let database = element.child("PSSGDATABASE").unwrap();
+        assert_eq!(database.attribute("scale"), Some("1 0.5 2"));
+        assert_eq!(database.attribute("id"), Some(""));
+        assert_eq!(database.attribute("typeCount"), Some("3"));
+        assert_eq!(database.attribute("creator"), Some("abcd"));
+    }
+
+    #[test]
+    fn mistyped_attributes_are_errors() {
+        let mut w = Writer::default();
+        let root = w.node("PSSGDATABASE", &[("count", u32s(&[1, 2]))], &[]);
+        assert!(read_binary(&w.file(&root)).is_err());
+    }
+
+    #[test]
+    fn unknown_attributes_fall_back_to_their_length() {
+        let mut w = Writer::default();
+        let root = w.node(
+            "PSSGDATABASE",
+            &[("a", u32s(&[7])), ("b", string("text")), ("c", vec![1, 2])],
+            &[],
+        );
+        let element = read_binary(&w.file(&root)).unwrap();
+        let database = element.child("PSSGDATABASE").unwrap();
+        assert_eq!(database.attribute("a"), Some("7"));
+        assert_eq!(database.attribute("b"), Some("text"));
+        assert_eq!(database.attribute("c"), Some("01 02"));
+    }
+
+    #[test]
+    fn data_nodes_stay_data_when_they_parse_as_nodes() {
+        let mut w = Writer::default();
+        let library = w.node("LIBRARY", &[], &[]);
+        let data = w.node("DATABLOCKDATA", &[], &library);
+        let root = w.node("PSSGDATABASE", &[], &data);
+        let element = read_binary(&w.file(&root)).unwrap();
+
+        let data_element = element
+            .child("PSSGDATABASE")
+            .and_then(|d| d.child("DATABLOCKDATA"))
+            .unwrap();
+        assert!(data_element.children.is_empty());
+        assert_eq!(data_element.text, hex_bytes(&library));
+    }
+
+    #[test]
+    fn container_nodes_propagate_errors() {
+        let mut w = Writer::default();
+        let root = w.node("PSSGDATABASE", &[], &[0, 0, 0, 9]);
+        let error = read_binary(&w.file(&root)).unwrap_err();
+        assert!(error.message().contains("Unknown PSSG node id 9"));
+    }
+
+    #[test]
+    fn unknown_nodes_fall_back_to_data() {
+        let mut w = Writer::default();
+        let child = w.node("LIBRARY", &[], &[]);
+        let parent = w.node("MYSTERY", &[], &child);
+        let blob = w.node("BLOB", &[], &[1, 2, 3]);
+        let root = w.node("PSSGDATABASE", &[], &[parent, blob].concat());
+        let element = read_binary(&w.file(&root)).unwrap();
+
+        let database = element.child("PSSGDATABASE").unwrap();
+        let mystery = database.child("MYSTERY").unwrap();
+        assert_eq!(mystery.children.len(), 1);
+        assert_eq!(mystery.children[0].name, "LIBRARY");
+        assert_eq!(database.child("BLOB").unwrap().text, "01 02 03");
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let mut w = Writer::default();
+        let root = w.node("PSSGDATABASE", &[("count", u32s(&[1]))], &[]);
+        let bytes = w.file(&root);
+        for len in [2, 10, bytes.len() - 1] {
+            assert!(read_binary(&bytes[..len]).is_err(), "length {}", len);
+        }
+        assert!(read_binary(b"NOPE").is_err());
+    }
+
+    #[test]
+    fn gzipped_binary_is_detected_and_read() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut w = Writer::default();
+        let root = w.node("PSSGDATABASE", &[], &[]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&w.file(&root)).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert!(is_pssg(gzipped.as_slice()));
+        assert!(is_pssg(&b"<?xml?><PSSGFILE>"[..]));
+        assert!(!is_pssg(&b"<COLLADA>"[..]));
+        let element = to_element(gzipped).unwrap();
+        assert!(element.child("PSSGDATABASE").is_some());
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tracing::{info, warn};
+
+use crate::{
+    assets::AssetIndex,
+    batch::{self, BatchReport},
+    coords::UpAxis,
+    entity::Entity,
+    error::ParseError,
+    math::{cross, dot, normal_matrix, normalize, sub, transform_point, transform_vector},
+    pssg,
+    texture::Image,
+};
+
+/// Vertical field of view of the camera in degrees.
+const FIELD_OF_VIEW: f32 = 30.0;
+/// Samples per pixel along each axis; the image is rendered this much larger
+/// and scaled down to smooth the edges.
+const SUPERSAMPLING: u32 = 2;
+const AMBIENT: f32 = 0.3;
+const BASE_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+/// Texels more transparent than this are cut out, as cloth and foliage
+/// textures are alpha tested in game.
+const ALPHA_CUTOFF: u8 = 128;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub enum RenderMode {
+    /// Light grey with diffuse lighting.
+    #[default]
+    Shaded,
+    /// The material textures with diffuse lighting; objects without a
+    /// texture or UVs are shaded.
+    Textured,
+}
+
+impl FromStr for RenderMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "shaded" => Ok(RenderMode::Shaded),
+            "textured" => Ok(RenderMode::Textured),
+            _ => Err(ParseError::new(&format!("Unknown render mode {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderMode::Shaded => write!(f, "shaded"),
+            RenderMode::Textured => write!(f, "textured"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    pub mode: RenderMode,
+    /// Camera angle around the up axis in degrees, 0 looking along -Z.
+    pub yaw: f32,
+    /// Camera angle above the horizon in degrees.
+    pub pitch: f32,
+    /// RGBA color behind the model.
+    pub background: [u8; 4],
+    /// Views of a contact sheet, spread evenly around the model from `yaw`;
+    /// 1 renders a single view.
+    pub views: usize,
+    /// Views per row of a contact sheet.
+    pub columns: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            mode: RenderMode::Shaded,
+            yaw: 45.0,
+            pitch: 25.0,
+            background: [0, 0, 0, 0],
+            views: 1,
+            columns: 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CatalogueOptions {
+    pub render: RenderOptions,
+    /// Directory of the PNG textures for [`RenderMode::Textured`].
+    pub texture_dir: Option<PathBuf>,
+    /// Number of worker threads; defaults to the number of cores.
+    pub jobs: Option<usize>,
+}
+
+/// A world-space corner of a triangle.
+#[derive(Clone, Copy)]
+struct Corner {
+    position: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+}
+
+struct Triangle {
+    corners: [Corner; 3],
+    /// Index into [`Scene::textures`].
+    texture: Option<usize>,
+}
+
+/// The triangles of an entity placed by its node transforms, with their
+/// textures loaded, ready to be rendered from any angle.
+struct Scene {
+    triangles: Vec<Triangle>,
+    textures: Vec<Image>,
+    center: [f32; 3],
+    radius: f32,
+    up: [f32; 3],
+}
+
+impl Scene {
+    fn new(entity: &Entity, mode: RenderMode, index: Option<&AssetIndex>) -> Option<Self> {
+        let sphere = entity.bounds()?.sphere;
+        let mut textures = Vec::new();
+        let mut loaded: HashMap<String, Option<usize>> = HashMap::new();
+        let mut texture = |material: Option<&str>| -> Option<usize> {
+            let name = entity.material(material?)?.texture.clone()?;
+            *loaded.entry(name.clone()).or_insert_with(|| {
+                let Some(path) = index?.resolve_texture(&name) else {
+                    warn!("No texture file found for {}", name);
+                    return None;
+                };
+                match Image::load_png(path) {
+                    Ok(image) if image.width > 0 && image.height > 0 => {
+                        textures.push(image);
+                        Some(textures.len() - 1)
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        warn!("{}", e);
+                        None
+                    }
+                }
+            })
+        };
+
+        let mut triangles = Vec::new();
+        for (object, transform) in entity.objects.iter().zip(entity.object_transforms()) {
+            let count = object.vertices.len();
+            if object.faces.iter().flatten().any(|&i| i as usize >= count) {
+                continue;
+            }
+            let normals = normal_matrix(&transform);
+            let has_normals = object.has_normals();
+            let has_uvs = object.has_uvs();
+            let ranges = object.face_ranges();
+            for (r, range) in ranges.into_iter().enumerate() {
+                let material = object
+                    .submeshes
+                    .get(r)
+                    .and_then(|s| s.material.as_deref())
+                    .or(object.material.as_deref());
+                let texture = match mode {
+                    RenderMode::Textured if has_uvs => texture(material),
+                    _ => None,
+                };
+                for face in &object.faces[range] {
+                    let positions =
+                        face.map(|i| transform_point(&object.vertices[i as usize], &transform));
+                    let flat = normalize(cross(
+                        sub(positions[1], positions[0]),
+                        sub(positions[2], positions[0]),
+                    ));
+                    let corners = [0, 1, 2].map(|k| {
+                        let i = face[k] as usize;
+                        Corner {
+                            position: positions[k],
+                            normal: if has_normals {
+                                normalize(transform_vector(&object.normals[i], &normals))
+                            } else {
+                                flat
+                            },
+                            uv: if has_uvs { object.uvs[i] } else { [0.0; 2] },
+                        }
+                    });
+                    triangles.push(Triangle { corners, texture });
+                }
+            }
+        }
+
+        let up = match entity.coordinate_system.up() {
+            UpAxis::Y => [0.0, 1.0, 0.0],
+            UpAxis::Z => [0.0, 0.0, 1.0],
+        };
+        Some(Scene {
+            triangles,
+            textures,
+            center: sphere.center,
+            radius: if sphere.radius > 0.0 {
+                sphere.radius
+            } else {
+                1.0
+            },
+            up,
+        })
+    }
+
+    /// Renders the scene from `yaw` and `pitch`, far enough away that the
+    /// bounding sphere fills the narrower side of the image.
+    fn render(&self, options: &RenderOptions, yaw: f32, pitch: f32) -> Image {
+        let width = options.width.max(1) * SUPERSAMPLING;
+        let height = options.height.max(1) * SUPERSAMPLING;
+        let aspect = width as f32 / height as f32;
+        let tan_y = (FIELD_OF_VIEW.to_radians() / 2.0).tan();
+        let tan_x = tan_y * aspect;
+        let half_fov = tan_x.min(tan_y).atan();
+        let distance = self.radius / half_fov.sin();
+
+        // Orbit around the up axis, starting on the side of +Z (+Y for Z-up).
+        let (yaw, pitch) = (yaw.to_radians(), pitch.clamp(-89.0, 89.0).to_radians());
+        let (front, side) = if self.up[1] == 1.0 {
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0])
+        } else {
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0])
+        };
+        let direction: [f32; 3] = [0, 1, 2].map(|i| {
+            pitch.cos() * (yaw.cos() * front[i] + yaw.sin() * side[i]) + pitch.sin() * self.up[i]
+        });
+        let eye = [0, 1, 2].map(|i| self.center[i] + direction[i] * distance);
+        let forward = direction.map(|v| -v);
+        let right = normalize(cross(forward, self.up));
+        let up = cross(right, forward);
+        // Key light from above, left of the camera.
+        let light = normalize([0, 1, 2].map(|i| direction[i] + up[i] - 0.5 * right[i]));
+
+        let mut color = vec![[0.0f32; 4]; (width * height) as usize];
+        let mut depth = vec![0.0f32; (width * height) as usize];
+        for triangle in &self.triangles {
+            // Screen x, y and 1 / view depth of each corner. The camera is
+            // outside the bounding sphere, so every corner is in front of it.
+            let screen = triangle.corners.map(|c| {
+                let p = sub(c.position, eye);
+                let z = dot(p, forward);
+                let x = dot(p, right) / (z * tan_x);
+                let y = dot(p, up) / (z * tan_y);
+                [
+                    (x + 1.0) / 2.0 * width as f32,
+                    (1.0 - y) / 2.0 * height as f32,
+                    1.0 / z,
+                ]
+            });
+            let area = edge(screen[0], screen[1], screen[2]);
+            if area.abs() < f32::EPSILON {
+                continue;
+            }
+            let min_x = screen.iter().map(|s| s[0]).fold(f32::MAX, f32::min);
+            let max_x = screen.iter().map(|s| s[0]).fold(f32::MIN, f32::max);
+            let min_y = screen.iter().map(|s| s[1]).fold(f32::MAX, f32::min);
+            let max_y = screen.iter().map(|s| s[1]).fold(f32::MIN, f32::max);
+            let x0 = min_x.floor().max(0.0) as u32;
+            let x1 = (max_x.ceil().max(0.0) as u32).min(width);
+            let y0 = min_y.floor().max(0.0) as u32;
+            let y1 = (max_y.ceil().max(0.0) as u32).min(height);
+
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let p = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+                    let weights = [
+                        edge(screen[1], screen[2], p) / area,
+                        edge(screen[2], screen[0], p) / area,
+                        edge(screen[0], screen[1], p) / area,
+                    ];
+                    if weights.iter().any(|w| *w < 0.0) {
+                        continue;
+                    }
+                    let inverse_z: f32 = (0..3).map(|k| weights[k] * screen[k][2]).sum();
+                    let pixel = (y * width + x) as usize;
+                    if inverse_z <= depth[pixel] {
+                        continue;
+                    }
+                    // Perspective-correct weights for the corner attributes.
+                    let weights = [0, 1, 2].map(|k| weights[k] * screen[k][2] / inverse_z);
+                    let Some(albedo) = self.albedo(triangle, &weights) else {
+                        continue;
+                    };
+                    let mut normal = normalize([0, 1, 2].map(|i| {
+                        (0..3)
+                            .map(|k| weights[k] * triangle.corners[k].normal[i])
+                            .sum()
+                    }));
+                    // Two-sided: light the side that faces the camera.
+                    if dot(normal, forward) > 0.0 {
+                        normal = normal.map(|v| -v);
+                    }
+                    let diffuse = AMBIENT + (1.0 - AMBIENT) * dot(normal, light).max(0.0);
+                    color[pixel] = [
+                        albedo[0] * diffuse,
+                        albedo[1] * diffuse,
+                        albedo[2] * diffuse,
+                        1.0,
+                    ];
+                    depth[pixel] = inverse_z;
+                }
+            }
+        }
+
+        resolve(&color, width, height, options.background)
+    }
+
+    /// The unlit color at a point of `triangle`, or `None` where its texture
+    /// is cut out.
+    fn albedo(&self, triangle: &Triangle, weights: &[f32; 3]) -> Option<[f32; 3]> {
+        let Some(texture) = triangle.texture else {
+            return Some(BASE_COLOR);
+        };
+        let image = &self.textures[texture];
+        let uv: [f32; 2] =
+            [0, 1].map(|i| (0..3).map(|k| weights[k] * triangle.corners[k].uv[i]).sum());
+        // Nearest texel, repeating outside the texture.
+        let x = (uv[0].rem_euclid(1.0) * image.width as f32) as u32;
+        let y = (uv[1].rem_euclid(1.0) * image.height as f32) as u32;
+        let i = ((y.min(image.height - 1) * image.width + x.min(image.width - 1)) * 4) as usize;
+        let texel = &image.pixels[i..i + 4];
+        if texel[3] < ALPHA_CUTOFF {
+            return None;
+        }
+        Some([0, 1, 2].map(|c| texel[c] as f32 / 255.0))
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `c`.
+fn edge(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Averages each block of supersamples into one pixel over `background`.
+fn resolve(color: &[[f32; 4]], width: u32, height: u32, background: [u8; 4]) -> Image {
+    let (out_width, out_height) = (width / SUPERSAMPLING, height / SUPERSAMPLING);
+    let samples = (SUPERSAMPLING * SUPERSAMPLING) as f32;
+    let background = background.map(|c| c as f32 / 255.0);
+    let mut pixels = Vec::with_capacity((out_width * out_height * 4) as usize);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let mut sum = [0.0f32; 4];
+            for sy in 0..SUPERSAMPLING {
+                for sx in 0..SUPERSAMPLING {
+                    let sample =
+                        color[((y * SUPERSAMPLING + sy) * width + x * SUPERSAMPLING + sx) as usize];
+                    // Premultiplied, so uncovered samples keep the background.
+                    let coverage = sample[3];
+                    for c in 0..3 {
+                        sum[c] +=
+                            sample[c] * coverage + background[c] * background[3] * (1.0 - coverage);
+                    }
+                    sum[3] += coverage + background[3] * (1.0 - coverage);
+                }
+            }
+            let alpha = sum[3] / samples;
+            for c in 0..3 {
+                let value = if alpha > 0.0 { sum[c] / sum[3] } else { 0.0 };
+                pixels.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+            pixels.push((alpha.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    Image {
+        width: out_width,
+        height: out_height,
+        pixels,
+    }
+}
+
+impl Entity {
+    /// Renders the entity on the CPU, framed by its bounding sphere. Textures
+    /// of [`RenderMode::Textured`] are resolved through `index`. An entity
+    /// without vertices renders as just the background.
+    pub fn render(&self, options: &RenderOptions, index: Option<&AssetIndex>) -> Image {
+        match Scene::new(self, options.mode, index) {
+            Some(scene) => scene.render(options, options.yaw, options.pitch),
+            None => Image::filled(options.width, options.height, options.background),
+        }
+    }
+
+    /// Renders `options.views` views spread evenly around the entity into a
+    /// grid of `options.columns` columns.
+    pub fn contact_sheet(&self, options: &RenderOptions, index: Option<&AssetIndex>) -> Image {
+        let views = options.views.max(1);
+        let Some(scene) = Scene::new(self, options.mode, index) else {
+            let cell = Image::filled(options.width, options.height, options.background);
+            return Image::grid(&vec![cell; views], options.columns);
+        };
+        let images: Vec<Image> = (0..views)
+            .map(|v| {
+                let yaw = options.yaw + v as f32 * 360.0 / views as f32;
+                scene.render(options, yaw, options.pitch)
+            })
+            .collect();
+        Image::grid(&images, options.columns)
+    }
+
+    /// A single view or, for several views, a contact sheet.
+    pub fn thumbnail(&self, options: &RenderOptions, index: Option<&AssetIndex>) -> Image {
+        if options.views > 1 {
+            self.contact_sheet(options, index)
+        } else {
+            self.render(options, index)
+        }
+    }
+}
+
+/// Renders a thumbnail of every PSSG file below `input` in parallel into
+/// `out_dir`, mirroring the directory tree, and writes an `index.html`
+/// showing them all.
+pub fn render_catalogue(
+    input: &Path,
+    out_dir: &Path,
+    options: &CatalogueOptions,
+) -> Result<BatchReport, ParseError> {
+    let index = AssetIndex::scan(None, options.texture_dir.as_deref());
+    let report = batch::for_each_file(input, options.jobs, |file, relative, entity| {
+        let out_dir = out_dir.join(relative);
+        fs::create_dir_all(&out_dir)
+            .map_err(|e| ParseError::new(&format!("Failed to create {:?}: {e}", out_dir)))?;
+        let path = out_dir.join(format!("{}.png", pssg::model_name(file)));
+        entity
+            .thumbnail(&options.render, Some(&index))
+            .save_png(&path)?;
+        Ok(vec![path])
+    })?;
+
+    let index_path = out_dir.join("index.html");
+    fs::write(&index_path, catalogue_html(&report, input, out_dir))
+        .map_err(|e| ParseError::new(&format!("Failed to write {:?}: {e}", index_path)))?;
+    info!(
+        "Wrote catalogue of {} models to {:?}",
+        report.succeeded, index_path
+    );
+    Ok(report)
+}
+
+fn catalogue_html(report: &BatchReport, input: &Path, out_dir: &Path) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>Models</title>\n<style>\n",
+        "body { font-family: sans-serif; background: #333; color: #eee; }\n",
+        "figure { display: inline-block; margin: 8px; text-align: center; }\n",
+        "figcaption { font-size: small; max-width: 256px; overflow-wrap: anywhere; }\n",
+        "</style>\n</head>\n<body>\n",
+    ));
+    for file in &report.files {
+        let Some(path) = file.outputs.first() else {
+            continue;
+        };
+        let src = path
+            .strip_prefix(out_dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let name = file.input.strip_prefix(input).unwrap_or(&file.input);
+        html += &format!(
+            "<figure><img src=\"{}\" loading=\"lazy\"><figcaption>{}</figcaption></figure>\n",
+            escape(&src),
+            escape(&name.to_string_lossy())
+        );
+    }
+    html += "</body>\n</html>\n";
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
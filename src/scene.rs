@@ -0,0 +1,180 @@
+use serde::Serialize;
+
+use crate::{
+    bounds::Aabb,
+    math::{Matrix, IDENTITY},
+    object::Object,
+    structure::{Node, ShaderInstance, Skeleton},
+};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum NodeKind {
+    Root,
+    Group,
+    Render,
+    Joint,
+    Skin,
+}
+
+impl NodeKind {
+    fn from_node_type(node_type: &str) -> Self {
+        match node_type {
+            "ROOTNODE" => NodeKind::Root,
+            "RENDERNODE" | "LODRENDERNODE" => NodeKind::Render,
+            "JOINTNODE" => NodeKind::Joint,
+            "SKINNODE" => NodeKind::Skin,
+            _ => NodeKind::Group,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct SceneNode {
+    pub id: String,
+    pub name: String,
+    pub kind: NodeKind,
+    pub transform: Matrix,
+    /// The node's `BOUNDINGBOX` from the file, in the node's space.
+    pub bounding_box: Option<Aabb>,
+    pub objects: Vec<usize>,
+    pub children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    /// A node without children or a bounding box, drawing the objects at
+    /// the indices `objects`.
+    pub fn new(name: &str, kind: NodeKind, transform: Matrix, objects: Vec<usize>) -> Self {
+        Self {
+            id: name.to_string(),
+            name: name.to_string(),
+            kind,
+            transform,
+            bounding_box: None,
+            objects,
+            children: Vec::new(),
+        }
+    }
+
+    /// Builds the scene hierarchy, linking render stream instances to the
+    /// objects (by index source id) they draw.
+    pub(crate) fn from_node(node: &Node, objects: &[Object]) -> Self {
+        let id = node.id.clone().unwrap_or_default();
+        let name = node.nickname.clone().unwrap_or_else(|| id.clone());
+        let transform = node
+            .transform
+            .as_ref()
+            .map(|t| t.matrix())
+            .unwrap_or(IDENTITY);
+
+        let object_indices = node
+            .stream_instances
+            .iter()
+            .filter_map(|i| i.indices.as_ref())
+            .filter_map(|indices| {
+                let indices = indices.replace("#", "");
+                objects.iter().position(|o| o.name == indices)
+            })
+            .collect();
+
+        let children = node
+            .children
+            .iter()
+            .map(|c| SceneNode::from_node(c, objects))
+            .collect();
+
+        Self {
+            id,
+            name,
+            kind: NodeKind::from_node_type(&node.node_type),
+            transform,
+            bounding_box: node.bounding_box.map(|[min, max]| Aabb { min, max }),
+            objects: object_indices,
+            children,
+        }
+    }
+
+    pub fn find(&self, id: &str) -> Option<&SceneNode> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(id))
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Material {
+    pub name: String,
+    pub texture: Option<String>,
+}
+
+impl Material {
+    pub fn new(name: &str, texture: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            texture: texture.map(str::to_string),
+        }
+    }
+
+    pub(crate) fn from_shader(shader: &ShaderInstance) -> Self {
+        let texture = shader
+            .inputs
+            .iter()
+            .find_map(|i| i.texture.as_ref())
+            .map(|t| t.replace("#", ""));
+        Self {
+            name: shader.id.clone(),
+            texture,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Skin {
+    pub joints: Vec<String>,
+    pub inverse_bind_matrices: Vec<Matrix>,
+}
+
+impl Skin {
+    /// A skin over the named joints; there must be one inverse bind matrix
+    /// per joint.
+    pub fn new(joints: Vec<String>, inverse_bind_matrices: Vec<Matrix>) -> Self {
+        Self {
+            joints,
+            inverse_bind_matrices,
+        }
+    }
+
+    /// Finds the first skin node in the hierarchy and resolves its joints and
+    /// inverse bind matrices.
+    pub(crate) fn find(node: &Node, skeletons: &[Skeleton]) -> Option<Self> {
+        if !node.skin_joints.is_empty() {
+            let joints: Vec<String> = node
+                .skin_joints
+                .iter()
+                .map(|j| j.joint.replace("#", ""))
+                .collect();
+            let skeleton = node
+                .skeleton
+                .as_ref()
+                .map(|s| s.replace("#", ""))
+                .and_then(|id| skeletons.iter().find(|s| s.id == id));
+            let inverse_bind_matrices = match skeleton {
+                Some(skeleton) if skeleton.inverse_bind_matrices.len() == joints.len() => skeleton
+                    .inverse_bind_matrices
+                    .iter()
+                    .map(|t| t.matrix())
+                    .collect(),
+                _ => vec![IDENTITY; joints.len()],
+            };
+            return Some(Self {
+                joints,
+                inverse_bind_matrices,
+            });
+        }
+        node.children.iter().find_map(|c| Skin::find(c, skeletons))
+    }
+}
@@ -0,0 +1,468 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use tracing::info;
+
+use crate::{
+    entity::Entity,
+    math::{cross, dot, normalize, sub},
+    object::Object,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SimplifyOptions {
+    /// Fraction of the triangles to keep.
+    pub ratio: f32,
+    /// Stop before collapses that move the surface further than this.
+    pub max_error: Option<f32>,
+}
+
+impl Default for SimplifyOptions {
+    fn default() -> Self {
+        Self {
+            ratio: 0.5,
+            max_error: None,
+        }
+    }
+}
+
+/// Sum of squared distances to a set of planes, as the symmetric 4x4 matrix
+/// of the plane equations (upper triangle, row by row).
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(n: [f64; 3], d: f64) -> Self {
+        let [a, b, c] = n;
+        Self([
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ])
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+
+    fn error(&self, p: [f32; 3]) -> f64 {
+        let [x, y, z] = p.map(f64::from);
+        let q = &self.0;
+        let error = q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9];
+        error.max(0.0)
+    }
+}
+
+/// Moving vertex `from` onto vertex `to`.
+struct Collapse {
+    cost: f64,
+    from: u32,
+    to: u32,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed, so the heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Simplifier<'a> {
+    object: &'a Object,
+    faces: Vec<Option<[u32; 3]>>,
+    vertex_faces: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    locked: Vec<bool>,
+    versions: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(object: &'a Object) -> Self {
+        let count = object.vertices.len();
+        let mut vertex_faces = vec![Vec::new(); count];
+        let mut quadrics = vec![Quadric::default(); count];
+        for (f, face) in object.faces.iter().enumerate() {
+            let [a, b, c] = face.map(|i| object.vertices[i as usize]);
+            let n = normalize(cross(sub(b, a), sub(c, a)));
+            let n = n.map(f64::from);
+            let d = -(n[0] * a[0] as f64 + n[1] * a[1] as f64 + n[2] * a[2] as f64);
+            let plane = Quadric::plane(n, d);
+            for &v in face {
+                vertex_faces[v as usize].push(f);
+                quadrics[v as usize].add(&plane);
+            }
+        }
+
+        Self {
+            object,
+            faces: object.faces.iter().copied().map(Some).collect(),
+            vertex_faces,
+            quadrics,
+            locked: locked_vertices(object),
+            versions: vec![0; count],
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    fn run(&mut self, target: usize, max_error: Option<f32>) {
+        for f in 0..self.faces.len() {
+            if let Some(face) = self.faces[f] {
+                for k in 0..3 {
+                    self.push(face[k], face[(k + 1) % 3]);
+                    self.push(face[(k + 1) % 3], face[k]);
+                }
+            }
+        }
+
+        let max_cost = max_error.map(|e| f64::from(e) * f64::from(e));
+        let mut alive = self.faces.len();
+        while alive > target {
+            let Some(collapse) = self.heap.pop() else {
+                break;
+            };
+            let (from, to) = (collapse.from as usize, collapse.to as usize);
+            if collapse.versions != (self.versions[from], self.versions[to]) {
+                continue;
+            }
+            if max_cost.is_some_and(|max| collapse.cost > max) {
+                break;
+            }
+            if self.flips(from, to) {
+                continue;
+            }
+            alive -= self.collapse(from, to);
+        }
+    }
+
+    fn push(&mut self, from: u32, to: u32) {
+        let (f, t) = (from as usize, to as usize);
+        if self.locked[f] {
+            return;
+        }
+        let mut quadric = self.quadrics[f];
+        quadric.add(&self.quadrics[t]);
+        self.heap.push(Collapse {
+            cost: quadric.error(self.object.vertices[t]),
+            from,
+            to,
+            versions: (self.versions[f], self.versions[t]),
+        });
+    }
+
+    /// Whether moving `from` onto `to` turns any remaining face over.
+    fn flips(&self, from: usize, to: usize) -> bool {
+        let vertices = &self.object.vertices;
+        self.vertex_faces[from].iter().any(|&f| {
+            let Some(face) = self.faces[f] else {
+                return false;
+            };
+            if face.contains(&(to as u32)) {
+                return false;
+            }
+            let normal = |face: [u32; 3]| {
+                let [a, b, c] = face.map(|i| vertices[i as usize]);
+                cross(sub(b, a), sub(c, a))
+            };
+            let moved = face.map(|i| if i as usize == from { to as u32 } else { i });
+            dot(normal(face), normal(moved)) <= 0.0
+        })
+    }
+
+    /// Collapses the edge, returning how many faces disappeared.
+    fn collapse(&mut self, from: usize, to: usize) -> usize {
+        let mut removed = 0;
+        for f in std::mem::take(&mut self.vertex_faces[from]) {
+            let Some(face) = &mut self.faces[f] else {
+                continue;
+            };
+            if face.contains(&(to as u32)) {
+                self.faces[f] = None;
+                removed += 1;
+            } else {
+                for v in face.iter_mut() {
+                    if *v as usize == from {
+                        *v = to as u32;
+                    }
+                }
+                self.vertex_faces[to].push(f);
+            }
+        }
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.versions[from] += 1;
+        self.versions[to] += 1;
+
+        // Only edges touching `to` changed cost; bumping its version above
+        // dropped their old entries.
+        let mut neighbours: Vec<u32> = self.vertex_faces[to]
+            .iter()
+            .filter_map(|&f| self.faces[f])
+            .flatten()
+            .filter(|&v| v as usize != to)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for n in neighbours {
+            self.push(to as u32, n);
+            self.push(n, to as u32);
+        }
+        removed
+    }
+}
+
+/// Vertices on open borders or UV/normal seams, which must not move.
+fn locked_vertices(object: &Object) -> Vec<bool> {
+    let key = |v: u32| object.vertices[v as usize].map(f32::to_bits);
+    let mut positions: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut seam = vec![false; object.vertices.len()];
+    for (v, p) in object.vertices.iter().enumerate() {
+        let first = *positions.entry(p.map(f32::to_bits)).or_insert(v as u32);
+        if first as usize != v {
+            seam[v] = true;
+            seam[first as usize] = true;
+        }
+    }
+
+    // Edges between positions, counted in both directions; an edge used once
+    // lies on a border.
+    let mut edges: HashMap<([u32; 3], [u32; 3]), usize> = HashMap::new();
+    for face in &object.faces {
+        for k in 0..3 {
+            let (a, b) = (key(face[k]), key(face[(k + 1) % 3]));
+            *edges
+                .entry(if a < b { (a, b) } else { (b, a) })
+                .or_default() += 1;
+        }
+    }
+    let mut locked = seam;
+    for face in &object.faces {
+        for k in 0..3 {
+            let (a, b) = (face[k], face[(k + 1) % 3]);
+            let (ka, kb) = (key(a), key(b));
+            if edges[&if ka < kb { (ka, kb) } else { (kb, ka) }] != 2 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+    }
+    locked
+}
+
+impl Object {
+    /// Reduces the triangle count with quadric error metrics, collapsing
+    /// edges onto existing vertices so attributes stay exact. Vertices on
+    /// borders and UV seams never move.
+    pub fn simplified(&self, options: &SimplifyOptions) -> Object {
+        let count = self.vertices.len();
+        if self.faces.iter().flatten().any(|&i| i as usize >= count) {
+            return self.clone();
+        }
+        let target = (self.faces.len() as f32 * options.ratio.clamp(0.0, 1.0)).ceil() as usize;
+        let mut simplifier = Simplifier::new(self);
+        simplifier.run(target, options.max_error);
+        let faces = simplifier.faces;
+
+        let mut object = self.clone();
+        let mut remap = vec![u32::MAX; count];
+        let mut kept = Vec::new();
+        for &v in faces.iter().flatten().flatten() {
+            if remap[v as usize] == u32::MAX {
+                remap[v as usize] = kept.len() as u32;
+                kept.push(v as usize);
+            }
+        }
+        let keep = |len: usize| len == count;
+        object.vertices = kept.iter().map(|&v| self.vertices[v]).collect();
+        if keep(self.normals.len()) {
+            object.normals = kept.iter().map(|&v| self.normals[v]).collect();
+        }
+        if keep(self.colors.len()) {
+            object.colors = kept.iter().map(|&v| self.colors[v]).collect();
+        }
+        if keep(self.uvs.len()) {
+            object.uvs = kept.iter().map(|&v| self.uvs[v]).collect();
+        }
+        if keep(self.joints.len()) {
+            object.joints = kept.iter().map(|&v| self.joints[v]).collect();
+        }
+        if keep(self.weights.len()) {
+            object.weights = kept.iter().map(|&v| self.weights[v]).collect();
+        }
+
+        object.faces.clear();
+        for (i, range) in self.face_ranges().into_iter().enumerate() {
+            let start = object.faces.len();
+            object.faces.extend(
+                faces[range]
+                    .iter()
+                    .flatten()
+                    .map(|f| f.map(|v| remap[v as usize])),
+            );
+            if let Some(submesh) = object.submeshes.get_mut(i) {
+                submesh.faces = start..object.faces.len();
+            }
+        }
+        object.submeshes.retain(|s| !s.faces.is_empty());
+        object
+    }
+}
+
+impl Entity {
+    /// A copy with every object simplified.
+    pub fn simplified(&self, options: &SimplifyOptions) -> Entity {
+        let mut entity = self.clone();
+        entity.objects = self.objects.iter().map(|o| o.simplified(options)).collect();
+        let before: usize = self.objects.iter().map(|o| o.faces.len()).sum();
+        let after: usize = entity.objects.iter().map(|o| o.faces.len()).sum();
+        info!("Simplified {} to {} faces", before, after);
+        entity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A closed torus of `n` by `m` quads split into triangles.
+    fn torus(n: usize, m: usize) -> Object {
+        let mut vertices = Vec::new();
+        for i in 0..n {
+            let u = i as f32 / n as f32 * std::f32::consts::TAU;
+            for j in 0..m {
+                let v = j as f32 / m as f32 * std::f32::consts::TAU;
+                let radius = 2.0 + v.cos();
+                vertices.push([radius * u.cos(), v.sin(), radius * u.sin()]);
+            }
+        }
+        let index = |i: usize, j: usize| ((i % n) * m + j % m) as u32;
+        let mut faces = Vec::new();
+        for i in 0..n {
+            for j in 0..m {
+                let (a, b) = (index(i, j), index(i + 1, j));
+                let (c, d) = (index(i + 1, j + 1), index(i, j + 1));
+                faces.push([a, c, b]);
+                faces.push([a, d, c]);
+            }
+        }
+        Object::new("torus", vertices, faces)
+    }
+
+    /// A flat `n` by `n` grid whose middle column is split by a UV seam.
+    fn seamed_grid(n: usize) -> Object {
+        let mut vertices = Vec::new();
+        let mut uvs = Vec::new();
+        let mut faces = Vec::new();
+        let half = n / 2;
+        for (offset, columns) in [(0, 0..=half), (1, half..=n)] {
+            let start = vertices.len() as u32;
+            let width = (columns.end() - columns.start() + 1) as u32;
+            for y in 0..=n {
+                for x in columns.clone() {
+                    vertices.push([x as f32, y as f32, 0.0]);
+                    uvs.push([x as f32 / n as f32 + offset as f32, y as f32 / n as f32]);
+                }
+            }
+            let index = |x: u32, y: u32| start + y * width + x;
+            for y in 0..n as u32 {
+                for x in 0..width - 1 {
+                    faces.push([index(x, y), index(x + 1, y), index(x + 1, y + 1)]);
+                    faces.push([index(x, y), index(x + 1, y + 1), index(x, y + 1)]);
+                }
+            }
+        }
+        let mut object = Object::new("grid", vertices, faces);
+        object.uvs = uvs;
+        object
+    }
+
+    fn simplify(object: &Object, ratio: f32, max_error: Option<f32>) -> Object {
+        object.simplified(&SimplifyOptions { ratio, max_error })
+    }
+
+    #[test]
+    fn border_and_seam_vertices_stay() {
+        let grid = seamed_grid(8);
+        let locked = locked_vertices(&grid);
+        let simplified = simplify(&grid, 0.1, None);
+        assert!(simplified.faces.len() < grid.faces.len() / 2);
+
+        let kept: Vec<_> = simplified.vertices.iter().zip(&simplified.uvs).collect();
+        for (i, (v, uv)) in grid.vertices.iter().zip(&grid.uvs).enumerate() {
+            let [x, y, _] = *v;
+            let border = x == 0.0 || y == 0.0 || x == 8.0 || y == 8.0;
+            let seam = x == 4.0;
+            assert_eq!(locked[i], border || seam, "{:?}", v);
+            if border || seam {
+                assert!(kept.contains(&(v, uv)), "{:?} moved", v);
+            }
+        }
+    }
+
+    #[test]
+    fn ratio_is_respected_on_a_closed_mesh() {
+        let torus = torus(16, 8);
+        assert!(locked_vertices(&torus).iter().all(|&l| !l));
+        for ratio in [0.75, 0.5, 0.25] {
+            let target = (torus.faces.len() as f32 * ratio).ceil() as usize;
+            let simplified = simplify(&torus, ratio, None);
+            // Each collapse removes the two faces around an edge.
+            let faces = simplified.faces.len();
+            assert!(
+                faces <= target && faces + 2 >= target,
+                "{}: {}",
+                ratio,
+                faces
+            );
+            assert!(simplified
+                .faces
+                .iter()
+                .flatten()
+                .all(|&i| (i as usize) < simplified.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn max_error_stops_early() {
+        let torus = torus(16, 8);
+        let faces = |max_error| simplify(&torus, 0.0, max_error).faces.len();
+        assert_eq!(faces(Some(0.0)), torus.faces.len());
+        let (tight, loose, unbounded) = (faces(Some(0.5)), faces(Some(1.0)), faces(None));
+        assert!(tight < torus.faces.len());
+        assert!(loose < tight, "{} {}", loose, tight);
+        assert!(unbounded < loose, "{} {}", unbounded, loose);
+    }
+}
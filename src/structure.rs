@@ -1,22 +1,25 @@
-use std::io::BufReader;
+use std::collections::HashMap;
+use std::fmt;
 use std::path::Path;
-use std::{fmt, fs::File};
 
 use serde::de::{self, MapAccess, Unexpected};
-use serde::{de::Visitor, Deserialize, Deserializer};
-use serde_xml_rs::from_reader;
-use tracing::warn;
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize};
+use serde_xml_rs::from_str;
 
-use crate::parse::read;
+use crate::archive;
+use crate::error::ParseError;
+use crate::math::Matrix;
+use crate::parse::{read, to_big_endian, ParseOptions};
+use crate::pssg;
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename = "PSSGFILE")]
 pub struct PssgFile {
     #[serde(rename = "PSSGDATABASE")]
     pub database: PssgDatabase,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PssgDatabase {
     #[serde(rename = "TYPEINFO")]
     pub type_info: Vec<TypeInfo>,
@@ -24,7 +27,7 @@ pub struct PssgDatabase {
     pub libraries: Vec<Library>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TypeInfo {
     #[serde(rename = "typeName")]
     pub type_name: String,
@@ -32,19 +35,27 @@ pub struct TypeInfo {
     pub type_count: usize,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Library {
     #[serde(rename = "type")]
     pub library_type: String,
-    #[serde(rename = "DATABLOCK", default)]
+    #[serde(rename = "DATABLOCK", default, skip_serializing_if = "Vec::is_empty")]
     pub data_blocks: Vec<DataBlock>,
-    #[serde(rename = "SEGMENTSET", default)]
+    #[serde(rename = "SEGMENTSET", default, skip_serializing_if = "Vec::is_empty")]
     pub segment_sets: Vec<SegmentSet>,
-    #[serde(rename = "ROOTNODE", default)]
+    #[serde(rename = "ROOTNODE", default, skip_serializing_if = "Option::is_none")]
     pub root_node: Option<RootNode>,
+    #[serde(
+        rename = "SHADERINSTANCE",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub shader_instances: Vec<ShaderInstance>,
+    #[serde(rename = "SKELETON", default, skip_serializing_if = "Vec::is_empty")]
+    pub skeletons: Vec<Skeleton>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DataBlock {
     #[serde(rename = "elementCount")]
     pub element_count: usize,
@@ -56,7 +67,7 @@ pub struct DataBlock {
     pub id: String,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DataBlockStream {
     #[serde(rename = "renderType")]
     pub render_type: String,
@@ -64,27 +75,29 @@ pub struct DataBlockStream {
     pub data_type: String,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DataBlockData {
-    #[serde(rename = "$value")]
+    #[serde(rename(serialize = "text", deserialize = "$value"))]
     pub text: String,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SegmentSet {
     #[serde(rename = "RENDERDATASOURCE")]
     pub render_data_sources: Vec<RenderDataSource>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RenderDataSource {
+    #[serde(rename = "id")]
+    pub id: Option<String>,
     #[serde(rename = "RENDERINDEXSOURCE")]
     pub index_source: RenderIndexSource,
     #[serde(rename = "RENDERSTREAM")]
     pub streams: Vec<RenderStream>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RenderIndexSource {
     #[serde(rename = "primitive")]
     pub primitive: String,
@@ -94,23 +107,50 @@ pub struct RenderIndexSource {
     pub index_data: Option<IndexSourceData>,
     #[serde(rename = "format")]
     pub data_type: String,
+    #[serde(rename = "id")]
+    pub id: Option<String>,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct IndexSourceData {
-    #[serde(rename = "$value")]
+    #[serde(rename(serialize = "text", deserialize = "$value"))]
     pub text: String,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RenderStream {
     #[serde(rename = "dataBlock")]
     pub data_block: String,
 }
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShaderInstance {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "SHADERINPUT", default)]
+    pub inputs: Vec<ShaderInput>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShaderInput {
+    #[serde(rename = "texture")]
+    pub texture: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Skeleton {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "SKELETONINVERSEBINDMATRIX", default)]
+    pub inverse_bind_matrices: Vec<Transform>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(transparent)]
 pub struct RootNode {
+    #[serde(skip)]
     pub render_nodes: Vec<RenderNode>,
+    pub node: Node,
 }
 
 impl<'de> Deserialize<'de> for RootNode {
@@ -118,47 +158,131 @@ impl<'de> Deserialize<'de> for RootNode {
     where
         D: Deserializer<'de>,
     {
-        struct RootNodeVisitor;
+        let mut node = Node::deserialize(deserializer)?;
+        node.node_type = "ROOTNODE".to_string();
+
+        let render_nodes = node
+            .children
+            .iter()
+            .filter(|c| c.node_type == "RENDERNODE")
+            .filter_map(|c| {
+                c.transform
+                    .clone()
+                    .map(|transform| RenderNode { transform })
+            })
+            .collect();
+
+        Ok(RootNode { render_nodes, node })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RenderNode {
+    pub transform: Transform,
+}
+
+#[derive(Serialize, Clone, Default, Debug)]
+pub struct Node {
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    #[serde(rename = "TRANSFORM", skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Transform>,
+    /// `[min, max]` from the node's `BOUNDINGBOX`, if it has a readable one.
+    #[serde(rename = "BOUNDINGBOX", skip_serializing_if = "Option::is_none")]
+    pub bounding_box: Option<[[f32; 3]; 2]>,
+    #[serde(rename = "RENDERSTREAMINSTANCE", skip_serializing_if = "Vec::is_empty")]
+    pub stream_instances: Vec<RenderStreamInstance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skeleton: Option<String>,
+    #[serde(rename = "SKINJOINT", skip_serializing_if = "Vec::is_empty")]
+    pub skin_joints: Vec<SkinJoint>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Node>,
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct NodeVisitor;
 
-        impl<'de> Visitor<'de> for RootNodeVisitor {
-            type Value = RootNode;
+        impl<'de> Visitor<'de> for NodeVisitor {
+            type Value = Node;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("struct RootNode")
+                formatter.write_str("struct Node")
             }
 
-            fn visit_map<V>(self, mut map: V) -> Result<RootNode, V::Error>
+            fn visit_map<V>(self, mut map: V) -> Result<Node, V::Error>
             where
                 V: MapAccess<'de>,
             {
-                let mut render_nodes = Vec::new();
+                let mut node = Node::default();
 
                 while let Some(key) = map.next_key::<String>()? {
-                    if key == "RENDERNODE" {
-                        let node: RenderNode = map.next_value()?;
-                        render_nodes.push(node);
-                    } else {
-                        let _: serde::de::IgnoredAny = map.next_value()?;
+                    match key.as_str() {
+                        "id" => node.id = Some(map.next_value()?),
+                        "nickname" => node.nickname = Some(map.next_value()?),
+                        "skeleton" => node.skeleton = Some(map.next_value()?),
+                        "TRANSFORM" => node.transform = Some(map.next_value()?),
+                        "BOUNDINGBOX" => {
+                            let s: String = map.next_value()?;
+                            node.bounding_box = read_bounding_box(&s);
+                        }
+                        "RENDERSTREAMINSTANCE" => node.stream_instances.push(map.next_value()?),
+                        "SKINJOINT" => node.skin_joints.push(map.next_value()?),
+                        k if k.ends_with("NODE") => {
+                            let mut child: Node = map.next_value()?;
+                            child.node_type = key;
+                            node.children.push(child);
+                        }
+                        _ => {
+                            let _: serde::de::IgnoredAny = map.next_value()?;
+                        }
                     }
                 }
 
-                Ok(RootNode { render_nodes })
+                Ok(node)
             }
         }
 
-        deserializer.deserialize_map(RootNodeVisitor)
+        deserializer.deserialize_map(NodeVisitor)
     }
 }
 
-#[derive(Deserialize, Clone)]
-pub struct RenderNode {
-    #[serde(rename = "TRANSFORM")]
-    pub transform: Transform,
+/// Bounding boxes are only informational, so one that can't be read is
+/// dropped rather than failing the parse.
+fn read_bounding_box(s: &str) -> Option<[[f32; 3]; 2]> {
+    let data: Vec<f32> = read(s, "float").ok()?;
+    match data[..] {
+        [x0, y0, z0, x1, y1, z1] => Some([[x0, y0, z0], [x1, y1, z1]]),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RenderStreamInstance {
+    #[serde(rename = "indices")]
+    pub indices: Option<String>,
+    #[serde(rename = "shader")]
+    pub shader: Option<String>,
 }
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SkinJoint {
+    #[serde(rename = "joint")]
+    pub joint: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(transparent)]
 pub struct Transform {
-    matrix: [[f32; 4]; 4],
+    matrix: Matrix,
 }
 
 impl<'de> Deserialize<'de> for Transform {
@@ -192,6 +316,12 @@ impl<'de> Deserialize<'de> for Transform {
     }
 }
 
+impl Transform {
+    pub fn matrix(&self) -> Matrix {
+        self.matrix
+    }
+}
+
 impl std::ops::Index<usize> for Transform {
     type Output = [f32; 4];
 
@@ -200,12 +330,114 @@ impl std::ops::Index<usize> for Transform {
     }
 }
 
-pub fn parse_xml_file(path: &Path) -> (Vec<DataBlock>, Vec<RenderDataSource>, Option<RootNode>) {
-    let pssg_file: PssgFile = {
-        let file = File::open(path).expect("Failed to open file");
-        let reader = BufReader::new(file);
-        from_reader(reader).expect("Failed to parse XML")
-    };
+/// A TYPEINFO entry next to the number of elements actually found, for the
+/// types the parser models.
+#[derive(Serialize, Clone, Debug)]
+#[non_exhaustive]
+pub struct TypeCount {
+    pub type_name: String,
+    pub expected: usize,
+    pub found: Option<usize>,
+}
+
+impl TypeCount {
+    pub fn is_mismatch(&self) -> bool {
+        self.found.is_some_and(|found| found != self.expected)
+    }
+}
+
+fn count_types(pssg_file: &PssgFile) -> Vec<TypeCount> {
+    fn count_nodes(node: &Node, counts: &mut HashMap<String, usize>) {
+        *counts.entry(node.node_type.clone()).or_default() += 1;
+        *counts
+            .entry("RENDERSTREAMINSTANCE".to_string())
+            .or_default() += node.stream_instances.len();
+        *counts.entry("SKINJOINT".to_string()).or_default() += node.skin_joints.len();
+        for child in &node.children {
+            count_nodes(child, counts);
+        }
+    }
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for library in &pssg_file.database.libraries {
+        let sources = library
+            .segment_sets
+            .iter()
+            .flat_map(|s| &s.render_data_sources);
+        *counts.entry("DATABLOCK".to_string()).or_default() += library.data_blocks.len();
+        *counts.entry("SEGMENTSET".to_string()).or_default() += library.segment_sets.len();
+        *counts.entry("RENDERDATASOURCE".to_string()).or_default() += sources.clone().count();
+        *counts.entry("RENDERINDEXSOURCE".to_string()).or_default() += sources.clone().count();
+        *counts.entry("RENDERSTREAM".to_string()).or_default() +=
+            sources.map(|s| s.streams.len()).sum::<usize>();
+        *counts.entry("SHADERINSTANCE".to_string()).or_default() += library.shader_instances.len();
+        *counts.entry("SKELETON".to_string()).or_default() += library.skeletons.len();
+        if let Some(root_node) = &library.root_node {
+            count_nodes(&root_node.node, &mut counts);
+        }
+    }
+
+    pssg_file
+        .database
+        .type_info
+        .iter()
+        .map(|t| TypeCount {
+            type_name: t.type_name.clone(),
+            expected: t.type_count,
+            found: counts.get(&t.type_name).copied(),
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct PssgContents {
+    pub data_blocks: Vec<DataBlock>,
+    pub render_data_sources: Vec<RenderDataSource>,
+    pub root_node: Option<RootNode>,
+    pub shader_instances: Vec<ShaderInstance>,
+    pub skeletons: Vec<Skeleton>,
+    pub type_counts: Vec<TypeCount>,
+    pub warnings: Vec<String>,
+}
+
+/// Loads a PSSG file: pssgConverter XML, binary PSSG (optionally gzipped), or
+/// either of those inside an archive.
+pub fn load_xml_file(path: &Path) -> Result<PssgFile, ParseError> {
+    load_bytes(archive::read_file(path)?)
+}
+
+/// Loads a PSSG file from its contents, in any format [`load_xml_file`] accepts.
+pub fn load_bytes(bytes: Vec<u8>) -> Result<PssgFile, ParseError> {
+    let xml = pssg::to_xml(bytes)?;
+    from_str(&xml).map_err(|e| ParseError::new(&format!("Failed to parse XML: {e}")))
+}
+
+pub fn parse_xml_file(path: &Path, options: &ParseOptions) -> Result<PssgContents, ParseError> {
+    contents(load_with(path, options)?, options)
+}
+
+/// Loads a PSSG file like [`load_xml_file`], reading hex payloads in the byte
+/// order of `options`.
+pub(crate) fn load_with(path: &Path, options: &ParseOptions) -> Result<PssgFile, ParseError> {
+    let bytes = archive::read_file(path)?;
+    if !options.is_little_endian() {
+        return load_bytes(bytes);
+    }
+    let mut element = pssg::to_element(bytes)?;
+    to_big_endian(&mut element);
+    from_str(&element.to_xml()).map_err(|e| ParseError::new(&format!("Failed to parse XML: {e}")))
+}
+
+/// Collects the libraries the entity is built from.
+pub fn contents(
+    mut pssg_file: PssgFile,
+    options: &ParseOptions,
+) -> Result<PssgContents, ParseError> {
+    let type_counts = count_types(&pssg_file);
+    pssg_file
+        .database
+        .libraries
+        .retain(|l| options.includes_library(&l.library_type));
 
     let source_count = pssg_file
         .database
@@ -225,10 +457,10 @@ pub fn parse_xml_file(path: &Path) -> (Vec<DataBlock>, Vec<RenderDataSource>, Op
         .into_iter()
         .filter(|l| l.library_type == "RENDERINTERFACEBOUND")
         .flat_map(|l| l.data_blocks)
-        .filter(|d| d.data.is_some())
+        .filter(|d| options.is_strict() || d.data.is_some())
         .collect();
 
-    let render_index_sources = pssg_file
+    let render_data_sources = pssg_file
         .clone()
         .database
         .libraries
@@ -236,22 +468,61 @@ pub fn parse_xml_file(path: &Path) -> (Vec<DataBlock>, Vec<RenderDataSource>, Op
         .filter(|l| l.library_type == "SEGMENTSET")
         .flat_map(|l| l.segment_sets)
         .flat_map(|s| s.render_data_sources)
-        .filter(|r| r.index_source.index_data.is_some())
+        .filter(|r| options.is_strict() || r.index_source.index_data.is_some())
         .collect::<Vec<_>>();
 
+    let shader_instances = pssg_file
+        .clone()
+        .database
+        .libraries
+        .into_iter()
+        .flat_map(|l| l.shader_instances)
+        .collect();
+
+    let skeletons = pssg_file
+        .clone()
+        .database
+        .libraries
+        .into_iter()
+        .flat_map(|l| l.skeletons)
+        .collect();
+
     let root_node = pssg_file
         .database
         .libraries
         .into_iter()
         .find_map(|l| l.root_node);
 
-    if source_count != render_index_sources.len() {
-        warn!(
+    let mut warnings = Vec::new();
+    if options.includes_library("SEGMENTSET") && source_count != render_data_sources.len() {
+        let warning = format!(
             "Expected {} RENDERDATASOURCE entries, found {}",
             source_count,
-            render_index_sources.len()
+            render_data_sources.len()
+        );
+        options.count_mismatch_found(&mut warnings, warning, false)?;
+    }
+    // RENDERDATASOURCE is checked above against the sources that are used.
+    for count in type_counts
+        .iter()
+        .filter(|t| t.is_mismatch() && t.type_name != "RENDERDATASOURCE")
+    {
+        let warning = format!(
+            "TYPEINFO expects {} {} nodes, found {}",
+            count.expected,
+            count.type_name,
+            count.found.unwrap_or_default()
         );
+        options.count_mismatch_found(&mut warnings, warning, false)?;
     }
 
-    (data_blocks, render_index_sources, root_node)
+    Ok(PssgContents {
+        data_blocks,
+        render_data_sources,
+        root_node,
+        shader_instances,
+        skeletons,
+        type_counts,
+        warnings,
+    })
 }
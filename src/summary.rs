@@ -0,0 +1,233 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{
+    bounds::{Bounds, BoundsMismatch, Sphere, BOUNDS_TOLERANCE},
+    entity::Entity,
+    math::{Matrix, IDENTITY},
+    object::Object,
+    scene::{NodeKind, SceneNode},
+    structure::TypeCount,
+    topology::TopologyReport,
+};
+
+type BoundingBox = [[f32; 3]; 2];
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct Summary {
+    pub object_count: usize,
+    pub objects: Vec<ObjectSummary>,
+    pub translation: [f32; 3],
+    pub nodes: Vec<NodeSummary>,
+    /// Bounds of the placed objects, with node transforms applied.
+    pub bounding_box: Option<BoundingBox>,
+    pub bounding_sphere: Option<Sphere>,
+    /// Nodes whose `BOUNDINGBOX` disagrees with their geometry.
+    pub bounds_mismatches: Vec<BoundsMismatch>,
+    pub type_mismatches: Vec<TypeCount>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct ObjectSummary {
+    pub name: String,
+    pub vertices: usize,
+    pub uvs: usize,
+    pub normals: usize,
+    pub faces: usize,
+    pub primitive: String,
+    pub material: Option<String>,
+    pub data_blocks: Vec<DataBlockSummary>,
+    pub bounding_box: Option<BoundingBox>,
+    pub bounding_sphere: Option<Sphere>,
+    pub topology: TopologyReport,
+}
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct DataBlockSummary {
+    pub id: String,
+    pub render_type: String,
+    pub data_type: String,
+}
+
+#[derive(Serialize, Debug)]
+#[non_exhaustive]
+pub struct NodeSummary {
+    pub path: String,
+    pub kind: NodeKind,
+    pub transform: Option<Matrix>,
+    pub objects: Vec<String>,
+}
+
+impl Entity {
+    pub fn summary(&self) -> Summary {
+        let objects: Vec<ObjectSummary> = self.objects.iter().map(ObjectSummary::new).collect();
+
+        let bounds = self.bounds();
+
+        let mut nodes = Vec::new();
+        if let Some(root) = &self.root {
+            self.summarize_node(root, "", &mut nodes);
+        }
+
+        Summary {
+            object_count: self.objects.len(),
+            objects,
+            translation: self.translation,
+            nodes,
+            bounding_box: bounds.map(|b| [b.aabb.min, b.aabb.max]),
+            bounding_sphere: bounds.map(|b| b.sphere),
+            bounds_mismatches: self.bounds_mismatches(BOUNDS_TOLERANCE),
+            type_mismatches: self
+                .type_counts
+                .iter()
+                .filter(|t| t.is_mismatch())
+                .cloned()
+                .collect(),
+            warnings: self.warnings.clone(),
+        }
+    }
+
+    fn summarize_node(&self, node: &SceneNode, parent: &str, nodes: &mut Vec<NodeSummary>) {
+        let path = format!("{}/{}", parent, node.name);
+        nodes.push(NodeSummary {
+            path: path.clone(),
+            kind: node.kind,
+            transform: (node.transform != IDENTITY).then_some(node.transform),
+            objects: node
+                .objects
+                .iter()
+                .map(|&i| self.objects[i].name.clone())
+                .collect(),
+        });
+        for child in &node.children {
+            self.summarize_node(child, &path, nodes);
+        }
+    }
+}
+
+impl ObjectSummary {
+    fn new(object: &Object) -> Self {
+        let bounds: Option<Bounds> = object.bounds();
+
+        Self {
+            name: object.name.clone(),
+            vertices: object.vertices.len(),
+            uvs: object.uvs.len(),
+            normals: object.normals.len(),
+            faces: object.faces.len(),
+            primitive: object.primitive.clone(),
+            material: object.material.clone(),
+            data_blocks: object
+                .streams
+                .iter()
+                .map(|s| DataBlockSummary {
+                    id: s.block_id.clone(),
+                    render_type: s.render_type.clone(),
+                    data_type: s.data_type.clone(),
+                })
+                .collect(),
+            bounding_box: bounds.map(|b| [b.aabb.min, b.aabb.max]),
+            bounding_sphere: bounds.map(|b| b.sphere),
+            topology: object.topology(),
+        }
+    }
+}
+
+impl Summary {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize summary")
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Objects: {}", self.object_count)?;
+        for object in &self.objects {
+            writeln!(
+                f,
+                "  {} ({}): {} vertices, {} uvs, {} normals, {} faces",
+                object.name,
+                object.primitive,
+                object.vertices,
+                object.uvs,
+                object.normals,
+                object.faces
+            )?;
+            if let Some(material) = &object.material {
+                writeln!(f, "    material: {}", material)?;
+            }
+            if let Some([min, max]) = object.bounding_box {
+                writeln!(f, "    bounds: {:?} - {:?}", min, max)?;
+            }
+            if let Some(sphere) = &object.bounding_sphere {
+                writeln!(f, "    sphere: {:?} r {}", sphere.center, sphere.radius)?;
+            }
+            if object.topology != TopologyReport::default() {
+                writeln!(f, "    topology: {}", object.topology)?;
+            }
+            for block in &object.data_blocks {
+                writeln!(
+                    f,
+                    "    {}: {} ({})",
+                    block.id, block.render_type, block.data_type
+                )?;
+            }
+        }
+
+        let [x, y, z] = self.translation;
+        writeln!(f, "Translation: {} {} {}", x, y, z)?;
+        if let Some([min, max]) = self.bounding_box {
+            writeln!(f, "Bounds: {:?} - {:?}", min, max)?;
+        }
+        if let Some(sphere) = &self.bounding_sphere {
+            writeln!(f, "Sphere: {:?} r {}", sphere.center, sphere.radius)?;
+        }
+
+        if !self.nodes.is_empty() {
+            writeln!(f, "Nodes:")?;
+            for node in &self.nodes {
+                write!(f, "  {} ({:?})", node.path, node.kind)?;
+                if let Some(transform) = &node.transform {
+                    let [x, y, z, _] = transform[3];
+                    write!(f, " translation {} {} {}", x, y, z)?;
+                }
+                if !node.objects.is_empty() {
+                    write!(f, " -> {}", node.objects.join(", "))?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        for mismatch in &self.type_mismatches {
+            writeln!(
+                f,
+                "TYPEINFO {}: expected {}, found {}",
+                mismatch.type_name,
+                mismatch.expected,
+                mismatch.found.unwrap_or_default()
+            )?;
+        }
+        for mismatch in &self.bounds_mismatches {
+            write!(
+                f,
+                "BOUNDINGBOX {}: file {:?} - {:?}",
+                mismatch.node, mismatch.file.min, mismatch.file.max
+            )?;
+            match &mismatch.computed {
+                Some(computed) => {
+                    writeln!(f, ", computed {:?} - {:?}", computed.min, computed.max)?
+                }
+                None => writeln!(f, ", no geometry")?,
+            }
+        }
+        for warning in &self.warnings {
+            writeln!(f, "Warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,744 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tracing::warn;
+
+use crate::{archive, error::ParseError, pssg, xml::Element};
+
+/// Cube map faces in the order PSSG image blocks are named.
+const CUBE_FACES: [&str; 6] = [
+    "PositiveX",
+    "NegativeX",
+    "PositiveY",
+    "NegativeY",
+    "PositiveZ",
+    "NegativeZ",
+];
+
+/// Pixel formats of the `texelFormat` attribute that can be decoded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum TexelFormat {
+    Dxt1,
+    Dxt3,
+    Dxt5,
+    /// `ui8x4`: 8-bit ARGB, the order vertex colors use too.
+    Argb8,
+    /// `u8x4`: 8-bit RGBA.
+    Rgba8,
+    /// `l8`: 8-bit luminance.
+    L8,
+}
+
+impl TexelFormat {
+    fn is_compressed(&self) -> bool {
+        matches!(
+            self,
+            TexelFormat::Dxt1 | TexelFormat::Dxt3 | TexelFormat::Dxt5
+        )
+    }
+
+    /// Bytes per 4x4 block for compressed formats, per pixel otherwise.
+    fn unit_size(&self) -> usize {
+        match self {
+            TexelFormat::Dxt1 => 8,
+            TexelFormat::Dxt3 | TexelFormat::Dxt5 => 16,
+            TexelFormat::Argb8 | TexelFormat::Rgba8 => 4,
+            TexelFormat::L8 => 1,
+        }
+    }
+
+    /// Size in bytes of a `width` x `height` image.
+    pub fn image_size(&self, width: u32, height: u32) -> usize {
+        let (width, height) = (width as usize, height as usize);
+        if self.is_compressed() {
+            width.div_ceil(4) * height.div_ceil(4) * self.unit_size()
+        } else {
+            width * height * self.unit_size()
+        }
+    }
+}
+
+impl FromStr for TexelFormat {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dxt1" | "dxt1_srgb" => Ok(TexelFormat::Dxt1),
+            "dxt3" | "dxt3_srgb" => Ok(TexelFormat::Dxt3),
+            "dxt5" | "dxt5_srgb" => Ok(TexelFormat::Dxt5),
+            "ui8x4" => Ok(TexelFormat::Argb8),
+            "u8x4" => Ok(TexelFormat::Rgba8),
+            "l8" => Ok(TexelFormat::L8),
+            _ => Err(ParseError::new(&format!("Unsupported texel format {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for TexelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TexelFormat::Dxt1 => "dxt1",
+            TexelFormat::Dxt3 => "dxt3",
+            TexelFormat::Dxt5 => "dxt5",
+            TexelFormat::Argb8 => "ui8x4",
+            TexelFormat::Rgba8 => "u8x4",
+            TexelFormat::L8 => "l8",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An 8-bit RGBA image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Reads a PNG of any color type and bit depth as 8-bit RGBA.
+    pub fn load_png(path: &Path) -> Result<Self, ParseError> {
+        let error =
+            |e: &dyn fmt::Display| ParseError::new(&format!("Failed to read {:?}: {e}", path));
+        let file = File::open(path).map_err(|e| error(&e))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+        let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+        let info = reader.next_frame(&mut buffer).map_err(|e| error(&e))?;
+        buffer.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(ParseError::new(&format!(
+                    "Unexpanded palette in {:?}",
+                    path
+                )))
+            }
+        };
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    /// The `width` x `height` pixels starting at `x`, `y`, clamped to the
+    /// image.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let row = self.width as usize * 4;
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for line in y as usize..(y + height) as usize {
+            let start = line * row + x as usize * 4;
+            pixels.extend_from_slice(&self.pixels[start..start + width as usize * 4]);
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), ParseError> {
+        let error =
+            |e: &dyn fmt::Display| ParseError::new(&format!("Failed to write {:?}: {e}", path));
+        let file = File::create(path).map_err(|e| error(&e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+        writer.write_image_data(&self.pixels).map_err(|e| error(&e))
+    }
+
+    /// A `width` x `height` image of one color.
+    pub fn filled(width: u32, height: u32, color: [u8; 4]) -> Image {
+        Image {
+            width,
+            height,
+            pixels: color.repeat((width * height) as usize),
+        }
+    }
+
+    /// Lays `images` out in rows of `columns` cells the size of the largest
+    /// image, leaving the rest of each cell transparent.
+    pub fn grid(images: &[Image], columns: usize) -> Image {
+        let columns = columns.clamp(1, images.len().max(1));
+        let rows = images.len().div_ceil(columns);
+        let cell_width = images.iter().map(|i| i.width).max().unwrap_or(0);
+        let cell_height = images.iter().map(|i| i.height).max().unwrap_or(0);
+        let mut grid = Image::filled(
+            cell_width * columns as u32,
+            cell_height * rows as u32,
+            [0; 4],
+        );
+        let row = grid.width as usize * 4;
+        for (n, image) in images.iter().enumerate() {
+            let x = (n % columns) * cell_width as usize;
+            let y = (n / columns) * cell_height as usize;
+            let line = image.width as usize * 4;
+            if line == 0 {
+                continue;
+            }
+            for (i, source) in image.pixels.chunks_exact(line).enumerate() {
+                let start = (y + i) * row + x * 4;
+                grid.pixels[start..start + line].copy_from_slice(source);
+            }
+        }
+        grid
+    }
+
+    /// Places `images` of the same height side by side.
+    fn strip(images: &[Image]) -> Image {
+        let height = images.first().map_or(0, |i| i.height);
+        let width: u32 = images.iter().map(|i| i.width).sum();
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height as usize {
+            for image in images {
+                let row = image.width as usize * 4;
+                pixels.extend_from_slice(&image.pixels[y * row..(y + 1) * row]);
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// One `TEXTUREIMAGEBLOCK`: the whole mip chain of a 2D texture or of one
+/// cube map face.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ImageBlock {
+    /// The face for cube maps, such as `PositiveX`.
+    pub face: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl ImageBlock {
+    pub fn new(face: Option<&str>, data: Vec<u8>) -> Self {
+        Self {
+            face: face.map(str::to_string),
+            data,
+        }
+    }
+}
+
+/// A `TEXTURE` node with its image data.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Texture {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: TexelFormat,
+    pub blocks: Vec<ImageBlock>,
+}
+
+/// Which mip levels [`Texture::save_png`] writes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MipSelection {
+    Level(usize),
+    All,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextureOptions {
+    pub mips: MipSelection,
+    /// Write each cube map face to its own file instead of one strip of six.
+    pub split_faces: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            mips: MipSelection::Level(0),
+            split_faces: true,
+        }
+    }
+}
+
+impl Texture {
+    pub fn new(
+        name: &str,
+        width: u32,
+        height: u32,
+        format: TexelFormat,
+        blocks: Vec<ImageBlock>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            width,
+            height,
+            format,
+            blocks,
+        }
+    }
+
+    pub fn from_element(element: &Element) -> Result<Self, ParseError> {
+        let name = element.attribute("id").unwrap_or_default().to_string();
+        let number = |key: &str| -> Result<u32, ParseError> {
+            element
+                .attribute(key)
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| ParseError::new(&format!("Texture {} has no valid {}", name, key)))
+        };
+        let width = number("width")?;
+        let height = number("height")?;
+        let format: TexelFormat = element
+            .attribute("texelFormat")
+            .ok_or_else(|| ParseError::new(&format!("Texture {} has no texelFormat", name)))?
+            .parse()?;
+
+        let mut blocks = Vec::new();
+        for block in element
+            .children
+            .iter()
+            .filter(|c| c.name == "TEXTUREIMAGEBLOCK")
+        {
+            let Some(data) = block.child("TEXTUREIMAGEBLOCKDATA") else {
+                continue;
+            };
+            let hex: String = data.text.split_whitespace().collect();
+            let data = hex::decode(hex).map_err(|e| {
+                ParseError::new(&format!("Invalid image data in texture {}: {e}", name))
+            })?;
+            let face = block
+                .attribute("typename")
+                .map(|t| t.trim_start_matches("Raw"))
+                .filter(|t| CUBE_FACES.contains(t))
+                .map(str::to_string);
+            blocks.push(ImageBlock { face, data });
+        }
+        if blocks.is_empty() {
+            return Err(ParseError::new(&format!(
+                "Texture {} has no image data",
+                name
+            )));
+        }
+
+        Ok(Self {
+            name,
+            width,
+            height,
+            format,
+            blocks,
+        })
+    }
+
+    pub fn is_cube_map(&self) -> bool {
+        self.blocks.iter().any(|b| b.face.is_some())
+    }
+
+    pub fn mip_size(&self, level: usize) -> (u32, u32) {
+        let shift = level.min(31) as u32;
+        ((self.width >> shift).max(1), (self.height >> shift).max(1))
+    }
+
+    /// Byte offset of a mip level within an image block.
+    fn mip_offset(&self, level: usize) -> usize {
+        (0..level)
+            .map(|l| {
+                let (w, h) = self.mip_size(l);
+                self.format.image_size(w, h)
+            })
+            .sum()
+    }
+
+    /// Mip levels present in every image block. The data is trusted over
+    /// `numberMipMapLevels`, which tools count differently.
+    pub fn mip_count(&self) -> usize {
+        let shortest = self.blocks.iter().map(|b| b.data.len()).min().unwrap_or(0);
+        let mut count = 0;
+        loop {
+            let (w, h) = self.mip_size(count);
+            if self.mip_offset(count) + self.format.image_size(w, h) > shortest {
+                return count;
+            }
+            count += 1;
+            if w == 1 && h == 1 {
+                return count;
+            }
+        }
+    }
+
+    /// Decodes mip `level` of image block `block` to RGBA.
+    pub fn decode(&self, block: usize, level: usize) -> Result<Image, ParseError> {
+        let data = &self
+            .blocks
+            .get(block)
+            .ok_or_else(|| {
+                ParseError::new(&format!("Texture {} has no block {}", self.name, block))
+            })?
+            .data;
+        let (width, height) = self.mip_size(level);
+        let start = self.mip_offset(level);
+        let data = data
+            .get(start..start + self.format.image_size(width, height))
+            .ok_or_else(|| {
+                ParseError::new(&format!("Texture {} has no mip level {}", self.name, level))
+            })?;
+
+        let pixels = match self.format {
+            TexelFormat::Dxt1 | TexelFormat::Dxt3 | TexelFormat::Dxt5 => {
+                decode_blocks(data, width, height, self.format)
+            }
+            TexelFormat::Argb8 => data
+                .chunks_exact(4)
+                .flat_map(|p| [p[1], p[2], p[3], p[0]])
+                .collect(),
+            TexelFormat::Rgba8 => data.to_vec(),
+            TexelFormat::L8 => data.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+        };
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Writes the selected mips as `<name>.png`, adding `_<face>` for cube
+    /// map faces and `_mip<n>` when writing every level. Returns the paths.
+    pub fn save_png(
+        &self,
+        out_dir: &Path,
+        options: &TextureOptions,
+    ) -> Result<Vec<PathBuf>, ParseError> {
+        let levels = match options.mips {
+            MipSelection::Level(level) => {
+                // Textures with shorter chains fall back to their smallest mip.
+                let last = self.mip_count().saturating_sub(1);
+                if level > last {
+                    warn!(
+                        "Texture {} has no mip level {}, writing level {}",
+                        self.name, level, last
+                    );
+                }
+                let level = level.min(last);
+                level..level + 1
+            }
+            MipSelection::All => 0..self.mip_count(),
+        };
+        let stem = Path::new(&self.name)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "texture".to_string());
+
+        let mut written = Vec::new();
+        for level in levels {
+            let mip = match options.mips {
+                MipSelection::All => format!("_mip{}", level),
+                MipSelection::Level(_) => String::new(),
+            };
+            let images = (0..self.blocks.len())
+                .map(|b| self.decode(b, level))
+                .collect::<Result<Vec<_>, _>>()?;
+            if self.is_cube_map() && options.split_faces {
+                for (block, image) in self.blocks.iter().zip(&images) {
+                    let face = block.face.as_deref().unwrap_or_default();
+                    let path = out_dir.join(format!("{}_{}{}.png", stem, face, mip));
+                    image.save_png(&path)?;
+                    written.push(path);
+                }
+            } else {
+                let path = out_dir.join(format!("{}{}.png", stem, mip));
+                Image::strip(&images).save_png(&path)?;
+                written.push(path);
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// Every decodable `TEXTURE` in a PSSG file; textures in other formats are
+/// skipped with a warning.
+pub fn load_textures(path: &Path) -> Result<Vec<Texture>, ParseError> {
+    crate::initialize_tracing();
+    let root = pssg::to_element(archive::read_file(path)?)?;
+    let mut elements = Vec::new();
+    collect_textures(&root, &mut elements);
+
+    let mut textures = Vec::new();
+    for element in elements {
+        match Texture::from_element(element) {
+            Ok(texture) => textures.push(texture),
+            Err(e) => warn!("Skipping texture: {}", e),
+        }
+    }
+    Ok(textures)
+}
+
+fn collect_textures<'a>(element: &'a Element, textures: &mut Vec<&'a Element>) {
+    if element.name == "TEXTURE" {
+        textures.push(element);
+        return;
+    }
+    for child in &element.children {
+        collect_textures(child, textures);
+    }
+}
+
+/// Writes the textures of a PSSG file as PNGs into `out_dir`.
+pub fn export_textures(
+    path: &Path,
+    out_dir: &Path,
+    options: &TextureOptions,
+) -> Result<Vec<PathBuf>, ParseError> {
+    fs::create_dir_all(out_dir)
+        .map_err(|e| ParseError::new(&format!("Failed to create {:?}: {e}", out_dir)))?;
+    let mut written = Vec::new();
+    for texture in load_textures(path)? {
+        if texture.mip_count() == 0 {
+            warn!("Skipping texture {}: image data is truncated", texture.name);
+            continue;
+        }
+        written.extend(texture.save_png(out_dir, options)?);
+    }
+    Ok(written)
+}
+
+fn decode_blocks(data: &[u8], width: u32, height: u32, format: TexelFormat) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(4);
+    let mut pixels = vec![0; width * height * 4];
+    for (i, block) in data.chunks_exact(format.unit_size()).enumerate() {
+        let texels = match format {
+            TexelFormat::Dxt1 => decode_color(block, true),
+            TexelFormat::Dxt3 => {
+                let mut texels = decode_color(&block[8..], false);
+                let alpha = u64::from_le_bytes(block[..8].try_into().unwrap_or_default());
+                for (t, texel) in texels.iter_mut().enumerate() {
+                    texel[3] = ((alpha >> (4 * t)) & 0xF) as u8 * 17;
+                }
+                texels
+            }
+            _ => {
+                let mut texels = decode_color(&block[8..], false);
+                let alphas = alpha_palette(block[0], block[1]);
+                let mut bits = [0; 8];
+                bits[..6].copy_from_slice(&block[2..8]);
+                let indices = u64::from_le_bytes(bits);
+                for (t, texel) in texels.iter_mut().enumerate() {
+                    texel[3] = alphas[((indices >> (3 * t)) & 0x7) as usize];
+                }
+                texels
+            }
+        };
+
+        let (bx, by) = (i % blocks_wide * 4, i / blocks_wide * 4);
+        for (t, texel) in texels.iter().enumerate() {
+            let (x, y) = (bx + t % 4, by + t / 4);
+            if x < width && y < height {
+                let p = (y * width + x) * 4;
+                pixels[p..p + 4].copy_from_slice(texel);
+            }
+        }
+    }
+    pixels
+}
+
+/// The 16 texels of a DXT colour block. Only DXT1 uses the three-colour
+/// mode with transparent black.
+fn decode_color(block: &[u8], dxt1: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u16, wb: u16, total: u16| -> [u8; 4] {
+        let channel = |i: usize| ((a[i] as u16 * wa + b[i] as u16 * wb) / total) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    let palette = if c0 > c1 || !dxt1 {
+        [a, b, mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [a, b, mix(1, 1, 2), [0, 0, 0, 0]]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|t| palette[((indices >> (2 * t)) & 0x3) as usize])
+}
+
+fn rgb565(c: u16) -> [u8; 4] {
+    let r = ((c >> 11) & 0x1F) as u8;
+    let g = ((c >> 5) & 0x3F) as u8;
+    let b = (c & 0x1F) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ]
+}
+
+/// The eight alpha values of a DXT5 block.
+fn alpha_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let (a0, a1) = (a0 as u16, a1 as u16);
+    let mix = |wa: u16, wb: u16, total: u16| ((a0 * wa + a1 * wb) / total) as u8;
+    if a0 > a1 {
+        [
+            a0 as u8,
+            a1 as u8,
+            mix(6, 1, 7),
+            mix(5, 2, 7),
+            mix(4, 3, 7),
+            mix(3, 4, 7),
+            mix(2, 5, 7),
+            mix(1, 6, 7),
+        ]
+    } else {
+        [
+            a0 as u8,
+            a1 as u8,
+            mix(4, 1, 5),
+            mix(3, 2, 5),
+            mix(2, 3, 5),
+            mix(1, 4, 5),
+            0,
+            255,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    /// A colour block with every row using palette entries 0, 1, 2, 3.
+    fn color_block(c0: u16, c1: u16) -> [u8; 8] {
+        let [a, b] = c0.to_le_bytes();
+        let [c, d] = c1.to_le_bytes();
+        [a, b, c, d, 0xE4, 0xE4, 0xE4, 0xE4]
+    }
+
+    fn row(texels: &[[u8; 4]; 16]) -> [[u8; 4]; 4] {
+        [texels[0], texels[1], texels[2], texels[3]]
+    }
+
+    #[test]
+    fn dxt1_four_color_mode() {
+        let texels = decode_color(&color_block(0xF800, 0x001F), true);
+        assert_eq!(
+            row(&texels),
+            [RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]]
+        );
+        assert_eq!(texels[12..], texels[..4]);
+    }
+
+    #[test]
+    fn dxt1_three_color_mode() {
+        let texels = decode_color(&color_block(0x001F, 0xF800), true);
+        assert_eq!(row(&texels), [BLUE, RED, [127, 0, 127, 255], [0; 4]]);
+        // DXT3 and DXT5 colour blocks never use it.
+        let texels = decode_color(&color_block(0x001F, 0xF800), false);
+        assert_eq!(row(&texels)[3], [170, 0, 85, 255]);
+    }
+
+    #[test]
+    fn dxt3_alpha_nibbles() {
+        let mut block = vec![0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE];
+        block.extend([0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+        let pixels = decode_blocks(&block, 4, 4, TexelFormat::Dxt3);
+        for (t, pixel) in pixels.chunks_exact(4).enumerate() {
+            assert_eq!(pixel, [255, 255, 255, t as u8 * 17]);
+        }
+    }
+
+    #[test]
+    fn dxt5_alpha_palettes() {
+        assert_eq!(
+            alpha_palette(200, 100),
+            [200, 100, 185, 171, 157, 142, 128, 114]
+        );
+        assert_eq!(
+            alpha_palette(100, 200),
+            [100, 200, 120, 140, 160, 180, 0, 255]
+        );
+
+        // Texel t uses alpha index t % 8.
+        let mut block = vec![200, 100, 0x88, 0xC6, 0xFA, 0x88, 0xC6, 0xFA];
+        block.extend([0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+        let pixels = decode_blocks(&block, 4, 4, TexelFormat::Dxt5);
+        let palette = alpha_palette(200, 100);
+        for (t, pixel) in pixels.chunks_exact(4).enumerate() {
+            assert_eq!(pixel[..3], WHITE[..3]);
+            assert_eq!(pixel[3], palette[t % 8]);
+        }
+    }
+
+    #[test]
+    fn small_images_clip_blocks() {
+        let pixels = decode_blocks(&color_block(0xF800, 0x001F), 2, 1, TexelFormat::Dxt1);
+        assert_eq!(pixels, [RED, BLUE].concat());
+    }
+
+    #[test]
+    fn grid_of_empty_images() {
+        let empty = Image::filled(0, 0, WHITE);
+        let grid = Image::grid(&[empty.clone(), empty], 2);
+        assert_eq!((grid.width, grid.height), (0, 0));
+
+        let grid = Image::grid(&[Image::filled(0, 2, WHITE), Image::filled(1, 1, RED)], 2);
+        assert_eq!((grid.width, grid.height), (2, 2));
+        assert_eq!(grid.pixels, [[0; 4], RED, [0; 4], [0; 4]].concat());
+    }
+
+    fn texture(format: TexelFormat, width: u32, height: u32, data: Vec<u8>) -> Texture {
+        Texture::new(
+            "test.dds",
+            width,
+            height,
+            format,
+            vec![ImageBlock::new(None, data)],
+        )
+    }
+
+    #[test]
+    fn uncompressed_formats() {
+        let argb = texture(TexelFormat::Argb8, 1, 1, vec![0x80, 0x10, 0x20, 0x30]);
+        assert_eq!(argb.decode(0, 0).unwrap().pixels, [0x10, 0x20, 0x30, 0x80]);
+        let l8 = texture(TexelFormat::L8, 2, 1, vec![0x40, 0xC0]);
+        assert_eq!(
+            l8.decode(0, 0).unwrap().pixels,
+            [0x40, 0x40, 0x40, 255, 0xC0, 0xC0, 0xC0, 255]
+        );
+    }
+
+    #[test]
+    fn missing_mips_fall_back_to_the_smallest() {
+        // A 2x2 level and a 1x1 level.
+        let l8 = texture(TexelFormat::L8, 2, 2, vec![1, 2, 3, 4, 5]);
+        assert_eq!(l8.mip_count(), 2);
+        let out_dir = std::env::temp_dir().join(format!("mips-{}", std::process::id()));
+        fs::create_dir_all(&out_dir).unwrap();
+        let options = TextureOptions {
+            mips: MipSelection::Level(5),
+            ..Default::default()
+        };
+        let written = l8.save_png(&out_dir, &options).unwrap();
+        let image = Image::load_png(&written[0]).unwrap();
+        assert_eq!(image.pixels, [5, 5, 5, 255]);
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use serde::Serialize;
+use tracing::info;
+
+use crate::{
+    entity::Entity,
+    math::{cross, dot, sub},
+    object::Object,
+};
+
+/// Topology problems found in an object. Edges and faces are compared by
+/// vertex position, so vertices split along UV seams count as one.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub struct TopologyReport {
+    /// Edges shared by more than two faces.
+    pub non_manifold_edges: usize,
+    /// Edges used by a single face.
+    pub boundary_edges: usize,
+    /// Connected chains of boundary edges: holes and open borders.
+    pub boundary_loops: usize,
+    /// Edges whose two faces run along them in the same direction, so one
+    /// of the faces is flipped.
+    pub inconsistent_edges: usize,
+    /// Vertices no face uses.
+    pub isolated_vertices: usize,
+    /// Faces with (nearly) zero area.
+    pub degenerate_faces: usize,
+    /// Faces repeating an earlier face with the same winding.
+    pub duplicate_faces: usize,
+}
+
+impl TopologyReport {
+    /// Whether nothing needs repairing; open borders are not problems.
+    pub fn is_clean(&self) -> bool {
+        self.non_manifold_edges == 0
+            && self.inconsistent_edges == 0
+            && self.isolated_vertices == 0
+            && self.degenerate_faces == 0
+            && self.duplicate_faces == 0
+    }
+}
+
+impl fmt::Display for TopologyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = [
+            (self.non_manifold_edges, "non-manifold edges"),
+            (self.boundary_edges, "boundary edges"),
+            (self.boundary_loops, "boundary loops"),
+            (self.inconsistent_edges, "inconsistently wound edges"),
+            (self.isolated_vertices, "isolated vertices"),
+            (self.degenerate_faces, "degenerate faces"),
+            (self.duplicate_faces, "duplicate faces"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        if parts.is_empty() {
+            write!(f, "closed and consistent")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// Which repair passes to run.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RepairOptions {
+    /// Flip faces so every connected patch agrees with its majority winding.
+    pub unify_winding: bool,
+    pub remove_degenerate: bool,
+    pub remove_duplicates: bool,
+    pub remove_unused: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            unify_winding: true,
+            remove_degenerate: true,
+            remove_duplicates: true,
+            remove_unused: true,
+        }
+    }
+}
+
+/// What a repair changed.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub struct RepairReport {
+    pub flipped_faces: usize,
+    pub degenerate_faces: usize,
+    pub duplicate_faces: usize,
+    pub unused_vertices: usize,
+}
+
+impl RepairReport {
+    pub fn is_empty(&self) -> bool {
+        *self == RepairReport::default()
+    }
+
+    fn add(&mut self, other: &RepairReport) {
+        self.flipped_faces += other.flipped_faces;
+        self.degenerate_faces += other.degenerate_faces;
+        self.duplicate_faces += other.duplicate_faces;
+        self.unused_vertices += other.unused_vertices;
+    }
+}
+
+impl fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "flipped {} faces, removed {} degenerate and {} duplicate faces and {} unused vertices",
+            self.flipped_faces, self.degenerate_faces, self.duplicate_faces, self.unused_vertices
+        )
+    }
+}
+
+/// Edge connectivity by position. Degenerate and duplicate faces are left
+/// out of the edges so they are not reported twice.
+struct Analysis {
+    degenerate: Vec<bool>,
+    duplicate: Vec<bool>,
+    /// Faces along each edge between two positions (smaller id first), and
+    /// whether the face runs from the first position to the second.
+    edges: HashMap<(u32, u32), Vec<(usize, bool)>>,
+}
+
+impl Analysis {
+    fn new(object: &Object) -> Self {
+        let mut ids: HashMap<[u32; 3], u32> = HashMap::new();
+        let positions: Vec<u32> = object
+            .vertices
+            .iter()
+            .map(|p| {
+                let next = ids.len() as u32;
+                *ids.entry(p.map(f32::to_bits)).or_insert(next)
+            })
+            .collect();
+
+        let degenerate: Vec<bool> = object
+            .faces
+            .iter()
+            .map(|face| {
+                let [a, b, c] = face.map(|v| positions[v as usize]);
+                a == b || b == c || a == c || zero_area(face.map(|v| object.vertices[v as usize]))
+            })
+            .collect();
+
+        let mut seen: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut duplicate = vec![false; object.faces.len()];
+        for (f, face) in object.faces.iter().enumerate() {
+            if degenerate[f] {
+                continue;
+            }
+            // The same face starting from another corner is still the same.
+            let mut key = face.map(|v| positions[v as usize]);
+            let first = (0..3).min_by_key(|&k| key[k]).unwrap_or(0);
+            key.rotate_left(first);
+            if seen.insert(key, f).is_some() {
+                duplicate[f] = true;
+            }
+        }
+
+        let mut edges: HashMap<(u32, u32), Vec<(usize, bool)>> = HashMap::new();
+        for (f, face) in object.faces.iter().enumerate() {
+            if degenerate[f] || duplicate[f] {
+                continue;
+            }
+            for k in 0..3 {
+                let a = positions[face[k] as usize];
+                let b = positions[face[(k + 1) % 3] as usize];
+                let key = if a < b { (a, b) } else { (b, a) };
+                edges.entry(key).or_default().push((f, a < b));
+            }
+        }
+
+        Self {
+            degenerate,
+            duplicate,
+            edges,
+        }
+    }
+
+    fn report(&self, object: &Object) -> TopologyReport {
+        let mut used = vec![false; object.vertices.len()];
+        for &v in object.faces.iter().flatten() {
+            used[v as usize] = true;
+        }
+
+        let mut report = TopologyReport {
+            isolated_vertices: used.iter().filter(|u| !**u).count(),
+            degenerate_faces: self.degenerate.iter().filter(|d| **d).count(),
+            duplicate_faces: self.duplicate.iter().filter(|d| **d).count(),
+            ..Default::default()
+        };
+
+        let mut loops = UnionFind::default();
+        for (&(a, b), faces) in &self.edges {
+            match faces[..] {
+                [_] => {
+                    report.boundary_edges += 1;
+                    loops.union(a, b);
+                }
+                [(_, x), (_, y)] if x == y => report.inconsistent_edges += 1,
+                [_, _] => {}
+                _ => report.non_manifold_edges += 1,
+            }
+        }
+        report.boundary_loops = loops.components();
+        report
+    }
+
+    /// Faces to flip so that faces sharing a manifold edge agree, keeping
+    /// the winding most of each connected patch already has.
+    fn flips(&self, face_count: usize) -> Vec<bool> {
+        let mut neighbours: Vec<Vec<(usize, bool)>> = vec![Vec::new(); face_count];
+        for faces in self.edges.values() {
+            if let [(f, x), (g, y)] = faces[..] {
+                // Faces running the same way along the edge disagree.
+                neighbours[f].push((g, x == y));
+                neighbours[g].push((f, x == y));
+            }
+        }
+
+        let mut flip = vec![false; face_count];
+        let mut visited = vec![false; face_count];
+        for start in 0..face_count {
+            if visited[start] || self.degenerate[start] || self.duplicate[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut patch = vec![start];
+            let mut queue = VecDeque::from([start]);
+            while let Some(f) = queue.pop_front() {
+                for &(g, disagree) in &neighbours[f] {
+                    if !visited[g] {
+                        visited[g] = true;
+                        flip[g] = flip[f] ^ disagree;
+                        patch.push(g);
+                        queue.push_back(g);
+                    }
+                }
+            }
+            let flipped = patch.iter().filter(|&&f| flip[f]).count();
+            if flipped * 2 > patch.len() {
+                for f in patch {
+                    flip[f] = !flip[f];
+                }
+            }
+        }
+        flip
+    }
+}
+
+#[derive(Default)]
+struct UnionFind {
+    parents: HashMap<u32, u32>,
+    ranks: HashMap<u32, u32>,
+}
+
+impl UnionFind {
+    fn find(&mut self, x: u32) -> u32 {
+        let mut root = x;
+        loop {
+            let parent = *self.parents.entry(root).or_insert(root);
+            if parent == root {
+                break;
+            }
+            root = parent;
+        }
+        // Point the whole path at the root.
+        let mut node = x;
+        while node != root {
+            node = self.parents.insert(node, root).unwrap_or(root);
+        }
+        root
+    }
+
+    fn union(&mut self, a: u32, b: u32) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let rank = |r: u32| self.ranks.get(&r).copied().unwrap_or(0);
+        let (rank_a, rank_b) = (rank(a), rank(b));
+        let (child, root) = if rank_a < rank_b { (a, b) } else { (b, a) };
+        self.parents.insert(child, root);
+        if rank_a == rank_b {
+            self.ranks.insert(root, rank_a + 1);
+        }
+    }
+
+    fn components(&mut self) -> usize {
+        let keys: Vec<u32> = self.parents.keys().copied().collect();
+        keys.into_iter().filter(|&k| self.find(k) == k).count()
+    }
+}
+
+/// Whether the triangle's area is negligible next to its longest edge.
+fn zero_area(corners: [[f32; 3]; 3]) -> bool {
+    let [a, b, c] = corners;
+    let n = cross(sub(b, a), sub(c, a));
+    let longest = [sub(b, a), sub(c, b), sub(a, c)]
+        .iter()
+        .map(|e| dot(*e, *e))
+        .fold(0.0f32, f32::max);
+    dot(n, n).sqrt() <= 1e-6 * longest
+}
+
+impl Object {
+    fn has_valid_faces(&self) -> bool {
+        let count = self.vertices.len();
+        self.faces.iter().flatten().all(|&i| (i as usize) < count)
+    }
+
+    pub fn topology(&self) -> TopologyReport {
+        if !self.has_valid_faces() {
+            return TopologyReport::default();
+        }
+        Analysis::new(self).report(self)
+    }
+
+    /// Runs the repair passes `options` enables.
+    pub fn repair(&mut self, options: &RepairOptions) -> RepairReport {
+        let mut report = RepairReport::default();
+        if !self.has_valid_faces() {
+            return report;
+        }
+        let analysis = Analysis::new(self);
+
+        if options.unify_winding {
+            let flips = analysis.flips(self.faces.len());
+            for (face, _) in self.faces.iter_mut().zip(&flips).filter(|(_, f)| **f) {
+                face.swap(1, 2);
+                report.flipped_faces += 1;
+            }
+        }
+
+        let keep: Vec<bool> = (0..self.faces.len())
+            .map(|f| {
+                let degenerate = options.remove_degenerate && analysis.degenerate[f];
+                let duplicate = options.remove_duplicates && analysis.duplicate[f];
+                report.degenerate_faces += degenerate as usize;
+                report.duplicate_faces += duplicate as usize;
+                !degenerate && !duplicate
+            })
+            .collect();
+        self.retain_faces(&keep);
+
+        if options.remove_unused {
+            report.unused_vertices = self.remove_unused_vertices();
+        }
+        report
+    }
+
+    /// Keeps the faces marked in `keep`, keeping the submesh ranges in step.
+    fn retain_faces(&mut self, keep: &[bool]) {
+        if keep.iter().all(|k| *k) {
+            return;
+        }
+        let mut faces = Vec::with_capacity(self.faces.len());
+        for (i, range) in self.face_ranges().into_iter().enumerate() {
+            let start = faces.len();
+            faces.extend(range.filter(|&f| keep[f]).map(|f| self.faces[f]));
+            if let Some(submesh) = self.submeshes.get_mut(i) {
+                submesh.faces = start..faces.len();
+            }
+        }
+        self.submeshes.retain(|s| !s.faces.is_empty());
+        self.faces = faces;
+    }
+
+    /// Drops vertices no face uses, keeping the order of the rest. Returns
+    /// how many were removed.
+    pub fn remove_unused_vertices(&mut self) -> usize {
+        let count = self.vertices.len();
+        let mut used = vec![false; count];
+        for &v in self.faces.iter().flatten() {
+            used[v as usize] = true;
+        }
+        let kept: Vec<usize> = (0..count).filter(|&v| used[v]).collect();
+        if kept.len() == count {
+            return 0;
+        }
+        let mut remap = vec![0; count];
+        for (new, &old) in kept.iter().enumerate() {
+            remap[old] = new as u32;
+        }
+        for v in self.faces.iter_mut().flatten() {
+            *v = remap[*v as usize];
+        }
+        retain(&mut self.vertices, &kept, count);
+        retain(&mut self.normals, &kept, count);
+        retain(&mut self.colors, &kept, count);
+        retain(&mut self.uvs, &kept, count);
+        retain(&mut self.joints, &kept, count);
+        retain(&mut self.weights, &kept, count);
+        count - kept.len()
+    }
+}
+
+impl Entity {
+    /// Topology of every object.
+    pub fn topology(&self) -> Vec<TopologyReport> {
+        self.objects.iter().map(Object::topology).collect()
+    }
+
+    /// Repairs every object, returning the combined changes.
+    pub fn repair(&mut self, options: &RepairOptions) -> RepairReport {
+        let mut total = RepairReport::default();
+        for object in &mut self.objects {
+            let report = object.repair(options);
+            if !report.is_empty() {
+                info!("Repaired {}: {}", object.name, report);
+            }
+            total.add(&report);
+        }
+        total
+    }
+}
+
+fn retain<T: Copy>(values: &mut Vec<T>, kept: &[usize], count: usize) {
+    if values.len() == count {
+        *values = kept.iter().map(|&i| values[i]).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A closed tetrahedron with every face wound outwards.
+    fn tetrahedron() -> Object {
+        let vertices = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ];
+        let faces = vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+        Object::new("tetrahedron", vertices, faces)
+    }
+
+    fn quad() -> Object {
+        let vertices = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        Object::new("quad", vertices, vec![[0, 1, 2], [0, 2, 3]])
+    }
+
+    #[test]
+    fn closed_tetrahedron_is_clean() {
+        let report = tetrahedron().topology();
+        assert_eq!(report, TopologyReport::default());
+        assert_eq!(report.to_string(), "closed and consistent");
+    }
+
+    #[test]
+    fn flipped_face_is_found_and_repaired() {
+        let mut object = tetrahedron();
+        object.faces[3] = [1, 3, 2];
+        let analysis = Analysis::new(&object);
+        let report = analysis.report(&object);
+        assert_eq!(report.inconsistent_edges, 3);
+        assert_eq!(report.boundary_edges, 0);
+        assert!(!report.is_clean());
+        assert_eq!(analysis.flips(4), vec![false, false, false, true]);
+
+        let repaired = object.repair(&RepairOptions::default());
+        assert_eq!(repaired.flipped_faces, 1);
+        assert_eq!(object.faces[3], [1, 2, 3]);
+        assert!(object.topology().is_clean());
+    }
+
+    #[test]
+    fn quad_has_one_boundary_loop() {
+        let report = quad().topology();
+        assert_eq!(report.boundary_edges, 4);
+        assert_eq!(report.boundary_loops, 1);
+        assert!(report.is_clean());
+        assert_eq!(report.to_string(), "4 boundary edges, 1 boundary loops");
+    }
+
+    #[test]
+    fn seams_do_not_split_boundary_loops() {
+        let mut object = quad();
+        // The second triangle gets its own copies of the diagonal.
+        object.vertices.extend([[0.0, 0.0, 0.0], [1.0, 1.0, 0.0]]);
+        object.faces[1] = [4, 5, 3];
+        let report = object.topology();
+        assert_eq!(report.boundary_edges, 4);
+        assert_eq!(report.boundary_loops, 1);
+        assert_eq!(report.inconsistent_edges, 0);
+    }
+
+    #[test]
+    fn duplicate_and_degenerate_faces() {
+        let mut object = quad();
+        object.vertices.extend([[0.5, 0.0, 0.0], [5.0, 5.0, 5.0]]);
+        object.faces.extend([
+            // The first face again, starting from another corner.
+            [1, 2, 0],
+            // A repeated corner and a sliver along the bottom edge.
+            [0, 1, 1],
+            [0, 4, 1],
+        ]);
+        let report = object.topology();
+        assert_eq!(report.duplicate_faces, 1);
+        assert_eq!(report.degenerate_faces, 2);
+        assert_eq!(report.isolated_vertices, 1);
+        assert_eq!(report.boundary_edges, 4);
+        assert_eq!(report.boundary_loops, 1);
+        assert_eq!(report.non_manifold_edges, 0);
+
+        let repaired = object.repair(&RepairOptions::default());
+        assert_eq!(
+            repaired,
+            RepairReport {
+                flipped_faces: 0,
+                degenerate_faces: 2,
+                duplicate_faces: 1,
+                unused_vertices: 2,
+            }
+        );
+        assert_eq!(object.faces, quad().faces);
+        assert_eq!(object.vertices, quad().vertices);
+        assert!(object.topology().is_clean());
+    }
+
+    #[test]
+    fn union_find_counts_components() {
+        let mut sets = UnionFind::default();
+        for (a, b) in [(1, 2), (3, 4), (2, 3), (10, 11), (4, 1)] {
+            sets.union(a, b);
+        }
+        assert_eq!(sets.components(), 2);
+        assert_eq!(sets.find(4), sets.find(1));
+        assert_ne!(sets.find(1), sets.find(10));
+        // Looking up an unseen value makes it a set of its own.
+        sets.find(20);
+        assert_eq!(sets.components(), 3);
+    }
+}
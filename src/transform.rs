@@ -0,0 +1,127 @@
+use crate::{
+    entity::Entity,
+    math::{multiply, Matrix, IDENTITY},
+    scene::{NodeKind, SceneNode},
+};
+
+/// How node transforms reach the output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub enum TransformMode {
+    /// Keep geometry local and write transforms as matrices.
+    #[default]
+    Keep,
+    /// Bake each object's world transform into its positions and normals.
+    Bake,
+}
+
+impl Entity {
+    /// World transform of every object: the product of the transforms of the
+    /// node that draws it and all its ancestors. Objects no node draws get the
+    /// identity; objects drawn by several nodes use the first.
+    pub fn object_transforms(&self) -> Vec<Matrix> {
+        self.object_placements()
+            .into_iter()
+            .map(|p| p.first().copied().unwrap_or(IDENTITY))
+            .collect()
+    }
+
+    /// World transforms of every node drawing each object, in scene order.
+    pub fn object_placements(&self) -> Vec<Vec<Matrix>> {
+        let mut placements = vec![Vec::new(); self.objects.len()];
+        if let Some(root) = &self.root {
+            collect_placements(root, &IDENTITY, &mut placements);
+        }
+        placements
+    }
+
+    /// Moves every object into world space and resets the node transforms, so
+    /// the geometry needs no further placement. An object drawn by several
+    /// nodes is copied once per extra node, and each node draws its own copy.
+    ///
+    /// Skinned objects stay in bind space; joints keep their world transforms
+    /// so the skeleton still poses them.
+    pub fn bake_transforms(&mut self) {
+        let placements = self.object_placements();
+        let mut copies: Vec<Vec<usize>> = (0..self.objects.len()).map(|i| vec![i]).collect();
+        for (i, transforms) in placements.iter().enumerate() {
+            if self.objects[i].is_skinned() {
+                continue;
+            }
+            for (n, transform) in transforms.iter().enumerate().skip(1) {
+                let mut copy = self.objects[i].clone();
+                copy.name = self.unique_object_name(&format!("{}_{}", copy.name, n));
+                copy.transform(transform);
+                copies[i].push(self.objects.len());
+                self.objects.push(copy);
+            }
+            if let Some(transform) = transforms.first().filter(|&t| *t != IDENTITY) {
+                self.objects[i].transform(transform);
+            }
+        }
+        if let Some(root) = &mut self.root {
+            let mut drawn = vec![0; copies.len()];
+            assign_copies(root, &copies, &mut drawn);
+            reset_transforms(root, &IDENTITY);
+        }
+        self.translation = [0.0; 3];
+    }
+
+    fn unique_object_name(&self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.objects.iter().any(|o| o.name == unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        unique
+    }
+
+    /// Applies `mode`; keeping transforms leaves the entity unchanged.
+    pub fn apply_transform_mode(&mut self, mode: TransformMode) {
+        if mode == TransformMode::Bake {
+            self.bake_transforms();
+        }
+    }
+}
+
+fn collect_placements(node: &SceneNode, parent: &Matrix, placements: &mut [Vec<Matrix>]) {
+    let world = multiply(&node.transform, parent);
+    for &i in &node.objects {
+        placements[i].push(world);
+    }
+    for child in &node.children {
+        collect_placements(child, &world, placements);
+    }
+}
+
+/// Points the nth node drawing an object at its nth copy, visiting nodes in
+/// the order [`collect_placements`] does.
+fn assign_copies(node: &mut SceneNode, copies: &[Vec<usize>], drawn: &mut [usize]) {
+    for i in &mut node.objects {
+        let n = drawn[*i];
+        drawn[*i] += 1;
+        if let Some(&copy) = copies[*i].get(n) {
+            *i = copy;
+        }
+    }
+    for child in &mut node.children {
+        assign_copies(child, copies, drawn);
+    }
+}
+
+fn reset_transforms(node: &mut SceneNode, parent: &Matrix) {
+    let world = multiply(&node.transform, parent);
+    if node.kind == NodeKind::Joint {
+        // The joint's parents now have identity transforms, so its local
+        // transform becomes its world transform; its subtree is unchanged.
+        node.transform = world;
+        return;
+    }
+    node.transform = IDENTITY;
+    // The box was in the node's old space.
+    node.bounding_box = None;
+    for child in &mut node.children {
+        reset_transforms(child, &world);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    coords::UpAxis,
+    entity::Entity,
+    error::ParseError,
+    level::Level,
+    math::{Matrix, IDENTITY},
+    object::Object,
+    scene::SceneNode,
+};
+
+const DEFAULT_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+
+impl Entity {
+    pub fn to_usda(&self) -> String {
+        let mut usda = header("Root", self.coordinate_system.up());
+        usda.push_str("def Xform \"Root\"\n{\n");
+        write_entity(&mut usda, self, 1);
+        usda.push_str("}\n");
+        usda
+    }
+}
+
+/// A `.usda` layer holding several placed entities. Entities added under the
+/// same name are written once as a prototype and referenced by every instance.
+#[derive(Debug)]
+pub struct UsdStage {
+    /// `(name the entity was added under, prim name, prim body)`.
+    prototypes: Vec<(String, String, String)>,
+    /// Prim names of the prototypes, which must stay unique even when
+    /// different names sanitize to the same identifier.
+    prototype_names: HashSet<String>,
+    instances: Vec<(String, String, Matrix)>,
+    up_axis: UpAxis,
+}
+
+impl UsdStage {
+    pub fn new() -> Self {
+        Self {
+            prototypes: Vec::new(),
+            prototype_names: HashSet::new(),
+            instances: Vec::new(),
+            up_axis: UpAxis::Y,
+        }
+    }
+
+    pub fn add_instance(&mut self, name: &str, entity: &Entity, transform: &Matrix) {
+        self.up_axis = entity.coordinate_system.up();
+        let prototype = match self.prototypes.iter().find(|(n, _, _)| n == name) {
+            Some((_, prototype, _)) => prototype.clone(),
+            None => {
+                let prototype = unique_name(&mut self.prototype_names, prim_name(name, "model"));
+                let mut body = String::new();
+                write_entity(&mut body, entity, 2);
+                self.prototypes
+                    .push((name.to_string(), prototype.clone(), body));
+                prototype
+            }
+        };
+
+        let instance = format!("{}_{}", prototype, self.instances.len());
+        self.instances.push((instance, prototype, *transform));
+    }
+
+    pub fn to_usda(&self) -> String {
+        let mut usda = header("World", self.up_axis);
+
+        usda.push_str("class Xform \"Prototypes\"\n{\n");
+        for (_, name, body) in &self.prototypes {
+            usda.push_str(&format!("    def Xform \"{}\"\n    {{\n", name));
+            usda.push_str(body);
+            usda.push_str("    }\n");
+        }
+        usda.push_str("}\n\n");
+
+        usda.push_str("def Xform \"World\"\n{\n");
+        for (name, prototype, transform) in &self.instances {
+            usda.push_str(&format!(
+                "    def Xform \"{}\" (\n        instanceable = true\n        prepend references = </Prototypes/{}>\n    )\n    {{\n",
+                name, prototype
+            ));
+            write_transform(&mut usda, transform, 2);
+            usda.push_str("    }\n");
+        }
+        usda.push_str("}\n");
+        usda
+    }
+}
+
+impl Default for UsdStage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Level {
+    /// Builds one layer with every unique mesh written once as a prototype
+    /// and each instance referencing it.
+    pub fn to_usda(&self) -> String {
+        let mut stage = UsdStage::new();
+        for instance in &self.instances {
+            let (name, _, entity) = &self.entities[instance.entity];
+            stage.add_instance(name, entity, &instance.transform);
+        }
+        stage.up_axis = self.coordinate_system.up();
+        stage.to_usda()
+    }
+
+    pub fn save_usda(&self, path: &Path) -> Result<(), ParseError> {
+        fs::write(path, self.to_usda())
+            .map_err(|e| ParseError::new(&format!("Failed to write file {:?}: {e}", path)))
+    }
+}
+
+fn header(default_prim: &str, up_axis: UpAxis) -> String {
+    format!(
+        "#usda 1.0\n(\n    defaultPrim = \"{}\"\n    doc = \"journey-model-parser {}\"\n    metersPerUnit = 1\n    upAxis = \"{}\"\n)\n\n",
+        default_prim,
+        env!("CARGO_PKG_VERSION"),
+        up_axis
+    )
+}
+
+fn write_entity(usda: &mut String, entity: &Entity, depth: usize) {
+    let mut placed = HashSet::new();
+    let mut names = HashSet::new();
+    if let Some(root) = &entity.root {
+        write_node(usda, entity, root, depth, &mut names, &mut placed);
+    }
+    for (i, object) in entity.objects.iter().enumerate() {
+        if !placed.contains(&i) {
+            let name = unique_name(&mut names, mesh_name(object, i));
+            write_mesh(usda, object, &name, depth);
+        }
+    }
+}
+
+/// Writes `node` under a parent whose children are named `siblings`.
+fn write_node(
+    usda: &mut String,
+    entity: &Entity,
+    node: &SceneNode,
+    depth: usize,
+    siblings: &mut HashSet<String>,
+    placed: &mut HashSet<usize>,
+) {
+    let indent = "    ".repeat(depth);
+    usda.push_str(&format!(
+        "{}def Xform \"{}\"\n{}{{\n",
+        indent,
+        unique_name(siblings, prim_name(&node.id, "node")),
+        indent
+    ));
+    write_transform(usda, &node.transform, depth + 1);
+    let mut children = HashSet::new();
+    for &i in &node.objects {
+        let name = unique_name(&mut children, mesh_name(&entity.objects[i], i));
+        write_mesh(usda, &entity.objects[i], &name, depth + 1);
+        placed.insert(i);
+    }
+    for child in &node.children {
+        write_node(usda, entity, child, depth + 1, &mut children, placed);
+    }
+    usda.push_str(&format!("{}}}\n", indent));
+}
+
+fn write_transform(usda: &mut String, transform: &Matrix, depth: usize) {
+    if *transform == IDENTITY {
+        return;
+    }
+    let indent = "    ".repeat(depth);
+    let rows: Vec<String> = transform
+        .iter()
+        .map(|r| format!("({}, {}, {}, {})", r[0], r[1], r[2], r[3]))
+        .collect();
+    usda.push_str(&format!(
+        "{}matrix4d xformOp:transform = ( {} )\n",
+        indent,
+        rows.join(", ")
+    ));
+    usda.push_str(&format!(
+        "{}uniform token[] xformOpOrder = [\"xformOp:transform\"]\n",
+        indent
+    ));
+}
+
+fn write_mesh(usda: &mut String, object: &Object, name: &str, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let inner = "    ".repeat(depth + 1);
+    let vertex_count = object.vertices.len();
+
+    usda.push_str(&format!("{}def Mesh \"{}\"\n{}{{\n", indent, name, indent));
+    usda.push_str(&format!(
+        "{}int[] faceVertexCounts = [{}]\n",
+        inner,
+        vec!["3"; object.faces.len()].join(", ")
+    ));
+    usda.push_str(&format!(
+        "{}int[] faceVertexIndices = [{}]\n",
+        inner,
+        join(object.faces.iter().flatten())
+    ));
+    usda.push_str(&format!(
+        "{}point3f[] points = [{}]\n",
+        inner,
+        tuples(object.vertices.iter().map(|v| v.as_slice()))
+    ));
+    if !object.normals.is_empty() && object.normals.len() == vertex_count {
+        usda.push_str(&format!(
+            "{}normal3f[] normals = [{}] (\n{}    interpolation = \"vertex\"\n{})\n",
+            inner,
+            tuples(object.normals.iter().map(|n| n.as_slice())),
+            inner,
+            inner
+        ));
+    }
+    if !object.uvs.is_empty() && object.uvs.len() == vertex_count {
+        usda.push_str(&format!(
+            "{}texCoord2f[] primvars:st = [{}] (\n{}    interpolation = \"vertex\"\n{})\n",
+            inner,
+            tuples(object.uvs.iter().map(|uv| uv.as_slice())),
+            inner,
+            inner
+        ));
+    }
+    if !object.colors.is_empty() && object.colors.len() == vertex_count {
+        usda.push_str(&format!(
+            "{}color3f[] primvars:displayColor = [{}] (\n{}    interpolation = \"vertex\"\n{})\n",
+            inner,
+            tuples(object.colors.iter().map(|c| &c[..3])),
+            inner,
+            inner
+        ));
+    } else {
+        usda.push_str(&format!(
+            "{}color3f[] primvars:displayColor = [{}] (\n{}    interpolation = \"constant\"\n{})\n",
+            inner,
+            tuples([DEFAULT_COLOR.as_slice()].into_iter()),
+            inner,
+            inner
+        ));
+    }
+    usda.push_str(&format!(
+        "{}uniform token subdivisionScheme = \"none\"\n",
+        inner
+    ));
+    let mut subsets = HashSet::new();
+    for submesh in &object.submeshes {
+        usda.push_str(&format!(
+            "{}def GeomSubset \"{}\"\n{}{{\n",
+            inner,
+            unique_name(&mut subsets, prim_name(&submesh.name, "subset")),
+            inner
+        ));
+        usda.push_str(&format!(
+            "{}    uniform token elementType = \"face\"\n",
+            inner
+        ));
+        usda.push_str(&format!(
+            "{}    uniform token familyName = \"materialBind\"\n",
+            inner
+        ));
+        usda.push_str(&format!(
+            "{}    int[] indices = [{}]\n",
+            inner,
+            join(submesh.faces.clone())
+        ));
+        usda.push_str(&format!("{}}}\n", inner));
+    }
+    usda.push_str(&format!("{}}}\n", indent));
+}
+
+fn mesh_name(object: &Object, i: usize) -> String {
+    prim_name(&object.name, &format!("mesh{}", i))
+}
+
+/// Turns an arbitrary name into a valid USD prim identifier, using
+/// `fallback` for an empty name.
+fn prim_name(name: &str, fallback: &str) -> String {
+    if name.is_empty() {
+        return fallback.to_string();
+    }
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name
+    } else {
+        format!("_{}", name)
+    }
+}
+
+/// Returns `name`, or `name` with a numbered suffix if `used` already has it,
+/// and records the result in `used`.
+fn unique_name(used: &mut HashSet<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut n = 2;
+    while !used.insert(unique.clone()) {
+        unique = format!("{}_{}", name, n);
+        n += 1;
+    }
+    unique
+}
+
+fn tuples<'a>(values: impl Iterator<Item = &'a [f32]>) -> String {
+    values
+        .map(|v| format!("({})", join(v)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn join<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+    values
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use tracing::info;
+
+use crate::{entity::Entity, object::Object};
+
+/// Which vertices count as duplicates when welding.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[non_exhaustive]
+pub enum WeldMode {
+    /// Position, normal, UV, color and skin data must all match, so seams
+    /// stay intact.
+    #[default]
+    Attributes,
+    /// Only positions must match; the first vertex's attributes are kept.
+    Position,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WeldOptions {
+    pub mode: WeldMode,
+    /// Largest per-component difference between welded values; 0 only welds
+    /// exact duplicates.
+    pub tolerance: f32,
+}
+
+impl Default for WeldOptions {
+    fn default() -> Self {
+        Self {
+            mode: WeldMode::Attributes,
+            tolerance: 0.0,
+        }
+    }
+}
+
+impl Object {
+    /// Merges duplicate vertices and remaps the faces, dropping faces that
+    /// collapse. Returns the number of vertices removed.
+    pub fn weld(&mut self, options: &WeldOptions) -> usize {
+        let count = self.vertices.len();
+        let tolerance = options.tolerance.max(0.0);
+        let cell = |p: &[f32; 3]| -> [i64; 3] {
+            if tolerance == 0.0 {
+                // Adding 0.0 turns -0.0 into 0.0 so the two share a key.
+                p.map(|v| (v + 0.0).to_bits() as i64)
+            } else {
+                // The cast saturates for tiny tolerances; so does the
+                // neighbour search below.
+                p.map(|v| (v / tolerance).floor() as i64)
+            }
+        };
+        let offsets: &[i64] = if tolerance == 0.0 { &[0] } else { &[-1, 0, 1] };
+
+        let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        let mut remap = Vec::with_capacity(count);
+        let mut kept = Vec::new();
+        for i in 0..count {
+            let key = cell(&self.vertices[i]);
+            let mut found = None;
+            'search: for &x in offsets {
+                for &y in offsets {
+                    for &z in offsets {
+                        let neighbour = [
+                            key[0].saturating_add(x),
+                            key[1].saturating_add(y),
+                            key[2].saturating_add(z),
+                        ];
+                        let Some(candidates) = grid.get(&neighbour) else {
+                            continue;
+                        };
+                        for &k in candidates {
+                            if self.same_vertex(kept[k as usize], i, options.mode, tolerance) {
+                                found = Some(k);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+            let index = found.unwrap_or_else(|| {
+                kept.push(i);
+                let index = kept.len() as u32 - 1;
+                grid.entry(key).or_default().push(index);
+                index
+            });
+            remap.push(index);
+        }
+
+        let removed = count - kept.len();
+        if removed == 0 {
+            return 0;
+        }
+
+        self.vertices = compact(&self.vertices, &kept, count);
+        self.normals = compact(&self.normals, &kept, count);
+        self.colors = compact(&self.colors, &kept, count);
+        self.uvs = compact(&self.uvs, &kept, count);
+        self.joints = compact(&self.joints, &kept, count);
+        self.weights = compact(&self.weights, &kept, count);
+        self.remap_faces(&remap);
+        removed
+    }
+
+    fn same_vertex(&self, a: usize, b: usize, mode: WeldMode, tolerance: f32) -> bool {
+        let count = self.vertices.len();
+        let close = |x: &[f32], y: &[f32]| x.iter().zip(y).all(|(x, y)| (x - y).abs() <= tolerance);
+        if !close(&self.vertices[a], &self.vertices[b]) {
+            return false;
+        }
+        if mode == WeldMode::Position {
+            return true;
+        }
+        (self.normals.len() != count || close(&self.normals[a], &self.normals[b]))
+            && (self.uvs.len() != count || close(&self.uvs[a], &self.uvs[b]))
+            && (self.colors.len() != count || close(&self.colors[a], &self.colors[b]))
+            && (self.weights.len() != count || close(&self.weights[a], &self.weights[b]))
+            && (self.joints.len() != count || self.joints[a] == self.joints[b])
+    }
+
+    /// Points the faces at the welded vertices and drops the ones that no
+    /// longer span three vertices, keeping the submesh ranges in step.
+    fn remap_faces(&mut self, remap: &[u32]) {
+        let ranges = self.face_ranges();
+        let mut faces = Vec::with_capacity(self.faces.len());
+        for (i, range) in ranges.into_iter().enumerate() {
+            let start = faces.len();
+            for face in &self.faces[range] {
+                let face = face.map(|v| remap.get(v as usize).copied().unwrap_or(v));
+                if face[0] != face[1] && face[1] != face[2] && face[0] != face[2] {
+                    faces.push(face);
+                }
+            }
+            if let Some(submesh) = self.submeshes.get_mut(i) {
+                submesh.faces = start..faces.len();
+            }
+        }
+        self.submeshes.retain(|s| !s.faces.is_empty());
+        self.faces = faces;
+    }
+}
+
+impl Entity {
+    /// Welds every object, returning the total number of vertices removed.
+    pub fn weld(&mut self, options: &WeldOptions) -> usize {
+        let before: usize = self.objects.iter().map(|o| o.vertices.len()).sum();
+        let removed = self.objects.iter_mut().map(|o| o.weld(options)).sum();
+        info!("Welded {} of {} vertices", removed, before);
+        removed
+    }
+}
+
+/// Keeps the `kept` elements of a per-vertex attribute; attributes that do
+/// not have one value per vertex are left alone.
+fn compact<T: Copy>(values: &[T], kept: &[usize], count: usize) -> Vec<T> {
+    if values.len() != count {
+        return values.to_vec();
+    }
+    kept.iter().map(|&i| values[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Submesh;
+
+    fn weld(object: &mut Object, mode: WeldMode, tolerance: f32) -> usize {
+        object.weld(&WeldOptions { mode, tolerance })
+    }
+
+    #[test]
+    fn exact_duplicates_and_signed_zero() {
+        let vertices = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [-0.0, 0.0, -0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ];
+        let mut object = Object::new("quad", vertices, vec![[0, 1, 2], [3, 5, 4]]);
+        assert_eq!(weld(&mut object, WeldMode::Attributes, 0.0), 2);
+        assert_eq!(object.vertices.len(), 4);
+        assert_eq!(object.faces, vec![[0, 1, 2], [0, 1, 3]]);
+    }
+
+    #[test]
+    fn exact_welding_ignores_near_misses() {
+        let vertices = vec![[0.0, 0.0, 0.0], [1e-6, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let mut object = Object::new("tri", vertices, vec![[0, 1, 2]]);
+        assert_eq!(weld(&mut object, WeldMode::Attributes, 0.0), 0);
+        assert_eq!(object.faces, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn tolerance_welds_across_cells() {
+        // Cells are as wide as the tolerance, so 0.099 and 0.101 land in
+        // neighbouring ones.
+        let vertices = vec![
+            [0.099, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.101, 0.0, 0.0],
+            [0.2, 0.0, 0.0],
+        ];
+        let mut object = Object::new("strip", vertices, vec![[0, 2, 1], [3, 4, 2]]);
+        assert_eq!(weld(&mut object, WeldMode::Attributes, 0.01), 1);
+        assert_eq!(object.vertices.len(), 4);
+        assert_eq!(object.faces, vec![[0, 2, 1], [0, 3, 2]]);
+    }
+
+    /// Two triangles sharing an edge whose vertices are split by a UV seam.
+    fn seam() -> Object {
+        let vertices = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+        ];
+        let mut object = Object::new("seam", vertices, vec![[0, 1, 2], [3, 5, 4]]);
+        object.uvs = vec![
+            [0.0, 0.0],
+            [0.5, 0.0],
+            [0.0, 0.5],
+            [0.5, 0.5],
+            [1.0, 0.5],
+            [1.0, 1.0],
+        ];
+        object
+    }
+
+    #[test]
+    fn attributes_mode_keeps_seams() {
+        let mut object = seam();
+        assert_eq!(weld(&mut object, WeldMode::Attributes, 0.0), 0);
+        assert_eq!(object.vertices.len(), 6);
+    }
+
+    #[test]
+    fn position_mode_welds_seams() {
+        let mut object = seam();
+        assert_eq!(weld(&mut object, WeldMode::Position, 0.0), 2);
+        assert_eq!(object.faces, vec![[0, 1, 2], [1, 3, 2]]);
+        // The first vertex's attributes win.
+        assert_eq!(
+            object.uvs,
+            vec![[0.0, 0.0], [0.5, 0.0], [0.0, 0.5], [1.0, 1.0]]
+        );
+    }
+
+    #[test]
+    fn submesh_ranges_follow_dropped_faces() {
+        let vertices = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+        ];
+        let faces = vec![[0, 1, 2], [1, 3, 2], [1, 0, 3], [3, 4, 2]];
+        let mut object = Object::new("merged", vertices, faces);
+        object.submeshes = vec![
+            Submesh::new("a", None, 0..2),
+            Submesh::new("collapsed", None, 2..3),
+            Submesh::new("b", Some("stone"), 3..4),
+        ];
+        assert_eq!(weld(&mut object, WeldMode::Attributes, 0.0), 1);
+
+        assert_eq!(object.faces, vec![[0, 1, 2], [1, 3, 2]]);
+        let ranges: Vec<_> = object
+            .submeshes
+            .iter()
+            .map(|s| (s.name.as_str(), s.faces.clone()))
+            .collect();
+        assert_eq!(ranges, vec![("a", 0..1), ("b", 1..2)]);
+    }
+}
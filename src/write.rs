@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use tracing::warn;
+
+use crate::{
+    archive,
+    entity::Entity,
+    error::ParseError,
+    object::{source_name, unorm_scale, Object, StreamInfo},
+    parse::{stream_format, to_big_endian, write, Encoding, ParseOptions},
+    pssg,
+    structure::{self, PssgFile},
+    xml::Element,
+};
+
+impl PssgFile {
+    /// Re-encodes the geometry of `entity` into the data blocks and index
+    /// sources it was originally decoded from. Objects are matched to their
+    /// `RENDERDATASOURCE` by the name they were parsed with.
+    ///
+    /// Fails if an object with faces has no source to write them to, or if
+    /// objects sharing a data block no longer agree on its contents.
+    pub fn apply_entity(&mut self, entity: &Entity) -> Result<(), ParseError> {
+        let mut blocks: HashMap<&str, (&str, (Vec<f32>, usize))> = HashMap::new();
+        let mut indices = HashMap::new();
+        for object in &entity.objects {
+            for stream in &object.streams {
+                let Some(values) = stream_values(object, stream)? else {
+                    continue;
+                };
+                match blocks.get(stream.block_id.as_str()) {
+                    Some((other, existing)) if *existing != values => {
+                        return Err(ParseError::new(&format!(
+                            "Objects {} and {} share data block {} but no longer agree on its contents",
+                            other, object.name, stream.block_id
+                        )));
+                    }
+                    Some(_) => {}
+                    None => {
+                        blocks.insert(stream.block_id.as_str(), (object.name.as_str(), values));
+                    }
+                }
+            }
+            let faces: Vec<u32> = object.faces.iter().flatten().copied().collect();
+            indices.insert(object.name.as_str(), faces);
+        }
+
+        let mut written = HashSet::new();
+        for library in &mut self.database.libraries {
+            for block in &mut library.data_blocks {
+                let (Some((_, (values, element_count))), Some(data)) =
+                    (blocks.get(block.id.as_str()), &mut block.data)
+                else {
+                    continue;
+                };
+                let (data_type, _) = stream_format(&block.stream.data_type)?;
+                data.text = write(values, data_type, Encoding::of(&data.text))?;
+                block.element_count = *element_count;
+            }
+
+            for source in library
+                .segment_sets
+                .iter_mut()
+                .flat_map(|s| s.render_data_sources.iter_mut())
+            {
+                let Some((name, faces)) =
+                    source_name(source).and_then(|n| indices.get_key_value(n))
+                else {
+                    continue;
+                };
+                let index_source = &mut source.index_source;
+                if let Some(data) = &mut index_source.index_data {
+                    data.text = write(faces, &index_source.data_type, Encoding::of(&data.text))?;
+                    index_source.count = faces.len();
+                    written.insert(*name);
+                }
+            }
+        }
+
+        if let Some(object) = entity
+            .objects
+            .iter()
+            .find(|o| !o.faces.is_empty() && !written.contains(o.name.as_str()))
+        {
+            return Err(ParseError::new(&format!(
+                "Object {} has no index source to write its faces to",
+                object.name
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Flattens the attribute of `object` that `stream` was decoded into, padded
+/// to the component count of the stream's data type.
+fn stream_values(
+    object: &Object,
+    stream: &StreamInfo,
+) -> Result<Option<(Vec<f32>, usize)>, ParseError> {
+    let (data_type, count) = stream_format(&stream.data_type)?;
+    let unorm = unorm_scale(data_type);
+    let normalized = unorm.unwrap_or(1.0);
+
+    let rows: Vec<Vec<f32>> = match stream.render_type.as_str() {
+        "Vertex" | "SkinnableVertex" => object.vertices.iter().map(|v| v.to_vec()).collect(),
+        "Normal" | "SkinnableNormal" => match unorm {
+            // The inverse of the [-1, 1] mapping in decoding.
+            Some(scale) => object
+                .normals
+                .iter()
+                .map(|n| n.iter().map(|v| (v + 1.0) / 2.0 * scale).collect())
+                .collect(),
+            None => object.normals.iter().map(|n| n.to_vec()).collect(),
+        },
+        "ST" => object.uvs.iter().map(|uv| uv.to_vec()).collect(),
+        "Color" if stream.data_type == "uint_color_argb" => object
+            .colors
+            .iter()
+            .map(|c| vec![c[3] * 255.0, c[0] * 255.0, c[1] * 255.0, c[2] * 255.0])
+            .collect(),
+        "Color" => object
+            .colors
+            .iter()
+            .map(|c| c.iter().map(|v| v * normalized).collect())
+            .collect(),
+        "SkinIndices" => object
+            .joints
+            .iter()
+            .map(|j| j.iter().map(|v| *v as f32).collect())
+            .collect(),
+        "SkinWeights" => object
+            .weights
+            .iter()
+            .map(|w| w.iter().map(|v| v * normalized).collect())
+            .collect(),
+        _ => return Ok(None),
+    };
+
+    if rows.is_empty() {
+        warn!(
+            "Object {} has no data for {} stream {}, keeping original",
+            object.name, stream.render_type, stream.block_id
+        );
+        return Ok(None);
+    }
+
+    let pad = if stream.render_type.ends_with("Vertex") {
+        1.0
+    } else {
+        0.0
+    };
+    let mut values = Vec::with_capacity(rows.len() * count);
+    for row in &rows {
+        for i in 0..count {
+            values.push(
+                row.get(i)
+                    .copied()
+                    .unwrap_or(if i == 3 { pad } else { 0.0 }),
+            );
+        }
+    }
+    if unorm.is_some() {
+        // Integer streams hold whole numbers; round before encoding.
+        for value in &mut values {
+            *value = value.round();
+        }
+    }
+    Ok(Some((values, rows.len())))
+}
+
+/// A PSSG XML file kept as a generic element tree, so it can be written back
+/// without losing anything the typed structures don't model.
+#[derive(Debug)]
+pub struct PssgDocument {
+    root: Element,
+    little_endian: bool,
+}
+
+impl PssgDocument {
+    pub fn load(xml_file: &Path) -> Result<Self, ParseError> {
+        Self::load_with(xml_file, &ParseOptions::default())
+    }
+
+    /// Loads a document like [`PssgDocument::load`], reading hex payloads in
+    /// the byte order of `options`. They are kept big-endian like those of
+    /// [`PssgFile`] and written back in the original order.
+    pub fn load_with(xml_file: &Path, options: &ParseOptions) -> Result<Self, ParseError> {
+        let mut root = pssg::to_element(archive::read_file(xml_file)?)?;
+        if root.name != "PSSGFILE" {
+            return Err(ParseError::new(&format!(
+                "Expected PSSGFILE root element, found {}",
+                root.name
+            )));
+        }
+        let little_endian = options.is_little_endian();
+        if little_endian {
+            to_big_endian(&mut root);
+        }
+        Ok(Self {
+            root,
+            little_endian,
+        })
+    }
+
+    /// Copies payloads and counts from `pssg` into the matching elements
+    /// (data blocks by id, index sources by the name of their object), then
+    /// recomputes the TYPEINFO counts.
+    pub fn update(&mut self, pssg: &PssgFile) {
+        let blocks: HashMap<_, _> = pssg
+            .database
+            .libraries
+            .iter()
+            .flat_map(|l| &l.data_blocks)
+            .map(|b| (b.id.as_str(), b))
+            .collect();
+        let index_sources: HashMap<_, _> = pssg
+            .database
+            .libraries
+            .iter()
+            .flat_map(|l| &l.segment_sets)
+            .flat_map(|s| &s.render_data_sources)
+            .filter_map(|r| source_name(r).map(|name| (name, &r.index_source)))
+            .collect();
+
+        self.root
+            .visit_mut(&mut |element| match element.name.as_str() {
+                "DATABLOCK" => {
+                    let Some(block) = element.attribute("id").and_then(|id| blocks.get(id)) else {
+                        return;
+                    };
+                    element.set_attribute("elementCount", &block.element_count.to_string());
+                    let stride = element
+                        .child("DATABLOCKSTREAM")
+                        .and_then(|s| s.attribute("stride"))
+                        .and_then(|s| s.parse::<usize>().ok());
+                    if let (Some(stride), Some(_)) = (stride, element.attribute("size")) {
+                        element.set_attribute("size", &(stride * block.element_count).to_string());
+                    }
+                    if let (Some(data), Some(child)) =
+                        (&block.data, element.child_mut("DATABLOCKDATA"))
+                    {
+                        child.text = data.text.clone();
+                    }
+                }
+                "RENDERDATASOURCE" => {
+                    let name = element
+                        .child("RENDERINDEXSOURCE")
+                        .and_then(|i| i.attribute("id"))
+                        .or(element.attribute("id"));
+                    let Some(source) = name.and_then(|name| index_sources.get(name)) else {
+                        return;
+                    };
+                    let Some(element) = element.child_mut("RENDERINDEXSOURCE") else {
+                        return;
+                    };
+                    element.set_attribute("count", &source.count.to_string());
+                    element.set_attribute("format", &source.data_type);
+                    if let (Some(data), Some(child)) =
+                        (&source.index_data, element.child_mut("INDEXSOURCEDATA"))
+                    {
+                        child.text = data.text.clone();
+                    }
+                }
+                _ => {}
+            });
+
+        self.update_type_info();
+    }
+
+    fn update_type_info(&mut self) {
+        let Some(database) = self.root.child_mut("PSSGDATABASE") else {
+            return;
+        };
+        let counts: Vec<(String, usize)> = database
+            .children
+            .iter()
+            .filter(|c| c.name == "TYPEINFO")
+            .filter_map(|c| c.attribute("typeName"))
+            .map(|name| {
+                let count = database
+                    .children
+                    .iter()
+                    .filter(|c| c.name == "LIBRARY")
+                    .map(|c| c.count(name))
+                    .sum();
+                (name.to_string(), count)
+            })
+            .collect();
+
+        for type_info in database
+            .children
+            .iter_mut()
+            .filter(|c| c.name == "TYPEINFO")
+        {
+            let name = type_info
+                .attribute("typeName")
+                .unwrap_or_default()
+                .to_string();
+            if let Some((_, count)) = counts.iter().find(|(n, _)| *n == name) {
+                type_info.set_attribute("typeCount", &count.to_string());
+            }
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        if !self.little_endian {
+            return self.root.to_xml();
+        }
+        let mut root = self.root.clone();
+        to_big_endian(&mut root);
+        root.to_xml()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ParseError> {
+        let mut file = File::create(path)
+            .map_err(|e| ParseError::new(&format!("Failed to create file {:?}: {e}", path)))?;
+        file.write_all(self.to_xml().as_bytes())
+            .map_err(|e| ParseError::new(&format!("Failed to write file {:?}: {e}", path)))
+    }
+}
+
+/// Writes `entity` back into a copy of the PSSG XML file it was parsed from.
+pub fn write_entity(original: &Path, entity: &Entity, out: &Path) -> Result<(), ParseError> {
+    write_entity_with(original, entity, out, &ParseOptions::default())
+}
+
+/// Writes `entity` back like [`write_entity`] into a file that was parsed
+/// with `options`, keeping its byte order.
+pub fn write_entity_with(
+    original: &Path,
+    entity: &Entity,
+    out: &Path,
+    options: &ParseOptions,
+) -> Result<(), ParseError> {
+    let mut pssg = structure::load_with(original, options)?;
+    pssg.apply_entity(entity)?;
+
+    let mut document = PssgDocument::load_with(original, options)?;
+    document.update(&pssg);
+    document.save(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_with, Endianness};
+
+    /// A quad with hex positions, lowercase hex UVs and a block no object
+    /// uses, written in decimal.
+    const QUAD: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<PSSGFILE version="1.0.0.0">
+<PSSGDATABASE creator="test" scale="1 1 1" up="0 1 0">
+<TYPEINFO typeName="DATABLOCK" typeCount="3"/>
+<TYPEINFO typeName="RENDERDATASOURCE" typeCount="1"/>
+<LIBRARY type="RENDERINTERFACEBOUND">
+<DATABLOCK streamCount="1" size="48" elementCount="4" allocationStrategy="1" id="quad_vtx">
+<DATABLOCKSTREAM renderType="Vertex" dataType="float3" offset="0" stride="12"/>
+<DATABLOCKDATA>00 00 00 00 00 00 00 00 00 00 00 00 3F 80 00 00 00 00 00 00 00 00 00 00 3F 80 00 00 3F 80 00 00 00 00 00 00 00 00 00 00 3F 80 00 00 00 00 00 00</DATABLOCKDATA>
+</DATABLOCK>
+<DATABLOCK streamCount="1" size="16" elementCount="4" allocationStrategy="1" id="quad_st">
+<DATABLOCKSTREAM renderType="ST" dataType="half2" offset="0" stride="4"/>
+<DATABLOCKDATA>00 00 00 00 3c 00 00 00 3c 00 3c 00 00 00 3c 00</DATABLOCKDATA>
+</DATABLOCK>
+<DATABLOCK streamCount="1" size="12" elementCount="3" allocationStrategy="1" id="unused">
+<DATABLOCKSTREAM renderType="Vertex" dataType="float1" offset="0" stride="4"/>
+<DATABLOCKDATA>1.5 2 3</DATABLOCKDATA>
+</DATABLOCK>
+</LIBRARY>
+<LIBRARY type="SEGMENTSET">
+<SEGMENTSET id="seg" segmentCount="1">
+<RENDERDATASOURCE streamCount="2" id="quad_rds">
+<RENDERINDEXSOURCE primitive="triangles" format="ushort" count="6" id="quad_idx">
+<INDEXSOURCEDATA>00 00 00 01 00 02 00 00 00 02 00 03</INDEXSOURCEDATA>
+</RENDERINDEXSOURCE>
+<RENDERSTREAM dataBlock="#quad_vtx" subStream="0" id="s1"/>
+<RENDERSTREAM dataBlock="#quad_st" subStream="0" id="s2"/>
+</RENDERDATASOURCE>
+</SEGMENTSET>
+</LIBRARY>
+<LIBRARY type="NODE">
+<ROOTNODE stopTraversal="0" nickname="Root" id="root">
+<RENDERNODE stopTraversal="0" nickname="QuadNode" id="quad_node">
+<RENDERSTREAMINSTANCE sourceCount="1" indices="#quad_idx" streamCount="0" shader="#mat" id="rsi"/>
+</RENDERNODE>
+</ROOTNODE>
+</LIBRARY>
+</PSSGDATABASE>
+</PSSGFILE>
+"##;
+
+    fn temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("write-{}-{}.xml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn block_text(path: &Path, id: &str) -> String {
+        let document = PssgDocument::load(path).unwrap();
+        let mut text = None;
+        let mut root = document.root;
+        root.visit_mut(&mut |e| {
+            if e.name == "DATABLOCK" && e.attribute("id") == Some(id) {
+                text = e.child("DATABLOCKDATA").map(|d| d.text.clone());
+            }
+        });
+        text.unwrap()
+    }
+
+    #[test]
+    fn edits_survive_a_round_trip() {
+        let original = temp_file("quad", QUAD);
+        let out = temp_file("quad-out", "");
+        let mut entity = parse(&original).unwrap();
+        entity.objects[0].vertices[2] = [2.0, 3.0, 0.5];
+        entity.objects[0].faces[1] = [2, 3, 0];
+        write_entity(&original, &entity, &out).unwrap();
+
+        let back = parse(&out).unwrap();
+        assert_eq!(back.objects[0].vertices, entity.objects[0].vertices);
+        assert_eq!(back.objects[0].faces, entity.objects[0].faces);
+        assert_eq!(back.objects[0].uvs, entity.objects[0].uvs);
+
+        // Unchanged blocks keep their text, including hex case and
+        // decimal payloads.
+        for id in ["quad_st", "unused"] {
+            assert_eq!(block_text(&out, id), block_text(&original, id), "{}", id);
+        }
+        assert!(block_text(&out, "quad_vtx").starts_with("00 00 00 00"));
+    }
+
+    #[test]
+    fn little_endian_files_keep_their_byte_order() {
+        let mut element = Element::read(QUAD.as_bytes()).unwrap();
+        to_big_endian(&mut element);
+        let original = temp_file("quad-le", &element.to_xml());
+        let out = temp_file("quad-le-out", "");
+        let options = ParseOptions::new().endianness(Endianness::Little);
+
+        let mut entity = parse_with(&original, &options).unwrap();
+        let big_endian = parse(&temp_file("quad-be", QUAD)).unwrap();
+        assert_eq!(entity.objects[0].vertices, big_endian.objects[0].vertices);
+        entity.objects[0].vertices[1] = [1.0, 0.0, 0.25];
+        entity.objects[0].faces[0] = [1, 2, 0];
+        write_entity_with(&original, &entity, &out, &options).unwrap();
+
+        let back = parse_with(&out, &options).unwrap();
+        assert_eq!(back.objects[0].vertices, entity.objects[0].vertices);
+        assert_eq!(back.objects[0].faces, entity.objects[0].faces);
+        assert_eq!(back.objects[0].uvs, big_endian.objects[0].uvs);
+        assert_eq!(
+            block_text(&out, "quad_st"),
+            block_text(&original, "quad_st")
+        );
+        // The second vertex starts with 1.0 as a little-endian float.
+        assert!(block_text(&out, "quad_vtx").contains("00 00 80 3F 00 00 00 00 00 00 80 3E"));
+    }
+
+    #[test]
+    fn renamed_objects_are_errors() {
+        let original = temp_file("renamed", QUAD);
+        let out = temp_file("renamed-out", "");
+        let mut entity = parse(&original).unwrap();
+        entity.objects[0].name = "gone".to_string();
+        assert!(write_entity(&original, &entity, &out).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::io::Read;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::error::ParseError;
+
+/// Generic XML element, used where the typed structures would drop data,
+/// e.g. when writing a file back.
+#[derive(Clone, Default, Debug)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn read<R: Read>(reader: R) -> Result<Self, ParseError> {
+        let mut stack: Vec<Element> = Vec::new();
+
+        for event in EventReader::new(reader) {
+            match event.map_err(|e| ParseError::new(&format!("Failed to parse XML: {e}")))? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let mut element = Element::new(&name.local_name);
+                    element.attributes = attributes
+                        .into_iter()
+                        .map(|a| (a.name.local_name, a.value))
+                        .collect();
+                    stack.push(element);
+                }
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text);
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    let element = stack
+                        .pop()
+                        .ok_or_else(|| ParseError::new("Unbalanced XML"))?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Err(ParseError::new("Unexpected end of XML"))
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.attributes.iter_mut().find(|(k, _)| k == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.attributes.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Element> {
+        self.children.iter_mut().find(|c| c.name == name)
+    }
+
+    /// Calls `f` for this element and all of its descendants.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Element)) {
+        f(self);
+        for child in &mut self.children {
+            child.visit_mut(f);
+        }
+    }
+
+    /// Counts this element and all descendants named `name`.
+    pub fn count(&self, name: &str) -> usize {
+        let own = usize::from(self.name == name);
+        own + self.children.iter().map(|c| c.count(name)).sum::<usize>()
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        self.write(&mut xml);
+        xml
+    }
+
+    fn write(&self, xml: &mut String) {
+        xml.push('<');
+        xml.push_str(&self.name);
+        for (key, value) in &self.attributes {
+            xml.push_str(&format!(" {}=\"{}\"", key, escape(value)));
+        }
+
+        let text = self.text.trim();
+        if self.children.is_empty() && text.is_empty() {
+            xml.push_str("/>\n");
+            return;
+        }
+
+        xml.push('>');
+        xml.push_str(&escape(text));
+        if !self.children.is_empty() {
+            xml.push('\n');
+            for child in &self.children {
+                child.write(xml);
+            }
+        }
+        xml.push_str(&format!("</{}>\n", self.name));
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}